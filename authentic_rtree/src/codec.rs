@@ -0,0 +1,893 @@
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Read};
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use types::hash_value::{HashBackend, HashValue, Sha3_256};
+
+use crate::node::{FromBytes, MRTreeDefault, ObjectEntry};
+use crate::shape::Rect;
+use crate::verify::{SiblingObject, VerifyObject, VerifyObjectEntry};
+
+/// 编码格式的版本号，格式变化时递增
+pub const WIRE_VERSION: u8 = 3;
+
+const TAG_LEVEL_BEGIN: u8 = 0;
+const TAG_LEVEL_END: u8 = 1;
+const TAG_TARGET: u8 = 2;
+const TAG_SIBLING: u8 = 3;
+
+/// 解码失败的原因，offset为出错位置在输入中的字节偏移
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// 不支持的版本号
+    UnsupportedVersion(u8),
+    /// 编码的维度或坐标宽度与目标类型不一致
+    LayoutMismatch { dimension: usize, width: usize },
+    /// 从offset开始的读取超出了输入的末尾
+    UnexpectedEof { offset: usize },
+    /// 未知的条目标记
+    InvalidTag { offset: usize, tag: u8 },
+    /// 无法还原的坐标
+    InvalidCoordinate { offset: usize },
+    /// key不是合法的UTF-8
+    InvalidKey { offset: usize },
+    /// 哈希的长度或格式不正确
+    InvalidHash,
+    /// 解码完成后仍有多余的字节
+    TrailingBytes { offset: usize },
+    /// VO中第index个条目的层次结构不合法
+    InvalidNesting { index: usize },
+    /// 从offset开始的条目编码长度超过了允许的最大长度，参见[`StreamDecoder::with_max_entry_len`]
+    EntryTooLarge { offset: usize, len: usize },
+    /// JSON格式错误
+    Json(String),
+    /// 读取输入时发生的IO错误
+    Io(String),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported wire version {}", v),
+            DecodeError::LayoutMismatch { dimension, width } => {
+                write!(f, "layout mismatch: encoded dimension {}, coordinate width {}", dimension, width)
+            },
+            DecodeError::UnexpectedEof { offset } => write!(f, "unexpected end of input reading byte {}", offset),
+            DecodeError::InvalidTag { offset, tag } => write!(f, "invalid tag {} at byte {}", tag, offset),
+            DecodeError::InvalidCoordinate { offset } => write!(f, "invalid coordinate at byte {}", offset),
+            DecodeError::InvalidKey { offset } => write!(f, "key at byte {} is not valid utf-8", offset),
+            DecodeError::InvalidHash => write!(f, "invalid hash"),
+            DecodeError::TrailingBytes { offset } => write!(f, "trailing bytes after byte {}", offset),
+            DecodeError::InvalidNesting { index } => write!(f, "invalid nesting at entry {}", index),
+            DecodeError::EntryTooLarge { offset, len } => write!(f, "entry at byte {} needs {} bytes, more than allowed", offset, len),
+            DecodeError::Json(e) => write!(f, "invalid json: {}", e),
+            DecodeError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// 二进制编码。编码以头部开始：版本号（u8）、维度D（u16）、坐标宽度（u8），
+/// 之后是类型本身的内容。整数均为小端序，字符串和序列以u32长度作为前缀
+pub trait WireFormat: Sized {
+    fn write_to(&self, buf: &mut Vec<u8>);
+
+    fn read_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError>;
+
+    /// 头部中记录的维度和坐标宽度
+    fn layout() -> (usize, usize);
+
+    fn encode(&self) -> Vec<u8> {
+        let (dimension, width) = Self::layout();
+        let mut buf = vec![WIRE_VERSION];
+        buf.extend_from_slice(&(dimension as u16).to_le_bytes());
+        buf.push(width as u8);
+        self.write_to(&mut buf);
+        buf
+    }
+
+    /// 解码完整的输入，拒绝版本不符、布局不符以及带有多余字节的输入
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let version = reader.u8()?;
+        if version != WIRE_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let dimension = reader.u16()? as usize;
+        let width = reader.u8()? as usize;
+        if (dimension, width) != Self::layout() {
+            return Err(DecodeError::LayoutMismatch { dimension, width });
+        }
+        let value = Self::read_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(DecodeError::TrailingBytes { offset: reader.offset() });
+        }
+        Ok(value)
+    }
+}
+
+/// 带位置记录的字节读取器，所有读取在输入不足时返回错误
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, offset: 0 }
+    }
+
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.offset == self.buf.len()
+    }
+
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.offset
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.remaining() {
+            return Err(DecodeError::UnexpectedEof { offset: self.offset });
+        }
+        let bytes = &self.buf[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, DecodeError> {
+        let mut b = [0u8; 2];
+        b.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(b))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        let mut b = [0u8; 4];
+        b.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    pub fn hash(&mut self) -> Result<HashValue, DecodeError> {
+        HashValue::from_slice(self.take(HashValue::LENGTH)?).ok_or(DecodeError::InvalidHash)
+    }
+
+    pub fn coordinate<V: FromBytes>(&mut self) -> Result<V, DecodeError> {
+        let offset = self.offset;
+        V::read_bytes(self.take(V::BYTES)?).ok_or(DecodeError::InvalidCoordinate { offset })
+    }
+
+    pub fn point<V: MRTreeDefault, const D: usize>(&mut self) -> Result<[V; D], DecodeError> {
+        let mut point = [V::default(); D];
+        for v in point.iter_mut() {
+            *v = self.coordinate()?;
+        }
+        Ok(point)
+    }
+
+    pub fn rect<V: MRTreeDefault, const D: usize>(&mut self) -> Result<Rect<V, D>, DecodeError> {
+        let mut min = [V::default(); D];
+        let mut max = [V::default(); D];
+        for v in min.iter_mut() {
+            *v = self.coordinate()?;
+        }
+        for v in max.iter_mut() {
+            *v = self.coordinate()?;
+        }
+        Ok(Rect::new(min, max))
+    }
+
+    pub fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.u32()? as usize;
+        let offset = self.offset;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidKey { offset })
+    }
+}
+
+/// 从[`Read`]中逐个解码[`VerifyObject`]编码中的条目，不需要将完整的编码读入内存，
+/// 通常与[`crate::verify::StreamVerifier`]一起使用。
+/// 读取完头部中记录的条目数量后，输入中不能再有多余的字节。
+/// 单个条目的编码长度不能超过`max_entry_len`，长度前缀在分配内存之前检查，
+/// 因此内存占用与输入中声明的长度无关
+pub struct StreamDecoder<R, V, const D: usize, H = Sha3_256> {
+    reader: R,
+    /// 尚未读取的条目数量
+    remaining: usize,
+    offset: usize,
+    /// 单个条目编码的最大字节数
+    max_entry_len: usize,
+    /// 出错或结束后不再读取
+    done: bool,
+    _marker: PhantomData<(V, fn() -> H)>,
+}
+
+impl<R, V, const D: usize, H: HashBackend> StreamDecoder<R, V, D, H>
+    where
+        R: Read,
+        V: MRTreeDefault,
+{
+    /// 单个条目编码默认的最大字节数
+    pub const DEFAULT_MAX_ENTRY_LEN: usize = 1 << 20;
+    /// 每次从输入中读取的最大字节数
+    const CHUNK_LEN: usize = 1 << 12;
+
+    /// 读取并检查头部和条目数量，单个条目的长度不超过[`StreamDecoder::DEFAULT_MAX_ENTRY_LEN`]
+    pub fn new(reader: R) -> Result<Self, DecodeError> {
+        Self::with_max_entry_len(reader, Self::DEFAULT_MAX_ENTRY_LEN)
+    }
+
+    /// 单个条目的编码超过max_entry_len字节时返回[`DecodeError::EntryTooLarge`]
+    pub fn with_max_entry_len(reader: R, max_entry_len: usize) -> Result<Self, DecodeError> {
+        let mut decoder = Self {
+            reader,
+            remaining: 0,
+            offset: 0,
+            max_entry_len,
+            done: false,
+            _marker: PhantomData,
+        };
+        let mut header = [0u8; 8];
+        decoder.read_exact(&mut header)?;
+        let mut reader = Reader::new(&header);
+        let version = reader.u8()?;
+        if version != WIRE_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let dimension = reader.u16()? as usize;
+        let width = reader.u8()? as usize;
+        if (dimension, width) != VerifyObject::<V, D>::layout() {
+            return Err(DecodeError::LayoutMismatch { dimension, width });
+        }
+        decoder.remaining = reader.u32()? as usize;
+        Ok(decoder)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), DecodeError> {
+        self.reader.read_exact(buf).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => DecodeError::UnexpectedEof { offset: self.offset },
+            _ => DecodeError::Io(e.to_string()),
+        })?;
+        self.offset += buf.len();
+        Ok(())
+    }
+
+    /// 读取len个字节追加到buf的末尾，buf中是从entry_start开始的条目编码。
+    /// 条目的总长度超过上限时不做任何读取，否则分块读取，内存只随实际读到的字节增长
+    fn read_into(&mut self, buf: &mut Vec<u8>, len: Option<usize>, entry_start: usize) -> Result<(), DecodeError> {
+        let total = len.and_then(|len| len.checked_add(buf.len()));
+        let total = match total {
+            Some(total) if total <= self.max_entry_len => total,
+            _ => return Err(DecodeError::EntryTooLarge { offset: entry_start, len: total.unwrap_or(usize::MAX) }),
+        };
+        while buf.len() < total {
+            let start = buf.len();
+            buf.resize(total.min(start + Self::CHUNK_LEN), 0);
+            self.read_exact(&mut buf[start..])?;
+        }
+        Ok(())
+    }
+
+    /// 先按照条目的类型读取完整的字节，再交给[`Reader`]解码，保证错误中的偏移与整体编码一致
+    fn read_entry(&mut self) -> Result<VerifyObjectEntry<V, D, H>, DecodeError> {
+        let start = self.offset;
+        let rect_len = 2 * D * V::BYTES;
+        let mut buf = vec![];
+        self.read_into(&mut buf, Some(1), start)?;
+        match buf[0] {
+            TAG_TARGET => {
+                self.read_into(&mut buf, Some(4), start)?;
+                let key_len = u32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+                self.read_into(&mut buf, Some(key_len), start)?;
+                self.read_into(&mut buf, Some(rect_len + HashValue::LENGTH + 4), start)?;
+                let n = buf.len();
+                let vertices = u32::from_le_bytes([buf[n - 4], buf[n - 3], buf[n - 2], buf[n - 1]]) as usize;
+                self.read_into(&mut buf, vertices.checked_mul(D * V::BYTES), start)?;
+            },
+            TAG_SIBLING => {
+                self.read_into(&mut buf, Some(rect_len + HashValue::LENGTH + 8 + 1), start)?;
+                if buf[buf.len() - 1] == 1 {
+                    self.read_into(&mut buf, Some(4), start)?;
+                }
+            },
+            _ => {},
+        }
+        let mut reader = Reader::new(&buf);
+        VerifyObjectEntry::read_from(&mut reader).map_err(|e| match e {
+            DecodeError::InvalidTag { offset, tag } => DecodeError::InvalidTag { offset: start + offset, tag },
+            DecodeError::InvalidCoordinate { offset } => DecodeError::InvalidCoordinate { offset: start + offset },
+            DecodeError::InvalidKey { offset } => DecodeError::InvalidKey { offset: start + offset },
+            e => e,
+        })
+    }
+
+    /// 所有条目读取完毕后，输入必须恰好结束
+    fn check_end(&mut self) -> Result<(), DecodeError> {
+        let mut byte = [0u8; 1];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(()),
+                Ok(_) => return Err(DecodeError::TrailingBytes { offset: self.offset }),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(DecodeError::Io(e.to_string())),
+            }
+        }
+    }
+}
+
+impl<R, V, const D: usize, H: HashBackend> Iterator for StreamDecoder<R, V, D, H>
+    where
+        R: Read,
+        V: MRTreeDefault,
+{
+    type Item = Result<VerifyObjectEntry<V, D, H>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = if self.remaining == 0 {
+            self.done = true;
+            match self.check_end() {
+                Ok(()) => return None,
+                Err(e) => Err(e),
+            }
+        } else {
+            self.remaining -= 1;
+            self.read_entry()
+        };
+        if res.is_err() {
+            self.done = true;
+        }
+        Some(res)
+    }
+}
+
+fn write_rect<V: MRTreeDefault, const D: usize>(rect: &Rect<V, D>, buf: &mut Vec<u8>) {
+    // 与Rect::write_bytes的哈希编码一致：先min后max
+    rect._min.iter().chain(rect._max.iter()).for_each(|v| v.write_bytes(buf));
+}
+
+fn write_str(s: &str, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// 检查VO的层次结构：每个条目都在某一层中，层成对出现，且只有一个根节点
+fn check_nesting<V: MRTreeDefault, H: HashBackend, const D: usize>(entries: &[VerifyObjectEntry<V, D, H>]) -> Result<(), DecodeError> {
+    let mut depth = 0usize;
+    let mut closed = false;
+    for (index, ety) in entries.iter().enumerate() {
+        match ety {
+            VerifyObjectEntry::LevelBegin if depth == 0 && closed => return Err(DecodeError::InvalidNesting { index }),
+            VerifyObjectEntry::LevelBegin => depth += 1,
+            VerifyObjectEntry::LevlEnd if depth == 0 => return Err(DecodeError::InvalidNesting { index }),
+            VerifyObjectEntry::LevlEnd => {
+                depth -= 1;
+                closed = depth == 0;
+            },
+            _ if depth == 0 => return Err(DecodeError::InvalidNesting { index }),
+            _ => {},
+        }
+    }
+    if depth != 0 {
+        return Err(DecodeError::InvalidNesting { index: entries.len() });
+    }
+    Ok(())
+}
+
+impl<V, const D: usize, H: HashBackend> WireFormat for ObjectEntry<V, D, H>
+    where
+        V: MRTreeDefault,
+{
+    /// key、loc、value和折线顶点（u32数量，点和矩形对象为0），对象摘要在解码时重新计算
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        write_str(&self.key(), buf);
+        write_rect(self.loc(), buf);
+        buf.extend_from_slice(self.value().as_ref());
+        let vertices = self.polyline().unwrap_or_default();
+        buf.extend_from_slice(&(vertices.len() as u32).to_le_bytes());
+        vertices.iter().flatten().for_each(|v| v.write_bytes(buf));
+    }
+
+    fn read_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let key = reader.string()?;
+        let loc = reader.rect()?;
+        let value = reader.hash()?;
+        let count = reader.u32()?;
+        let mut vertices = vec![];
+        for _ in 0..count {
+            vertices.push(reader.point()?);
+        }
+        let polyline = if vertices.is_empty() { None } else { Some(vertices) };
+        Ok(ObjectEntry::from_parts(key, loc, polyline, value))
+    }
+
+    fn layout() -> (usize, usize) {
+        (D, V::BYTES)
+    }
+}
+
+impl<V, const D: usize> WireFormat for SiblingObject<V, D>
+    where
+        V: MRTreeDefault,
+{
+    /// range、hash、count（u64）和height，height为`None`时只写入标记0
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        write_rect(self.range(), buf);
+        buf.extend_from_slice(self.hash_ref());
+        buf.extend_from_slice(&self.count().to_le_bytes());
+        match self.height() {
+            None => buf.push(0),
+            Some(h) => {
+                buf.push(1);
+                buf.extend_from_slice(&h.to_le_bytes());
+            },
+        }
+    }
+
+    fn read_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let range = reader.rect()?;
+        let hash = reader.hash()?;
+        let count = reader.u64()?;
+        let offset = reader.offset();
+        let height = match reader.u8()? {
+            0 => None,
+            1 => Some(reader.u32()?),
+            tag => return Err(DecodeError::InvalidTag { offset, tag }),
+        };
+        Ok(SiblingObject::new(range, hash, height, count))
+    }
+
+    fn layout() -> (usize, usize) {
+        (D, V::BYTES)
+    }
+}
+
+impl<V, const D: usize, H: HashBackend> WireFormat for VerifyObjectEntry<V, D, H>
+    where
+        V: MRTreeDefault,
+{
+    /// 一个字节的标记，之后是Target或Sibling的内容
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            VerifyObjectEntry::LevelBegin => buf.push(TAG_LEVEL_BEGIN),
+            VerifyObjectEntry::LevlEnd => buf.push(TAG_LEVEL_END),
+            VerifyObjectEntry::Target(t) => {
+                buf.push(TAG_TARGET);
+                t.write_to(buf);
+            },
+            VerifyObjectEntry::Sibling(s) => {
+                buf.push(TAG_SIBLING);
+                s.write_to(buf);
+            },
+        }
+    }
+
+    fn read_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let offset = reader.offset();
+        match reader.u8()? {
+            TAG_LEVEL_BEGIN => Ok(VerifyObjectEntry::LevelBegin),
+            TAG_LEVEL_END => Ok(VerifyObjectEntry::LevlEnd),
+            TAG_TARGET => Ok(VerifyObjectEntry::Target(ObjectEntry::read_from(reader)?)),
+            TAG_SIBLING => Ok(VerifyObjectEntry::Sibling(SiblingObject::read_from(reader)?)),
+            tag => Err(DecodeError::InvalidTag { offset, tag }),
+        }
+    }
+
+    fn layout() -> (usize, usize) {
+        (D, V::BYTES)
+    }
+}
+
+impl<V, const D: usize, H: HashBackend> WireFormat for VerifyObject<V, D, H>
+    where
+        V: MRTreeDefault,
+{
+    /// u32条目数量，之后是各个条目
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.iter().len() as u32).to_le_bytes());
+        self.iter().for_each(|ety| ety.write_to(buf));
+    }
+
+    fn read_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let count = reader.u32()? as usize;
+        // 不信任输入中的数量，每个条目至少占一个字节
+        let mut entries = Vec::with_capacity(count.min(reader.remaining()));
+        for _ in 0..count {
+            entries.push(VerifyObjectEntry::read_from(reader)?);
+        }
+        check_nesting(&entries)?;
+        let mut vo = VerifyObject::new();
+        entries.into_iter().for_each(|ety| vo.push(ety));
+        Ok(vo)
+    }
+
+    fn layout() -> (usize, usize) {
+        (D, V::BYTES)
+    }
+}
+
+/// JSON中的矩形，坐标为长度D的数组
+#[derive(Serialize, Deserialize)]
+struct RectJson<V> {
+    min: Vec<V>,
+    max: Vec<V>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ObjectJson<V> {
+    key: String,
+    loc: RectJson<V>,
+    value: String,
+    /// 折线对象的顶点，点和矩形对象省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    polyline: Option<Vec<Vec<V>>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SiblingJson<V> {
+    range: RectJson<V>,
+    hash: String,
+    height: Option<u32>,
+    count: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum EntryJson<V> {
+    LevelBegin,
+    LevelEnd,
+    Target(ObjectJson<V>),
+    Sibling(SiblingJson<V>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct VerifyObjectJson<V> {
+    version: u8,
+    entries: Vec<EntryJson<V>>,
+}
+
+fn rect_to_json<V: MRTreeDefault, const D: usize>(rect: &Rect<V, D>) -> RectJson<V> {
+    RectJson { min: rect._min.to_vec(), max: rect._max.to_vec() }
+}
+
+fn point_from_json<V: MRTreeDefault, const D: usize>(point: Vec<V>) -> Result<[V; D], DecodeError> {
+    point.try_into()
+        .map_err(|v: Vec<V>| DecodeError::LayoutMismatch { dimension: v.len(), width: V::BYTES })
+}
+
+fn rect_from_json<V: MRTreeDefault, const D: usize>(rect: RectJson<V>) -> Result<Rect<V, D>, DecodeError> {
+    Ok(Rect::new(point_from_json(rect.min)?, point_from_json(rect.max)?))
+}
+
+fn hash_from_hex(s: &str) -> Result<HashValue, DecodeError> {
+    let bytes = hex::decode(s).map_err(|_| DecodeError::InvalidHash)?;
+    HashValue::from_slice(&bytes).ok_or(DecodeError::InvalidHash)
+}
+
+fn object_to_json<V: MRTreeDefault, H: HashBackend, const D: usize>(obj: &ObjectEntry<V, D, H>) -> ObjectJson<V> {
+    ObjectJson {
+        key: obj.key(),
+        loc: rect_to_json(obj.loc()),
+        value: obj.value().to_hex(),
+        polyline: obj.polyline().map(|vs| vs.iter().map(|v| v.to_vec()).collect()),
+    }
+}
+
+fn object_from_json<V: MRTreeDefault, H: HashBackend, const D: usize>(obj: ObjectJson<V>) -> Result<ObjectEntry<V, D, H>, DecodeError> {
+    let polyline = obj.polyline
+        .map(|vs| vs.into_iter().map(point_from_json).collect::<Result<Vec<_>, _>>())
+        .transpose()?;
+    Ok(ObjectEntry::from_parts(obj.key, rect_from_json(obj.loc)?, polyline, hash_from_hex(&obj.value)?))
+}
+
+fn sibling_to_json<V: MRTreeDefault, const D: usize>(sibling: &SiblingObject<V, D>) -> SiblingJson<V> {
+    SiblingJson {
+        range: rect_to_json(sibling.range()),
+        hash: sibling.hash().to_hex(),
+        height: sibling.height(),
+        count: sibling.count(),
+    }
+}
+
+fn sibling_from_json<V: MRTreeDefault, const D: usize>(sibling: SiblingJson<V>) -> Result<SiblingObject<V, D>, DecodeError> {
+    Ok(SiblingObject::new(rect_from_json(sibling.range)?, hash_from_hex(&sibling.hash)?, sibling.height, sibling.count))
+}
+
+fn entry_to_json<V: MRTreeDefault, H: HashBackend, const D: usize>(ety: &VerifyObjectEntry<V, D, H>) -> EntryJson<V> {
+    match ety {
+        VerifyObjectEntry::LevelBegin => EntryJson::LevelBegin,
+        VerifyObjectEntry::LevlEnd => EntryJson::LevelEnd,
+        VerifyObjectEntry::Target(t) => EntryJson::Target(object_to_json(t)),
+        VerifyObjectEntry::Sibling(s) => EntryJson::Sibling(sibling_to_json(s)),
+    }
+}
+
+fn entry_from_json<V: MRTreeDefault, H: HashBackend, const D: usize>(ety: EntryJson<V>) -> Result<VerifyObjectEntry<V, D, H>, DecodeError> {
+    Ok(match ety {
+        EntryJson::LevelBegin => VerifyObjectEntry::LevelBegin,
+        EntryJson::LevelEnd => VerifyObjectEntry::LevlEnd,
+        EntryJson::Target(t) => VerifyObjectEntry::Target(object_from_json(t)?),
+        EntryJson::Sibling(s) => VerifyObjectEntry::Sibling(sibling_from_json(s)?),
+    })
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    // 中间结构只包含字符串、数组和坐标，序列化不会失败
+    serde_json::to_string_pretty(value).expect("json serialization")
+}
+
+fn from_json<T: DeserializeOwned>(s: &str) -> Result<T, DecodeError> {
+    serde_json::from_str(s).map_err(|e| DecodeError::Json(e.to_string()))
+}
+
+/// 与二进制编码对应的JSON格式，便于调试。哈希编码为十六进制字符串
+pub trait JsonFormat: Sized {
+    fn to_json(&self) -> String;
+
+    fn from_json(s: &str) -> Result<Self, DecodeError>;
+}
+
+impl<V, const D: usize, H: HashBackend> JsonFormat for ObjectEntry<V, D, H>
+    where
+        V: MRTreeDefault + Serialize + DeserializeOwned,
+{
+    fn to_json(&self) -> String {
+        to_json(&object_to_json(self))
+    }
+
+    fn from_json(s: &str) -> Result<Self, DecodeError> {
+        object_from_json(from_json(s)?)
+    }
+}
+
+impl<V, const D: usize> JsonFormat for SiblingObject<V, D>
+    where
+        V: MRTreeDefault + Serialize + DeserializeOwned,
+{
+    fn to_json(&self) -> String {
+        to_json(&sibling_to_json(self))
+    }
+
+    fn from_json(s: &str) -> Result<Self, DecodeError> {
+        sibling_from_json(from_json(s)?)
+    }
+}
+
+impl<V, const D: usize, H: HashBackend> JsonFormat for VerifyObjectEntry<V, D, H>
+    where
+        V: MRTreeDefault + Serialize + DeserializeOwned,
+{
+    fn to_json(&self) -> String {
+        to_json(&entry_to_json(self))
+    }
+
+    fn from_json(s: &str) -> Result<Self, DecodeError> {
+        entry_from_json(from_json(s)?)
+    }
+}
+
+impl<V, const D: usize, H: HashBackend> JsonFormat for VerifyObject<V, D, H>
+    where
+        V: MRTreeDefault + Serialize + DeserializeOwned,
+{
+    fn to_json(&self) -> String {
+        to_json(&VerifyObjectJson { version: WIRE_VERSION, entries: self.iter().map(entry_to_json).collect() })
+    }
+
+    fn from_json(s: &str) -> Result<Self, DecodeError> {
+        let json: VerifyObjectJson<V> = from_json(s)?;
+        if json.version != WIRE_VERSION {
+            return Err(DecodeError::UnsupportedVersion(json.version));
+        }
+        let entries = json.entries.into_iter().map(entry_from_json).collect::<Result<Vec<_>, _>>()?;
+        check_nesting(&entries)?;
+        let mut vo = VerifyObject::new();
+        entries.into_iter().for_each(|ety| vo.push(ety));
+        Ok(vo)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use types::test_utils::{generate_points, num_hash};
+    use crate::codec::{DecodeError, JsonFormat, StreamDecoder, WireFormat, WIRE_VERSION};
+    use crate::mrtree::MerkleRTree;
+    use crate::node::ObjectEntry;
+    use crate::shape::Rect;
+    use crate::verify::{StreamVerifier, VerifyObject, VerifyObjectEntry};
+
+    fn build_vo() -> (VerifyObject<f64, 2>, Rect<f64, 2>, types::hash_value::HashValue) {
+        let mut tree = MerkleRTree::<f64, 2, 4>::new();
+        for (i, p) in generate_points([0.0f64, 0.0], [100.0, 100.0], 100).into_iter().enumerate() {
+            tree.insert(format!("key-{}", i), p, num_hash(i as i32)).unwrap();
+        }
+        let query = Rect::new([20.0f64, 30.0], [45.0, 60.0]);
+        (tree.range_query(&query).unwrap(), query, tree.root_hash().unwrap())
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let (vo, query, root) = build_vo();
+        let bytes = vo.encode();
+        assert_eq!(bytes[0], WIRE_VERSION);
+        let decoded = VerifyObject::<f64, 2>::decode(&bytes).unwrap();
+        assert_eq!(decoded.encode(), bytes);
+        assert!(decoded.verify(&query, root).is_ok());
+
+        // 单独的条目也可以编码
+        let obj: ObjectEntry<usize, 2> = ObjectEntry::new("key".to_string(), [1usize, 2], num_hash(7));
+        let decoded = ObjectEntry::<usize, 2>::decode(&obj.encode()).unwrap();
+        assert!(decoded.match_key("key"));
+        assert_eq!(decoded.hash(), obj.hash());
+    }
+
+    #[test]
+    fn test_binary_reject() {
+        let (vo, _, _) = build_vo();
+        let bytes = vo.encode();
+        // 多余的字节
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(VerifyObject::<f64, 2>::decode(&trailing).err(), Some(DecodeError::TrailingBytes { offset: bytes.len() }));
+        // 任意截断都返回错误
+        for len in (0..bytes.len()).step_by(7) {
+            assert!(VerifyObject::<f64, 2>::decode(&bytes[..len]).is_err());
+        }
+        // 版本号和布局
+        let mut version = bytes.clone();
+        version[0] = WIRE_VERSION + 1;
+        assert_eq!(VerifyObject::<f64, 2>::decode(&version).err(), Some(DecodeError::UnsupportedVersion(WIRE_VERSION + 1)));
+        assert!(matches!(VerifyObject::<f32, 2>::decode(&bytes).err(), Some(DecodeError::LayoutMismatch { .. })));
+        assert!(matches!(VerifyObject::<f64, 3>::decode(&bytes).err(), Some(DecodeError::LayoutMismatch { .. })));
+        // 不合法的层次结构
+        let mut unbalanced = VerifyObject::<f64, 2>::new();
+        vo.iter().skip(1).cloned().for_each(|e| unbalanced.push(e));
+        assert!(matches!(VerifyObject::<f64, 2>::decode(&unbalanced.encode()).err(), Some(DecodeError::InvalidNesting { .. })));
+        let mut twice = vo.clone();
+        twice.extend(vo.clone());
+        assert_eq!(
+            VerifyObject::<f64, 2>::decode(&twice.encode()).err(),
+            Some(DecodeError::InvalidNesting { index: vo.iter().count() })
+        );
+        // 未知的标记
+        let mut tag = bytes.clone();
+        tag[8] = 9;
+        assert_eq!(VerifyObject::<f64, 2>::decode(&tag).err(), Some(DecodeError::InvalidTag { offset: 8, tag: 9 }));
+    }
+
+    #[test]
+    fn test_polyline_roundtrip() {
+        let mut tree = MerkleRTree::<f64, 2, 4>::new();
+        for i in 0..40 {
+            let x = (i % 8) as f64 * 10.0;
+            let y = (i / 8) as f64 * 10.0;
+            let line = vec![[x, y], [x + 3.0, y + 1.0], [x + 4.0, y + 5.0]];
+            tree.insert_object(ObjectEntry::with_polyline(format!("line-{}", i), line, num_hash(i)).unwrap()).unwrap();
+        }
+        let root = tree.root_hash().unwrap();
+        let query = Rect::new([0.0f64, 0.0], [45.0, 25.0]);
+        let vo = tree.range_query(&query).unwrap();
+        assert!(vo.iter().any(|e| matches!(e, VerifyObjectEntry::Target(t) if t.polyline().is_some())));
+        let bytes = vo.encode();
+        let decoded = VerifyObject::<f64, 2>::decode(&bytes).unwrap();
+        assert!(decoded.verify(&query, root).is_ok());
+        let decoded = VerifyObject::<f64, 2>::from_json(&vo.to_json()).unwrap();
+        assert_eq!(decoded.encode(), bytes);
+        let decoder = StreamDecoder::<_, f64, 2>::new(bytes.as_slice()).unwrap();
+        assert!(StreamVerifier::new(query.clone(), root).verify_all(decoder.map(|e| e.unwrap()), |_| {}).is_ok());
+
+        // 篡改折线的顶点后对象摘要不同
+        let target = vo.iter().find_map(|e| match e {
+            VerifyObjectEntry::Target(t) if t.match_key("line-0") => Some(t.clone()),
+            _ => None,
+        }).unwrap();
+        let json = target.to_json().replacen("1.0", "2.0", 1);
+        assert_ne!(json, target.to_json());
+        let tampered = ObjectEntry::<f64, 2>::from_json(&json).unwrap();
+        assert_ne!(tampered.hash(), target.hash());
+    }
+
+    #[test]
+    fn test_stream_decode() {
+        let (vo, query, root) = build_vo();
+        let bytes = vo.encode();
+        let decoder = StreamDecoder::<_, f64, 2>::new(bytes.as_slice()).unwrap();
+        let entries = decoder.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries.len(), vo.iter().count());
+        // 边读取边验证
+        let mut results = 0;
+        let verifier = StreamVerifier::new(query.clone(), root);
+        let decoder = StreamDecoder::<_, f64, 2>::new(bytes.as_slice()).unwrap();
+        assert!(verifier.verify_all(decoder.map(|e| e.unwrap()), |_| results += 1).is_ok());
+        assert_eq!(results, vo.iter().filter(|e| matches!(e, VerifyObjectEntry::Target(_))).count());
+
+        // 多余的字节在最后报告
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        let last = StreamDecoder::<_, f64, 2>::new(trailing.as_slice()).unwrap().last().unwrap();
+        assert_eq!(last.err(), Some(DecodeError::TrailingBytes { offset: bytes.len() }));
+        // 截断的输入在出错后结束
+        for len in [3usize, 8, 20, bytes.len() - 1] {
+            let res = StreamDecoder::<_, f64, 2>::new(&bytes[..len])
+                .and_then(|d| d.collect::<Result<Vec<_>, _>>());
+            match res {
+                Err(DecodeError::UnexpectedEof { offset }) => assert!(offset <= len),
+                _ => panic!("truncated input at {} is accepted", len),
+            }
+        }
+        let mut tag = bytes.clone();
+        tag[8] = 9;
+        let res = StreamDecoder::<_, f64, 2>::new(tag.as_slice()).unwrap().next().unwrap();
+        assert_eq!(res.err(), Some(DecodeError::InvalidTag { offset: 8, tag: 9 }));
+    }
+
+    #[test]
+    fn test_stream_decode_oversized() {
+        let (vo, ..) = build_vo();
+        let bytes = vo.encode();
+        // 头部之后是一个声明了u32::MAX字节key的对象，在分配内存之前被拒绝
+        let mut hostile = bytes[..8].to_vec();
+        hostile.push(2);
+        hostile.extend_from_slice(&u32::MAX.to_le_bytes());
+        let res = StreamDecoder::<_, f64, 2>::new(hostile.as_slice()).unwrap().next().unwrap();
+        assert_eq!(res.err(), Some(DecodeError::EntryTooLarge { offset: 8, len: 5 + u32::MAX as usize }));
+        // 声明了u32::MAX个顶点的折线
+        let mut hostile = bytes[..8].to_vec();
+        hostile.push(2);
+        hostile.extend_from_slice(&1u32.to_le_bytes());
+        hostile.push(b'k');
+        hostile.extend_from_slice(&[0u8; 4 * 8 + 32]);
+        hostile.extend_from_slice(&u32::MAX.to_le_bytes());
+        let res = StreamDecoder::<_, f64, 2>::new(hostile.as_slice()).unwrap().next().unwrap();
+        assert!(matches!(res, Err(DecodeError::EntryTooLarge { offset: 8, .. })));
+
+        // 上限之内的长度前缀在输入不足时报告位置，而不是一次分配全部内存
+        let mut truncated = bytes[..8].to_vec();
+        truncated.push(2);
+        truncated.extend_from_slice(&1000u32.to_le_bytes());
+        let res = StreamDecoder::<_, f64, 2>::new(truncated.as_slice()).unwrap().next().unwrap();
+        assert_eq!(res.err(), Some(DecodeError::UnexpectedEof { offset: 13 }));
+        // 较小的上限拒绝正常的条目
+        let res = StreamDecoder::<_, f64, 2>::with_max_entry_len(bytes.as_slice(), 8).unwrap()
+            .collect::<Result<Vec<_>, _>>();
+        assert!(matches!(res, Err(DecodeError::EntryTooLarge { .. })));
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let (vo, query, root) = build_vo();
+        let json = vo.to_json();
+        let decoded = VerifyObject::<f64, 2>::from_json(&json).unwrap();
+        assert_eq!(decoded.encode(), vo.encode());
+        assert!(decoded.verify(&query, root).is_ok());
+
+        let ety = vo.iter().find(|e| matches!(e, VerifyObjectEntry::Sibling(_))).unwrap();
+        let decoded = VerifyObjectEntry::<f64, 2>::from_json(&ety.to_json()).unwrap();
+        assert_eq!(decoded.hash(), ety.hash());
+
+        assert!(matches!(VerifyObject::<f64, 2>::from_json("{"), Err(DecodeError::Json(_))));
+        let nested = format!(r#"{{"version": {}, "entries": ["level_end"]}}"#, WIRE_VERSION);
+        assert_eq!(VerifyObject::<f64, 2>::from_json(&nested).err(), Some(DecodeError::InvalidNesting { index: 0 }));
+        let dim = r#"{"key": "k", "loc": {"min": [1.0], "max": [1.0]}, "value": "00"}"#;
+        assert!(matches!(ObjectEntry::<f64, 2>::from_json(dim).err(), Some(DecodeError::LayoutMismatch { .. })));
+    }
+}
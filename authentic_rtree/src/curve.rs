@@ -0,0 +1,460 @@
+//! 空间填充曲线：将对象映射为曲线上的一维序号，序号相近的对象在空间上也相近。
+//!
+//! 批量插入、分区内压缩重建以及[`crate::split::CurveSplit`]都按曲线序号对条目排序，
+//! 曲线通过[`Curve`]选择，默认为希尔伯特曲线。
+
+use std::marker::PhantomData;
+use std::str::FromStr;
+use crate::node::{ESMTEntry, FromPrimitive, MRTreeDefault, MRTreeFunc, ToPrimitive};
+use types::hash_value::HashBackend;
+use crate::shape::Rect;
+
+/// 将矩形（以其中心表示）映射为曲线上的序号
+pub trait SpaceFillingCurve<V, const D: usize>
+    where
+        V: MRTreeDefault,
+{
+    fn curve_idx(&self, obj: &Rect<V, D>) -> u128;
+}
+
+/// 可选的空间填充曲线
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Curve {
+    /// 希尔伯特曲线，局部性最好
+    #[default]
+    Hilbert,
+    /// Z序（Morton）曲线，只需按位交错，计算开销最小
+    ZOrder,
+    /// 皮亚诺曲线，每一维按三进制划分
+    Peano,
+}
+
+impl Curve {
+    /// 构造以`area`为取值范围的排序器
+    pub fn sorter<'a, V, const D: usize>(self, area: &Rect<V, D>) -> Box<dyn SpaceFillingCurve<V, D> + 'a>
+        where
+            V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive + 'a,
+    {
+        match self {
+            Curve::Hilbert => Box::new(HilbertSorter::<V, D, 0>::new(area)),
+            Curve::ZOrder => Box::new(ZOrderSorter::new(area)),
+            Curve::Peano => Box::new(PeanoSorter::new(area)),
+        }
+    }
+}
+
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hilbert" => Ok(Curve::Hilbert),
+            "zorder" | "morton" => Ok(Curve::ZOrder),
+            "peano" => Ok(Curve::Peano),
+            _ => Err(format!("unknown space-filling curve: {}", s)),
+        }
+    }
+}
+
+/// 按曲线序号对条目排序，序号相同的条目保持原有顺序
+pub(crate) fn sort_by_curve<V, H: HashBackend, const D: usize, const C: usize>(
+    curve: &dyn SpaceFillingCurve<V, D>,
+    v: Vec<ESMTEntry<V, D, C, H>>,
+) -> Vec<ESMTEntry<V, D, C, H>>
+    where
+        V: MRTreeDefault,
+{
+    let mut indexed = v.into_iter()
+        .map(|e| (curve.curve_idx(e.mbr()), e))
+        .collect::<Vec<_>>();
+    indexed.sort_by_key(|(idx, _)| *idx);
+    indexed.into_iter()
+        .map(|(_, e)| e)
+        .collect()
+}
+
+/// 将区域每一维划分为`max_cell + 1`个格子，矩形中心映射到所在格子的坐标
+struct Grid<V, const D: usize> {
+    max_cell: u64,
+    lowbound: [f64; D],
+    range: [f64; D],
+    _marker: PhantomData<V>,
+}
+
+impl<V, const D: usize> Grid<V, D>
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    fn new(area: &Rect<V, D>, max_cell: u64) -> Self {
+        let mut lowbound = [0.0; D];
+        let mut range = [0.0; D];
+        for i in 0..D {
+            lowbound[i] = area._min[i].to_f64();
+            range[i] = area._max[i].to_f64() - lowbound[i];
+        }
+        Self {
+            max_cell,
+            lowbound,
+            range,
+            _marker: PhantomData,
+        }
+    }
+
+    fn cell(&self, obj: &Rect<V, D>) -> [u64; D] {
+        let obj_c = Self::center(obj);
+        let mut cell = [0u64; D];
+        for i in 0..D {
+            // 某一维范围为0时所有对象都落在第一个格子中
+            if self.range[i] > 0.0 {
+                let ratio = (obj_c[i].to_f64() - self.lowbound[i]) / self.range[i];
+                if ratio > 0.0 {
+                    cell[i] = ((ratio * (self.max_cell as f64 + 1.0)) as u64).min(self.max_cell);
+                }
+            }
+        }
+        cell
+    }
+
+    fn center(rect: &Rect<V, D>) -> [V; D] {
+        let mut c = [V::default(); D];
+        for (i, v) in c.iter_mut().enumerate() {
+            *v = (rect._max[i] + rect._min[i]) / (V::from_i32(2));
+        }
+        c
+    }
+}
+
+/// 默认的二进制曲线阶数：每一维划分为2^order个格子，总位数不超过128
+pub const fn default_hilbert_order(dims: usize) -> u32 {
+    if dims == 0 {
+        return 1;
+    }
+    let order = 128 / dims;
+    if order > 32 { 32 } else if order == 0 { 1 } else { order as u32 }
+}
+
+/// 默认的皮亚诺曲线阶数：每一维划分为3^order个格子，序号不超过128位
+pub const fn default_peano_order(dims: usize) -> u32 {
+    if dims == 0 {
+        return 1;
+    }
+    let order = 80 / dims;
+    if order > 20 { 20 } else if order == 0 { 1 } else { order as u32 }
+}
+
+fn check_binary_order(name: &str, dims: usize, order: u32) {
+    assert!((1..=64).contains(&order) && dims * order as usize <= 128,
+            "{} order {} is not supported for {}-D data", name, order, dims);
+}
+
+pub struct HilbertSorter<V, const D: usize, const C: usize>
+    where
+        V: MRTreeDefault,
+{
+    order: u32,
+    grid: Grid<V, D>,
+}
+
+impl<V, const D: usize, const C: usize> HilbertSorter<V, D, C>
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    pub fn new(area: &Rect<V, D>) -> Self {
+        Self::with_order(area, default_hilbert_order(D))
+    }
+
+    /// 指定阶数，要求`D * order <= 128`且`order <= 64`
+    pub fn with_order(area: &Rect<V, D>, order: u32) -> Self {
+        check_binary_order("hilbert", D, order);
+        Self {
+            order,
+            grid: Grid::new(area, u64::MAX >> (64 - order)),
+        }
+    }
+
+    #[inline]
+    pub fn order(&self) -> u32 {
+        self.order
+    }
+
+    /// 计算矩形中心所在格子的希尔伯特序号，占用低`D * order`位
+    pub fn hilbert_idx(&self, obj: &Rect<V, D>) -> u128 {
+        hilbert_encode(self.grid.cell(obj), self.order)
+    }
+}
+
+impl<V, const D: usize, const C: usize> SpaceFillingCurve<V, D> for HilbertSorter<V, D, C>
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    fn curve_idx(&self, obj: &Rect<V, D>) -> u128 {
+        self.hilbert_idx(obj)
+    }
+}
+
+pub struct ZOrderSorter<V, const D: usize>
+    where
+        V: MRTreeDefault,
+{
+    order: u32,
+    grid: Grid<V, D>,
+}
+
+impl<V, const D: usize> ZOrderSorter<V, D>
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    pub fn new(area: &Rect<V, D>) -> Self {
+        Self::with_order(area, default_hilbert_order(D))
+    }
+
+    /// 指定阶数，要求`D * order <= 128`且`order <= 64`
+    pub fn with_order(area: &Rect<V, D>, order: u32) -> Self {
+        check_binary_order("z-order", D, order);
+        Self {
+            order,
+            grid: Grid::new(area, u64::MAX >> (64 - order)),
+        }
+    }
+
+    #[inline]
+    pub fn order(&self) -> u32 {
+        self.order
+    }
+}
+
+impl<V, const D: usize> SpaceFillingCurve<V, D> for ZOrderSorter<V, D>
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    fn curve_idx(&self, obj: &Rect<V, D>) -> u128 {
+        zorder_encode(self.grid.cell(obj), self.order)
+    }
+}
+
+pub struct PeanoSorter<V, const D: usize>
+    where
+        V: MRTreeDefault,
+{
+    order: u32,
+    grid: Grid<V, D>,
+}
+
+impl<V, const D: usize> PeanoSorter<V, D>
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    pub fn new(area: &Rect<V, D>) -> Self {
+        Self::with_order(area, default_peano_order(D))
+    }
+
+    /// 指定阶数，要求`D * order <= 80`且`order <= 40`，保证序号不超过128位
+    pub fn with_order(area: &Rect<V, D>, order: u32) -> Self {
+        assert!((1..=40).contains(&order) && D * order as usize <= 80,
+                "peano order {} is not supported for {}-D data", order, D);
+        Self {
+            order,
+            grid: Grid::new(area, 3u64.pow(order) - 1),
+        }
+    }
+
+    #[inline]
+    pub fn order(&self) -> u32 {
+        self.order
+    }
+}
+
+impl<V, const D: usize> SpaceFillingCurve<V, D> for PeanoSorter<V, D>
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    fn curve_idx(&self, obj: &Rect<V, D>) -> u128 {
+        peano_encode(self.grid.cell(obj), self.order)
+    }
+}
+
+/// 将D维格子坐标（每维`order`位）编码为希尔伯特序号，使用Skilling的转置算法
+pub fn hilbert_encode<const D: usize>(mut x: [u64; D], order: u32) -> u128 {
+    if D == 0 {
+        return 0;
+    }
+    let m = 1u64 << (order - 1);
+    // 逆向还原
+    let mut q = m;
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..D {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+    // 格雷编码
+    for i in 1..D {
+        x[i] ^= x[i - 1];
+    }
+    let mut t = 0;
+    q = m;
+    while q > 1 {
+        if x[D - 1] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    for v in x.iter_mut() {
+        *v ^= t;
+    }
+    zorder_encode(x, order)
+}
+
+/// 将D维格子坐标（每维`order`位）按位交错编码为Z序序号
+pub fn zorder_encode<const D: usize>(x: [u64; D], order: u32) -> u128 {
+    let mut idx = 0u128;
+    for b in (0..order).rev() {
+        for v in x.iter() {
+            idx = (idx << 1) | ((v >> b) & 1) as u128;
+        }
+    }
+    idx
+}
+
+/// 将D维格子坐标（每维`order`个三进制位）编码为皮亚诺序号。
+/// 自高位到低位依次输出各维的三进制数字，其它维已输出的数字之和为奇数时该数字取反（`2 - d`）
+pub fn peano_encode<const D: usize>(x: [u64; D], order: u32) -> u128 {
+    let mut parity = [0u64; D];
+    let mut total = 0u64;
+    let mut idx = 0u128;
+    for level in (0..order).rev() {
+        let p = 3u64.pow(level);
+        for i in 0..D {
+            let mut digit = (x[i] / p) % 3;
+            if (total ^ parity[i]) & 1 == 1 {
+                digit = 2 - digit;
+            }
+            parity[i] ^= digit & 1;
+            total ^= digit & 1;
+            idx = idx * 3 + digit as u128;
+        }
+    }
+    idx
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Curve, HilbertSorter, default_peano_order, PeanoSorter, SpaceFillingCurve, ZOrderSorter, hilbert_encode, peano_encode, zorder_encode};
+    use crate::shape::Rect;
+
+    #[test]
+    fn test_hilbert_idx() {
+        let hilbert_sorter: HilbertSorter<f64, 2, 4> = HilbertSorter::new(&Rect::new([0.0f64, 0.0f64], [64.0f64, 64.0f64]));
+        let points = vec![
+            [0.0f64, 1.7],
+            [35.189, 64.0],
+            [64.0, 29.3742],
+            [26.6746, 0.0],
+            [63.12, 12.10342],
+        ];
+        for p in points {
+            let idx = hilbert_sorter.hilbert_idx(&Rect::new_point(p));
+            println!("point: {:?} = {}", p, idx);
+        }
+    }
+
+    /// 遍历所有格子，检查编码是双射；`adjacent`为真时还要求按序号排序后相邻格子只在一个维度上相差1
+    fn check_curve<const D: usize>(side: u64, encode: impl Fn([u64; D]) -> u128, adjacent: bool) {
+        let total = side.pow(D as u32);
+        let mut cells = Vec::new();
+        for n in 0..total {
+            let mut c = [0u64; D];
+            let mut rest = n;
+            for v in c.iter_mut() {
+                *v = rest % side;
+                rest /= side;
+            }
+            cells.push((encode(c), c));
+        }
+        cells.sort_by_key(|(idx, _)| *idx);
+        for (i, (idx, _)) in cells.iter().enumerate() {
+            assert_eq!(*idx, i as u128);
+        }
+        if adjacent {
+            for w in cells.windows(2) {
+                let dist: u64 = (0..D).map(|i| w[0].1[i].abs_diff(w[1].1[i])).sum();
+                assert_eq!(dist, 1, "{:?} -> {:?}", w[0], w[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hilbert_curve() {
+        check_curve::<2>(2, |c| hilbert_encode(c, 1), true);
+        check_curve::<2>(16, |c| hilbert_encode(c, 4), true);
+        check_curve::<3>(8, |c| hilbert_encode(c, 3), true);
+        check_curve::<4>(4, |c| hilbert_encode(c, 2), true);
+    }
+
+    #[test]
+    fn test_zorder_curve() {
+        check_curve::<2>(16, |c| zorder_encode(c, 4), false);
+        check_curve::<3>(8, |c| zorder_encode(c, 3), false);
+        // 按位交错，y在低位
+        assert_eq!(zorder_encode([0b10, 0b01], 2), 0b1001);
+        assert_eq!(zorder_encode([1, 1], 1), 3);
+    }
+
+    #[test]
+    fn test_peano_curve() {
+        check_curve::<2>(3, |c| peano_encode(c, 1), true);
+        check_curve::<2>(27, |c| peano_encode(c, 3), true);
+        check_curve::<3>(9, |c| peano_encode(c, 2), true);
+        check_curve::<4>(9, |c| peano_encode(c, 2), true);
+    }
+
+    #[test]
+    fn test_hilbert_degenerate() {
+        // 某一维范围为0时不能出现除零
+        let sorter: HilbertSorter<usize, 3, 4> = HilbertSorter::new(&Rect::new([3, 0, 7], [3, 100, 7]));
+        assert_eq!(sorter.hilbert_idx(&Rect::new_point([3, 0, 7])), 0);
+        let a = sorter.hilbert_idx(&Rect::new_point([3, 40, 7]));
+        let b = sorter.hilbert_idx(&Rect::new_point([3, 60, 7]));
+        assert!(a < b);
+        // 高阶数能区分非常接近的点
+        let sorter: HilbertSorter<f64, 2, 4> = HilbertSorter::new(&Rect::new([0.0, 0.0], [1.0, 1.0]));
+        assert_eq!(sorter.order(), 32);
+        let a = sorter.hilbert_idx(&Rect::new_point([0.5, 0.5]));
+        let b = sorter.hilbert_idx(&Rect::new_point([0.5 + 1e-8, 0.5]));
+        assert_ne!(a, b);
+        let sorter: HilbertSorter<f64, 2, 4> = HilbertSorter::with_order(&Rect::new([0.0, 0.0], [1.0, 1.0]), 6);
+        assert_eq!(sorter.hilbert_idx(&Rect::new_point([0.5, 0.5])), sorter.hilbert_idx(&Rect::new_point([0.5 + 1e-8, 0.5])));
+        assert!(sorter.hilbert_idx(&Rect::new_point([1.0, 1.0])) < 1 << 12);
+    }
+
+    #[test]
+    fn test_curve_sorters() {
+        let area = Rect::new([0.0f64, 0.0], [90.0, 90.0]);
+        let zorder: ZOrderSorter<f64, 2> = ZOrderSorter::with_order(&area, 2);
+        // 4x4的格子，每格边长22.5
+        assert_eq!(zorder.curve_idx(&Rect::new_point([10.0, 10.0])), 0);
+        assert_eq!(zorder.curve_idx(&Rect::new_point([30.0, 10.0])), 0b10);
+        assert_eq!(zorder.curve_idx(&Rect::new_point([90.0, 90.0])), 15);
+        let peano: PeanoSorter<f64, 2> = PeanoSorter::with_order(&area, 2);
+        assert_eq!(peano.order(), 2);
+        assert_eq!(peano.curve_idx(&Rect::new_point([0.0, 0.0])), 0);
+        assert_eq!(peano.curve_idx(&Rect::new_point([90.0, 90.0])), 80);
+        assert_eq!(PeanoSorter::<f64, 2>::new(&area).order(), 20);
+        assert_eq!(default_peano_order(3), 20);
+        assert_eq!(default_peano_order(5), 16);
+        // 默认曲线与HilbertSorter一致
+        let rect = Rect::new([12.0, 40.0], [20.0, 44.0]);
+        let hilbert: HilbertSorter<f64, 2, 0> = HilbertSorter::new(&area);
+        assert_eq!(Curve::default().sorter(&area).curve_idx(&rect), hilbert.hilbert_idx(&rect));
+        assert_eq!("morton".parse::<Curve>(), Ok(Curve::ZOrder));
+        assert_eq!("peano".parse::<Curve>(), Ok(Curve::Peano));
+        assert!("gray".parse::<Curve>().is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use std::fmt::{Display, Formatter};
+
+/// 对树的插入、删除、更新、合并和查询失败的原因。
+/// 操作失败时不会panic，调用者可以丢弃出错的请求继续处理后续请求
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EsmtError {
+    /// 插入的key已经存在
+    DuplicateKey(String),
+    /// key不存在，或者对象不在给定的位置上
+    KeyNotFound(String),
+    /// 对象的位置超出了分区管理的区域
+    OutOfArea(String),
+    /// 折线对象的形状由顶点决定，不能只移动其位置
+    PolylineMove(String),
+    /// 树中没有任何对象
+    EmptyTree,
+    /// 延迟计算哈希的修改还没有commit，节点中的哈希已经过期
+    Uncommitted,
+    /// 树的结构与记录不一致，如期望子节点的位置出现了空间对象
+    CorruptedStructure(&'static str),
+}
+
+impl Display for EsmtError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EsmtError::DuplicateKey(key) => write!(f, "key {} already exists", key),
+            EsmtError::KeyNotFound(key) => write!(f, "key {} not found", key),
+            EsmtError::OutOfArea(key) => write!(f, "location of key {} is outside of the managed area", key),
+            EsmtError::PolylineMove(key) => write!(f, "key {} is a polyline and cannot be moved by location", key),
+            EsmtError::EmptyTree => write!(f, "tree is empty"),
+            EsmtError::Uncommitted => write!(f, "tree has uncommitted changes"),
+            EsmtError::CorruptedStructure(reason) => write!(f, "corrupted structure: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for EsmtError {}
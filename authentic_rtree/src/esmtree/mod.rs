@@ -1,25 +1,28 @@
 use core::time;
 use std::collections::{VecDeque, HashMap, HashSet};
 use std::time::{Instant, Duration};
-use types::hash_value::HashValue;
-use crate::node::{ESMTEntry, FromPrimitive, HilbertSorter, MRTreeDefault, MRTreeFunc, Node, ObjectEntry, ToPrimitive};
-use crate::shape::Rect;
-use crate::verify::{VerifyObject, VerifyObjectEntry, SiblingObject};
+use types::hash_value::{HashBackend, HashValue, Sha3_256};
+use crate::curve::{Curve, sort_by_curve};
+use crate::error::EsmtError;
+use crate::node::{ESMTEntry, FromPrimitive, KnnBound, MRTreeDefault, MRTreeFunc, Node, ObjectEntry, ToPrimitive, KeyIndex, knn_dists, merkle_path, merkle_root, partition_leaf};
+use crate::shape::{Circle, QueryShape, Rect};
+use crate::split::{HilbertSplit, Overflow, SplitPolicy};
+use crate::verify::{VerifyObject, VerifyObjectEntry, SiblingObject, KeyProof, PartitionProof, PartitionVerifyObject};
 use threadpool::ThreadPool;
 use std::sync::{Arc, Barrier, Mutex};
 
-struct EfficientMRTreeNode<V, const D: usize, const C: usize>
+struct EfficientMRTreeNode<V, const D: usize, const C: usize, H>
     where
         V: MRTreeDefault,
 {
-    node: Node<V, D, C>,
+    node: Node<V, D, C, H>,
 }
 
-impl<V, const D: usize, const C: usize> EfficientMRTreeNode<V, D, C>
+impl<V, const D: usize, const C: usize, H: HashBackend> EfficientMRTreeNode<V, D, C, H>
     where
         V: MRTreeDefault + MRTreeFunc + ToPrimitive + FromPrimitive,
 {
-    pub fn new(node: Node<V, D, C>) -> Self {
+    pub fn new(node: Node<V, D, C, H>) -> Self {
         Self {
             node,
         }
@@ -37,12 +40,17 @@ impl<V, const D: usize, const C: usize> EfficientMRTreeNode<V, D, C>
     }
 
     #[inline]
-    pub fn unpack_node(self) -> Node<V, D, C> {
+    pub fn unpack_node(self) -> Node<V, D, C, H> {
         self.node
     }
 
     /// 插入，重新计算当前层的mbr以及下一层的hash
-    fn insert_by_esmt(node: &mut Node<V, D, C>, obj: ESMTEntry<V, D, C>, loc: &Rect<V, D>, height: u32) {
+    fn insert_by_esmt(node: &mut Node<V, D, C, H>,
+                      obj: ESMTEntry<V, D, C, H>,
+                      loc: &Rect<V, D>,
+                      height: u32,
+                      overflow: &mut Overflow<V, D, C, H>,
+    ) -> Result<(), EsmtError> {
         if height == 0 {
             if let Some(i) = node.first_stale() {
                 node.entry[i] = obj;
@@ -52,39 +60,39 @@ impl<V, const D: usize, const C: usize> EfficientMRTreeNode<V, D, C>
             node.recalculate_mbr();
         } else {
             let subtree_idx = node.choose_subtree(&loc);
-            let node_mut = node.entry[subtree_idx].get_node_mut();
+            let node_mut = node.entry[subtree_idx].get_node_mut()?;
             // node_mut.insert_by_mrt(obj, loc, height - 1);
-            Self::insert_by_esmt(node_mut, obj, loc, height - 1);
+            Self::insert_by_esmt(node_mut, obj, loc, height - 1, overflow)?;
             // need to split
             if node_mut.entry.len() > Node::<V, D, C>::CAPACITY {
-                // 分裂并重新计算mbr
-                let new_node = node_mut.split_by_hilbert_sort();
-                node.mbr.expand(new_node.mbr());
-                node.mbr.expand(node_mut.mbr());
-                node.entry.push(ESMTEntry::ENode(new_node));
+                // 分裂并重新计算mbr；强制重插时节点已经重新计算了状态
+                if let Some(new_node) = overflow.treat(node_mut) {
+                    node.entry.push(ESMTEntry::ENode(new_node));
+                }
             } else {
-                node_mut.rehash();
+                node_mut.mark_dirty();
             }
             node.recalculate_mbr();
         }
+        Ok(())
     }
 
-    pub fn insert(&mut self, obj: ESMTEntry<V, D, C>, loc: &Rect<V, D>, height: u32) {
-        Self::insert_by_esmt(&mut self.node, obj, loc, height);
+    pub fn insert(&mut self, obj: ESMTEntry<V, D, C, H>, loc: &Rect<V, D>, height: u32, overflow: &mut Overflow<V, D, C, H>) -> Result<(), EsmtError> {
+        Self::insert_by_esmt(&mut self.node, obj, loc, height, overflow)
     }
 
     /// 删除时设置stale, 不需要重新计算哈希和mbr
-    fn _delete_by_esmt(node: &mut Node<V, D, C>,
+    fn _delete_by_esmt(node: &mut Node<V, D, C, H>,
                       rect: &Rect<V, D>,
                       key: &str,
                       height: u32,
-    ) -> Option<ESMTEntry<V, D, C>> {
+    ) -> Result<Option<ESMTEntry<V, D, C, H>>, EsmtError> {
         if height == 0 {
             for i in 0..node.entry.len() {
-                if node.entry[i].get_object().match_key(key) {
-                    let to_delete = node.entry[i].get_object().clone();
-                    node.entry[i].get_object_mut().delete();
-                    return Some(ESMTEntry::Object(to_delete));
+                if node.entry[i].get_object()?.is_live(key) {
+                    let to_delete = node.entry[i].get_object()?.clone();
+                    node.entry[i].get_object_mut()?.delete();
+                    return Ok(Some(ESMTEntry::Object(to_delete)));
                 }
             }
         } else {
@@ -92,104 +100,251 @@ impl<V, const D: usize, const C: usize> EfficientMRTreeNode<V, D, C>
                 if !rect.intersects(node.entry[i].mbr()) {
                     continue;
                 }
-                let child = node.entry[i].get_node_mut();
+                let child = node.entry[i].get_node_mut()?;
                 // let (removed, mut recalced) = node.delete_by_mrt(rect, key, reinsert, height - 1);
                 let removed =
-                    Self::_delete_by_esmt(child, rect, key, height - 1);
+                    Self::_delete_by_esmt(child, rect, key, height - 1)?;
                 if removed.is_none() {
                     continue;
                 }
-                return removed;
+                return Ok(removed);
             }
         }
-        None
+        Ok(None)
     }
 
     pub fn delete(&mut self,
                   rect: &Rect<V, D>,
                   key: &str,
                   height: u32,
-    ) -> Option<ESMTEntry<V, D, C>> {
+    ) -> Result<Option<ESMTEntry<V, D, C, H>>, EsmtError> {
         //Self::delete_by_esmt(&mut self.node, rect, key, height)
         let func =
-            |node: &mut Node<V, D, C>, key: &str| -> Option<ESMTEntry<V, D, C>> {
+            |node: &mut Node<V, D, C, H>, key: &str| -> Result<Option<ESMTEntry<V, D, C, H>>, EsmtError> {
                 for i in 0..node.entry.len() {
-                    if node.entry[i].get_object().match_key(key) {
-                        let to_delete = node.entry[i].get_object().clone();
-                        node.entry[i].get_object_mut().delete();
-                        return Some(ESMTEntry::Object(to_delete));
+                    if node.entry[i].get_object()?.is_live(key) {
+                        let to_delete = node.entry[i].get_object()?.clone();
+                        node.entry[i].get_object_mut()?.delete();
+                        return Ok(Some(ESMTEntry::Object(to_delete)));
                     }
                 }
-                None
+                Ok(None)
             };
-        Self::search_by_esmt(&mut self.node, rect, key, height, &func)
+        // 删除的对象不再计入对象数量，需要沿路径重新计算哈希
+        Self::search_by_esmt(&mut self.node, rect, key, height, true, &func)
     }
 
-    /// 如果调用了insert方法，返回true
+    /// 如果调用了insert方法，返回true；位置为oloc的对象不存在时返回[`EsmtError::KeyNotFound`]
     pub fn update(&mut self,
                   oloc: &Rect<V, D>,
                   nloc: Rect<V, D>,
                   key: &str,
                   height: u32,
-    ) -> bool {
+                  overflow: &mut Overflow<V, D, C, H>,
+    ) -> Result<bool, EsmtError> {
         let func =
-            |node: &mut Node<V, D, C>, key: &str| -> Option<ESMTEntry<V, D, C>> {
+            |node: &mut Node<V, D, C, H>, key: &str| -> Result<Option<ESMTEntry<V, D, C, H>>, EsmtError> {
                 for i in 0..node.entry.len() {
-                    if node.entry[i].get_object().match_key(key) {
+                    if node.entry[i].get_object()?.is_live(key) {
                         // 如果更新的位置还在原来的mbr中，则只调整空间对象的位置
                         if node.mbr.contains(&nloc) {
-                            node.entry[i].get_object_mut().update_loc(nloc.clone());
+                            node.entry[i].get_object_mut()?.update_loc(nloc.clone());
                         } else { // 删除原来的节点
-                            node.entry[i].get_object_mut().delete();
+                            node.entry[i].get_object_mut()?.delete();
                         }
-                        let to_return = node.entry[i].get_object().clone();
-                        return Some(ESMTEntry::Object(to_return));
+                        let to_return = node.entry[i].get_object()?.clone();
+                        return Ok(Some(ESMTEntry::Object(to_return)));
                     }
                 }
-                None
+                Ok(None)
             };
-        let mut updated_obj = Self::search_by_esmt(&mut self.node, oloc, key, height, &func).unwrap();
-        if updated_obj.get_object().is_stale() {
+        let mut updated_obj = Self::search_by_esmt(&mut self.node, oloc, key, height, true, &func)?
+            .ok_or_else(|| EsmtError::KeyNotFound(key.to_string()))?;
+        if updated_obj.get_object()?.is_stale() {
             // 更新位置和stale重新插入
-            updated_obj.get_object_mut().update_loc(nloc.clone());
-            updated_obj.get_object_mut().refresh();
-            self.insert(updated_obj, &nloc, height);
-            return true;
+            updated_obj.get_object_mut()?.update_loc(nloc.clone());
+            updated_obj.get_object_mut()?.refresh();
+            self.insert(updated_obj, &nloc, height, overflow)?;
+            return Ok(true);
         }
-        false
+        Ok(false)
     }
 
-    fn search_by_esmt(node: &mut Node<V, D, C>,
+    /// 原地替换位置为loc的对象的值哈希，只沿到根的路径重新计算哈希；对象不存在时返回`None`
+    pub fn update_hash(&mut self,
+                         loc: &Rect<V, D>,
+                         key: &str,
+                         value: HashValue,
+                         height: u32,
+    ) -> Result<Option<ESMTEntry<V, D, C, H>>, EsmtError> {
+        let func =
+            |node: &mut Node<V, D, C, H>, key: &str| -> Result<Option<ESMTEntry<V, D, C, H>>, EsmtError> {
+                for i in 0..node.entry.len() {
+                    if node.entry[i].get_object()?.is_live(key) {
+                        node.entry[i].get_object_mut()?.update_value(value);
+                        return Ok(Some(ESMTEntry::Object(node.entry[i].get_object()?.clone())));
+                    }
+                }
+                Ok(None)
+            };
+        Self::search_by_esmt(&mut self.node, loc, key, height, true, &func)
+    }
+
+    /// 查找key所在的叶子节点并调用func。refresh为true时沿路径重新计算mbr和哈希
+    fn search_by_esmt(node: &mut Node<V, D, C, H>,
                       rect: &Rect<V, D>,
                       key: &str,
                       height: u32,
-                      func: &dyn Fn(&mut Node<V, D, C>, &str) -> Option<ESMTEntry<V, D, C>>,
-    ) -> Option<ESMTEntry<V, D, C>> {
-        if height == 0 {
-            return func(node, key);
+                      refresh: bool,
+                      func: &dyn Fn(&mut Node<V, D, C, H>, &str) -> Result<Option<ESMTEntry<V, D, C, H>>, EsmtError>,
+    ) -> Result<Option<ESMTEntry<V, D, C, H>>, EsmtError> {
+        let found = if height == 0 {
+            func(node, key)?
         } else {
+            let mut found = None;
             for i in 0..node.entry.len() {
                 if !rect.intersects(node.entry[i].mbr()) {
                     continue;
                 }
-                let child = node.entry[i].get_node_mut();
-                let found =
-                    Self::search_by_esmt(child, rect, key, height - 1, func);
-                if found.is_none() {
-                    continue;
+                let child = node.entry[i].get_node_mut()?;
+                found = Self::search_by_esmt(child, rect, key, height - 1, refresh, func)?;
+                if found.is_some() {
+                    break;
+                }
+            }
+            found
+        };
+        if found.is_some() && refresh {
+            node.recalculate_mbr();
+            node.mark_dirty();
+        }
+        Ok(found)
+    }
+
+    /// 批量将对象标记为已删除，返回的对象与keys的顺序一致，没有找到的对象为`None`
+    pub fn batch_delete(&mut self,
+                        keys: &[&str],
+                        locs: &[&Rect<V, D>],
+                        height: u32,
+    ) -> Result<Vec<Option<ObjectEntry<V, D, H>>>, EsmtError> {
+        let mut removed = vec![None; keys.len()];
+        let mut func = |node: &mut Node<V, D, C, H>, i: usize, k: usize| -> Result<(), EsmtError> {
+            let obj = node.entry[i].get_object_mut()?;
+            obj.delete();
+            removed[k] = Some(obj.clone());
+            Ok(())
+        };
+        let mut found = vec![false; keys.len()];
+        Self::batch_search_by_esmt(&mut self.node, keys, locs, (0..keys.len()).collect(), &mut found, height, &mut func)?;
+        Ok(removed)
+    }
+
+    /// 批量移动对象。新位置仍在所在叶子节点的mbr中时原地修改，否则标记为已删除，
+    /// 返回需要在新位置重新插入的条目，以及每个对象是否找到
+    pub fn batch_update(&mut self,
+                        keys: &[&str],
+                        olocs: &[&Rect<V, D>],
+                        nlocs: &[Rect<V, D>],
+                        height: u32,
+    ) -> Result<(Vec<bool>, Vec<ESMTEntry<V, D, C, H>>), EsmtError> {
+        let mut reinsert = vec![];
+        let mut func = |node: &mut Node<V, D, C, H>, i: usize, k: usize| -> Result<(), EsmtError> {
+            if node.mbr.contains(&nlocs[k]) {
+                node.entry[i].get_object_mut()?.update_loc(nlocs[k].clone());
+            } else {
+                let obj = node.entry[i].get_object_mut()?;
+                obj.delete();
+                let mut moved = obj.clone();
+                moved.update_loc(nlocs[k].clone());
+                moved.refresh();
+                reinsert.push(ESMTEntry::Object(moved));
+            }
+            Ok(())
+        };
+        let mut found = vec![false; keys.len()];
+        Self::batch_search_by_esmt(&mut self.node, keys, olocs, (0..keys.len()).collect(), &mut found, height, &mut func)?;
+        Ok((found, reinsert))
+    }
+
+    /// 检查每个对象是否位于locs中对应的位置上，不修改树，返回每个对象是否找到
+    pub fn batch_contains(&self, keys: &[&str], locs: &[&Rect<V, D>], height: u32) -> Result<Vec<bool>, EsmtError> {
+        let mut found = vec![false; keys.len()];
+        Self::batch_find_by_esmt(&self.node, keys, locs, (0..keys.len()).collect(), &mut found, height)?;
+        Ok(found)
+    }
+
+    /// [`EfficientMRTreeNode::batch_search_by_esmt`]的只读版本
+    fn batch_find_by_esmt(node: &Node<V, D, C, H>,
+                          keys: &[&str],
+                          locs: &[&Rect<V, D>],
+                          pending: Vec<usize>,
+                          found: &mut [bool],
+                          height: u32,
+    ) -> Result<(), EsmtError> {
+        if height == 0 {
+            for k in pending {
+                found[k] = node.entry.iter()
+                    .any(|ety| matches!(ety, ESMTEntry::Object(obj) if obj.is_live(keys[k])));
+            }
+        } else {
+            for ety in node.entry.iter() {
+                let sub = pending.iter()
+                    .copied()
+                    .filter(|&k| !found[k] && locs[k].intersects(ety.mbr()))
+                    .collect::<Vec<_>>();
+                if !sub.is_empty() {
+                    Self::batch_find_by_esmt(ety.get_node()?, keys, locs, sub, found, height - 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 同时查找多个对象并调用func(叶子节点, 条目下标, 对象编号)。每个对象只进入与locs中的位置相交的子树，
+    /// 修改过对象的节点在所有对象处理完之后只重新计算一次mbr和哈希
+    fn batch_search_by_esmt(node: &mut Node<V, D, C, H>,
+                            keys: &[&str],
+                            locs: &[&Rect<V, D>],
+                            pending: Vec<usize>,
+                            found: &mut [bool],
+                            height: u32,
+                            func: &mut dyn FnMut(&mut Node<V, D, C, H>, usize, usize) -> Result<(), EsmtError>,
+    ) -> Result<bool, EsmtError> {
+        let mut dirty = false;
+        if height == 0 {
+            for k in pending {
+                let pos = node.entry.iter()
+                    .position(|ety| matches!(ety, ESMTEntry::Object(obj) if obj.is_live(keys[k])));
+                if let Some(i) = pos {
+                    func(node, i, k)?;
+                    found[k] = true;
+                    dirty = true;
                 }
-                return found;
             }
+        } else {
+            for ety in node.entry.iter_mut() {
+                let sub = pending.iter()
+                    .copied()
+                    .filter(|&k| !found[k] && locs[k].intersects(ety.mbr()))
+                    .collect::<Vec<_>>();
+                if !sub.is_empty() {
+                    dirty |= Self::batch_search_by_esmt(ety.get_node_mut()?, keys, locs, sub, found, height - 1, func)?;
+                }
+            }
+        }
+        if dirty {
+            node.recalculate_mbr();
+            node.mark_dirty();
         }
-        None
+        Ok(dirty)
     }
 
     /// 删除时会重新计算每一层的mbr以及hash；是否发生下溢由上一层进行判断
-    fn delete_downcast(node: &mut Node<V, D, C>,
+    fn delete_downcast(node: &mut Node<V, D, C, H>,
                      rect: &Rect<V, D>,
-                     reinsert: &mut VecDeque<ESMTEntry<V, D, C>>,
+                     reinsert: &mut VecDeque<ESMTEntry<V, D, C, H>>,
                      height: u32,
-    ) -> (Option<ESMTEntry<V, D, C>>, bool) {
+    ) -> Result<(Option<ESMTEntry<V, D, C, H>>, bool), EsmtError> {
         let subtree_idx = node.choose_subtree(rect);
         if height == 0 {
             let to_delete = node.entry.swap_remove(subtree_idx);
@@ -197,30 +352,30 @@ impl<V, const D: usize, const C: usize> EfficientMRTreeNode<V, D, C>
             if recalced {
                 node.recalculate_mbr();
             }
-            node.rehash();
-            (Some(to_delete),
-             recalced)
+            node.mark_dirty();
+            Ok((Some(to_delete),
+             recalced))
         } else {
-            let child = node.entry[subtree_idx].get_node_mut();
+            let child = node.entry[subtree_idx].get_node_mut()?;
             let (something_delete, mut recalced) =
-                Self::delete_downcast(child, rect, reinsert, height - 1);
+                Self::delete_downcast(child, rect, reinsert, height - 1)?;
             if child.need_downcast() {
                 reinsert.extend(child.entry.drain(..));
                 let underflow_node = node.entry.swap_remove(subtree_idx);
-                recalced = node.mbr.on_edge(underflow_node.mbr());
+                recalced |= node.mbr.on_edge(underflow_node.mbr());
             }
             if recalced {
                 node.recalculate_mbr();
             }
-            node.rehash();
-            (something_delete, recalced)
+            node.mark_dirty();
+            Ok((something_delete, recalced))
         }
     }
 
     /// 打包entry形成节点
-    fn pack_node(mut entries: Vec<ESMTEntry<V, D, C>>,
+    fn pack_node(mut entries: Vec<ESMTEntry<V, D, C, H>>,
                  height: u32,
-    ) -> Vec<ESMTEntry<V, D, C>> {
+    ) -> Vec<ESMTEntry<V, D, C, H>> {
         // 如果条目数量不足以打包，那么需要重新插入
         if entries.len() < Node::<V, D, C>::CAPACITY {
             return entries;
@@ -244,31 +399,26 @@ impl<V, const D: usize, const C: usize> EfficientMRTreeNode<V, D, C>
         nodes
     }
 
-    fn compact(root: Node<V,D,C>) -> Vec<ESMTEntry<V, D, C>> {
-        let sorter = HilbertSorter::new(root.mbr());
+    fn compact(root: Node<V, D, C, H>, curve: Curve) -> Result<Vec<ESMTEntry<V, D, C, H>>, EsmtError> {
+        let sorter = curve.sorter(root.mbr());
         let mut queue = VecDeque::new();
         let mut objs = vec![];
         queue.push_back(root);
-        while !queue.is_empty() {
-            let node =queue.pop_front().unwrap();
-            if node.height == 0 {
-                objs.extend(node.entry
-                    .into_iter()
-                    .filter(|e| {
-                        !e.get_object().is_stale()
-                    }));
-            } else {
-                queue.extend(node.entry
-                    .into_iter()
-                    .map(|e| {
-                        e.unpack_node()
-                    }));
+        while let Some(node) = queue.pop_front() {
+            for e in node.entry {
+                if node.height == 0 {
+                    if !e.get_object()?.is_stale() {
+                        objs.push(e);
+                    }
+                } else {
+                    queue.push_back(e.unpack_node()?);
+                }
             }
         }
-        sorter.sort(objs)
+        Ok(sort_by_curve(sorter.as_ref(), objs))
     }
 
-    fn build_tree(mut objs: Vec<ESMTEntry<V, D, C>>) -> Node<V, D, C> {
+    fn build_tree(mut objs: Vec<ESMTEntry<V, D, C, H>>) -> Node<V, D, C, H> {
         let cap = Node::<V, D, C>::CAPACITY;
         let mut height = 0u32;
         while objs.len() > cap {
@@ -280,128 +430,172 @@ impl<V, const D: usize, const C: usize> EfficientMRTreeNode<V, D, C>
         root
     }
 
-    pub fn range_query(&self, query: &Rect<V, D>, height: u32) -> VerifyObject<V, D> {
+    pub fn range_query<Q: QueryShape<V, D>>(&self, query: &Q, height: u32) -> Result<VerifyObject<V, D, H>, EsmtError> {
         Self::range_query_impl(&self.node, query, height)
     }
 
-    fn range_query_impl(node: &Node<V, D, C>, query: &Rect<V, D>, height: u32) -> VerifyObject<V, D> {
+    fn range_query_impl<Q: QueryShape<V, D>>(node: &Node<V, D, C, H>, query: &Q, height: u32) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        // 每个被访问的节点都完整地输出一层，查询结果为空时也能重新计算根哈希
         let mut vo = VerifyObject::new();
+        vo.push(VerifyObjectEntry::LevelBegin);
         if height == 0 {
-            let exist_vec = node.entry.iter()
-                .map(|ety| query.contains(ety.mbr()))
-                .collect::<Vec<_>>();
-            let exist_flag = exist_vec.iter().fold(false, |acc, e| acc || *e);
-            if exist_flag {
-                vo.push(VerifyObjectEntry::LevelBegin);
-                for i in 0..exist_vec.len() {
-                    if exist_vec[i] {
-                        vo.push(VerifyObjectEntry::Target(node.entry[i].get_object().clone()));
-                    } else {
-                        vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(node.entry[i].get_object())));
-                    }
+            for ety in node.entry.iter() {
+                // 已删除的对象不含在对象数量中，作为兄弟节点输出
+                if ety.count() > 0 && query.contains(ety.mbr()) {
+                    vo.push(VerifyObjectEntry::Target(ety.get_object()?.clone()));
+                } else {
+                    vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(ety.get_object()?)));
                 }
-                vo.push(VerifyObjectEntry::LevlEnd);
             }
         } else {
-            let mut exist_vec = vec![];
-            let mut temp_vo = VerifyObject::new();
             for ety in node.entry.iter() {
                 if query.intersects(ety.mbr()) {
-                    let sub_vo = Self::range_query_impl(ety.get_node(), query, height - 1);
-                    if sub_vo.is_empty() {
-                        exist_vec.push(false);
-                    } else {
-                        exist_vec.push(true);
-                        temp_vo.extend(sub_vo);
-                    }
+                    vo.extend(Self::range_query_impl(ety.get_node()?, query, height - 1)?);
                 } else {
-                    exist_vec.push(false);
-                }
-            }
-            if !temp_vo.is_empty() {
-                vo.push(VerifyObjectEntry::LevelBegin);
-                vo.extend(temp_vo);
-                for i in 0..exist_vec.len() {
-                    if !exist_vec[i] {
-                        vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(node.entry[i].get_node())));
-                    }
+                    vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(ety.get_node()?)));
                 }
-                vo.push(VerifyObjectEntry::LevlEnd);
             }
         }
-        vo
+        vo.push(VerifyObjectEntry::LevlEnd);
+        Ok(vo)
     }
 
-    pub fn traverse(&self, height: u32) -> VerifyObject<V, D> {
+    pub fn traverse(&self, height: u32) -> Result<VerifyObject<V, D, H>, EsmtError> {
         Self::traverse_impl(&self.node, height)
     }
 
-    fn traverse_impl(node: &Node<V, D, C>, height: u32) -> VerifyObject<V, D> {
+    fn traverse_impl(node: &Node<V, D, C, H>, height: u32) -> Result<VerifyObject<V, D, H>, EsmtError> {
         let mut vo = VerifyObject::new();
         if height == 0 {
             vo.push(VerifyObjectEntry::LevelBegin);
             for entry in node.entry.iter() {
-                if entry.get_object().is_stale() {
-                    vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(entry.get_object())));
+                let obj = entry.get_object()?;
+                if obj.is_stale() {
+                    vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(obj)));
                 } else {
-                    vo.push(VerifyObjectEntry::Target(entry.get_object().clone()));
+                    vo.push(VerifyObjectEntry::Target(obj.clone()));
                 }
             }
             vo.push(VerifyObjectEntry::LevlEnd);
         } else {
             let mut temp_vo = VerifyObject::new();
             for ety in node.entry.iter() {
-                let sub_vo = Self::traverse_impl(ety.get_node(), height - 1);
+                let sub_vo = Self::traverse_impl(ety.get_node()?, height - 1)?;
                 temp_vo.extend(sub_vo);
             }
             vo.push(VerifyObjectEntry::LevelBegin);
             vo.extend(temp_vo);
             vo.push(VerifyObjectEntry::LevlEnd);
         }
-        vo
+        Ok(vo)
     }
 }
 
-pub struct PartionTree<V, const D: usize, const C: usize> 
+pub struct PartionTree<V, const D: usize, const C: usize, H = Sha3_256> 
     where
         V: MRTreeDefault,
 {
-    root: Option<EfficientMRTreeNode<V, D, C>>,
+    root: Option<EfficientMRTreeNode<V, D, C, H>>,
     area: Rect<V, D>,
     height: u32,
     len: usize,
     keys: HashSet<String>,
+    split: Arc<dyn SplitPolicy<V, D>>,
+    /// 压缩重建子树时使用的空间填充曲线
+    curve: Curve,
+    /// 为true时修改只把路径上的节点标记为dirty，由[`PartionTree::commit`]统一重新计算哈希
+    deferred: bool,
 }
 
-impl<V, const D: usize, const C: usize> PartionTree<V, D, C> 
+impl<V, const D: usize, const C: usize, H: HashBackend> PartionTree<V, D, C, H> 
     where
         V: MRTreeDefault + MRTreeFunc + ToPrimitive + FromPrimitive,
 {
     pub fn new() -> Self {
-        Self {
-            root: None,
-            area: Rect::default(),
-            height: 0,
-            len: 0,
-            keys: HashSet::new(),
-        }
+        Self::new_with_area(Rect::default())
     }
 
     pub fn new_with_area(area: Rect<V, D>) -> Self {
+        Self::new_with_split(area, Arc::new(HilbertSplit))
+    }
+
+    /// 使用指定的节点分裂策略，参见[`crate::split`]
+    pub fn with_split_policy<P: SplitPolicy<V, D> + 'static>(policy: P) -> Self {
+        Self::new_with_split(Rect::default(), Arc::new(policy))
+    }
+
+    fn new_with_split(area: Rect<V, D>, split: Arc<dyn SplitPolicy<V, D>>) -> Self {
         Self {
             root: None,
             area,
             height: 0,
             len: 0,
             keys: HashSet::new(),
+            split,
+            curve: Curve::default(),
+            deferred: false,
         }
     }
 
+    #[inline]
+    pub fn curve(&self) -> Curve {
+        self.curve
+    }
+
+    /// 设置压缩重建子树时使用的空间填充曲线，只影响之后的重建
+    pub fn set_curve(&mut self, curve: Curve) {
+        self.curve = curve;
+    }
+
     #[inline]
     pub fn area(&self) -> Rect<V, D> {
         self.area.clone()
     }
 
+    #[inline]
+    pub fn is_deferred(&self) -> bool {
+        self.deferred
+    }
+
+    /// 开启后修改不再立即重新计算哈希，同一批修改经过的节点只在[`PartionTree::commit`]时计算一次。
+    /// 关闭时立即commit
+    pub fn set_deferred(&mut self, deferred: bool) {
+        self.deferred = deferred;
+        if !deferred {
+            self.commit();
+        }
+    }
+
+    /// 是否有尚未commit的修改
+    #[inline]
+    pub fn is_dirty(&self) -> bool {
+        self.root.as_ref().is_some_and(|r| r.node.dirty)
+    }
+
+    /// 自底向上重新计算所有dirty节点的哈希，每个节点只计算一次
+    pub fn commit(&mut self) {
+        if let Some(root) = self.root.as_mut() {
+            root.node.commit();
+        }
+    }
+
+    /// 非延迟模式下每个修改操作结束时立即commit
+    fn settle<T>(&mut self, res: Result<T, EsmtError>) -> Result<T, EsmtError> {
+        if !self.deferred {
+            self.commit();
+        }
+        res
+    }
+
+    /// 用于生成VO的根节点，树为空时返回[`EsmtError::EmptyTree`]，有未commit的修改时返回[`EsmtError::Uncommitted`]
+    fn query_root(&self) -> Result<&EfficientMRTreeNode<V, D, C, H>, EsmtError> {
+        match &self.root {
+            None => Err(EsmtError::EmptyTree),
+            Some(root) if root.node.dirty => Err(EsmtError::Uncommitted),
+            Some(root) => Ok(root),
+        }
+    }
+
     pub fn range(&self) -> Option<Rect<V, D>> {
         match &self.root {
             None => { None }
@@ -416,169 +610,343 @@ impl<V, const D: usize, const C: usize> PartionTree<V, D, C>
         self.len
     }
 
-    pub fn root_hash(&self) -> Option<HashValue> {
+    /// 根哈希，树为空时为`None`。与生成VO的查询相同，有未commit的修改时返回[`EsmtError::Uncommitted`]
+    pub fn root_hash(&self) -> Result<Option<HashValue>, EsmtError> {
         match &self.root {
-            None => { None }
-            Some(r) => { Some(r.hash()) }
+            None => Ok(None),
+            Some(r) if r.node.dirty => Err(EsmtError::Uncommitted),
+            Some(r) => Ok(Some(r.hash())),
         }
     }
 
     #[inline]
-    pub fn contains(&self, key: &String) -> bool {
+    pub fn contains(&self, key: &str) -> bool {
         self.keys.contains(key)
     }
 
-    pub fn insert(&mut self, key: String, loc:[V; D], hash: HashValue) {
+    pub fn insert(&mut self, key: String, loc:[V; D], hash: HashValue) -> Result<(), EsmtError> {
+        self.insert_object(ObjectEntry::new(key, loc, hash))
+    }
+
+    /// 插入任意形状的空间对象，矩形和折线对象以其MBR参与索引。key已经存在时返回[`EsmtError::DuplicateKey`]
+    pub fn insert_object(&mut self, obj: ObjectEntry<V, D, H>) -> Result<(), EsmtError> {
+        let key = obj.key();
+        if self.keys.contains(&key) {
+            return Err(EsmtError::DuplicateKey(key));
+        }
         if self.root.is_none() {
             self.root = Some(EfficientMRTreeNode::new_with_height(0));
         }
-        let obj = ESMTEntry::Object(ObjectEntry::new(key.clone(), loc, hash));
+        let obj = ESMTEntry::Object(obj);
         let obj_loc = obj.mbr().clone();
-        self.insert_impl(obj, &obj_loc, self.height);
-        self.len += 1;
-        self.keys.insert(key);
-    }
-
-    fn insert_impl(&mut self, entry: ESMTEntry<V, D, C>, loc: &Rect<V, D>, height: u32) {
-        let root = self.root.as_mut().unwrap();
-        root.insert(entry, loc, height);
-        let need_split = root.node.is_overflow();
-        if need_split {
-            self.height += 1;
-            let mut new_root = Node::new_with_height(self.height);
-            let mut origin = self.root.take().unwrap().unpack_node();
-            let another = origin.split_by_hilbert_sort();
-            new_root.entry.push(ESMTEntry::ENode(origin));
-            new_root.entry.push(ESMTEntry::ENode(another));
-            new_root.recalculate_state_after_sort();
-            self.root = Some(EfficientMRTreeNode::new(new_root));
-        } else {
-            root.node.rehash()
+        let res = self.insert_impl(obj, &obj_loc, self.height);
+        if res.is_ok() {
+            self.len += 1;
+            self.keys.insert(key);
         }
+        self.settle(res)
     }
 
-    fn insert_node(&mut self, node: Node<V, D, C>, keys: Vec<String>) {
+    /// 批量插入任意形状的空间对象，插入过程中经过的节点只在最后重新计算一次哈希。
+    /// 有key已经存在或重复时返回[`EsmtError::DuplicateKey`]，不做任何修改
+    pub fn batch_insert_objects(&mut self, objs: Vec<ObjectEntry<V, D, H>>) -> Result<(), EsmtError> {
+        let mut seen = HashSet::new();
+        for obj in objs.iter() {
+            let key = obj.key();
+            if self.keys.contains(&key) || !seen.insert(key.clone()) {
+                return Err(EsmtError::DuplicateKey(key));
+            }
+        }
+        if objs.is_empty() {
+            return Ok(());
+        }
         if self.root.is_none() {
-            self.height = node.height;
-            self.len = keys.len();
-            self.keys.extend(keys);
-            self.root = Some(EfficientMRTreeNode::new(node));
-        } else {
+            self.root = Some(EfficientMRTreeNode::new_with_height(0));
+        }
+        let mut res = Ok(());
+        for obj in objs {
+            let key = obj.key();
+            let obj = ESMTEntry::Object(obj);
+            let obj_loc = obj.mbr().clone();
+            res = self.insert_impl(obj, &obj_loc, self.height);
+            if res.is_err() {
+                break;
+            }
+            self.len += 1;
+            self.keys.insert(key);
+        }
+        self.settle(res)
+    }
+
+    fn insert_impl(&mut self, entry: ESMTEntry<V, D, C, H>, loc: &Rect<V, D>, height: u32) -> Result<(), EsmtError> {
+        let split = self.split.clone();
+        let mut overflow = Overflow::new(split.as_ref());
+        self.root.as_mut().ok_or(EsmtError::EmptyTree)?.insert(entry, loc, height, &mut overflow)?;
+        self.treat_root(&mut overflow)
+    }
+
+    /// 插入后处理根节点的溢出，然后重插被强制移出的条目
+    fn treat_root(&mut self, overflow: &mut Overflow<V, D, C, H>) -> Result<(), EsmtError> {
+        loop {
+            let root = self.root.as_mut().ok_or(EsmtError::EmptyTree)?;
+            if root.node.is_overflow() {
+                self.height += 1;
+                let mut new_root = Node::new_with_height(self.height);
+                let mut origin = self.root.take().ok_or(EsmtError::EmptyTree)?.unpack_node();
+                let another = origin.split_with(overflow.policy());
+                new_root.entry.push(ESMTEntry::ENode(origin));
+                new_root.entry.push(ESMTEntry::ENode(another));
+                new_root.recalculate_state_after_sort();
+                self.root = Some(EfficientMRTreeNode::new(new_root));
+            } else {
+                root.node.mark_dirty()
+            }
+            match overflow.pop() {
+                Some(entry) => {
+                    let entry_loc = entry.mbr().clone();
+                    let height = self.insert_height(&entry);
+                    self.root.as_mut().ok_or(EsmtError::EmptyTree)?.insert(entry, &entry_loc, height, overflow)?;
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// 条目应当插入的深度：对象插入叶子节点，子树插入到高度比它大1的节点中
+    fn insert_height(&self, entry: &ESMTEntry<V, D, C, H>) -> u32 {
+        match entry {
+            ESMTEntry::ENode(n) => self.height - n.height - 1,
+            ESMTEntry::Object(_) => self.height,
+        }
+    }
+
+    fn insert_node(&mut self, node: Node<V, D, C, H>, keys: Vec<String>) -> Result<(), EsmtError> {
+        if let Some(root) = self.root.take() {
             let (large_tree, small_tree) = if self.height < node.height {
-                (node, self.root.take().unwrap().unpack_node())
+                (node, root.unpack_node())
             } else {
-                (self.root.take().unwrap().unpack_node(), node)
+                (root.unpack_node(), node)
             };
 
             let expected_insert_height = (large_tree.height as i32) - (small_tree.height as i32) - 1;
             if expected_insert_height >= 0 {
                 let loc = small_tree.mbr.clone();
+                self.height = large_tree.height;
                 self.root = Some(EfficientMRTreeNode::new(large_tree));
-                self.insert_impl(ESMTEntry::ENode(small_tree), &loc, expected_insert_height as u32);
+                self.insert_impl(ESMTEntry::ENode(small_tree), &loc, expected_insert_height as u32)?;
             } else {
-                let new_root = Node::new_with_entry(
+                let mut new_root = Node::new_with_entry(
                     small_tree.height + 1,
                     vec![
                         ESMTEntry::ENode(large_tree),
                         ESMTEntry::ENode(small_tree),
                     ]
                 );
+                new_root.recalculate_state_after_sort();
                 self.height = new_root.height;
                 self.root = Some(EfficientMRTreeNode::new(new_root));
             }
             self.len += keys.len();
             self.keys.extend(keys);
+        } else {
+            self.height = node.height;
+            self.len = keys.len();
+            self.keys.extend(keys);
+            self.root = Some(EfficientMRTreeNode::new(node));
         }
+        self.settle(Ok(()))
+    }
+
+    pub fn delete(&mut self, key: &str, rect: &[V;D]) -> Result<ObjectEntry<V, D, H>, EsmtError> {
+        self.delete_rect(key, &Rect::new_point(*rect))
+    }
 
+    /// 删除位置（矩形和折线对象为其MBR）为loc的对象，对象不存在时返回[`EsmtError::KeyNotFound`]
+    pub fn delete_rect(&mut self, key: &str, loc: &Rect<V, D>) -> Result<ObjectEntry<V, D, H>, EsmtError> {
+        let root = match &mut self.root {
+            Some(root) if self.keys.contains(key) => root,
+            _ => return Err(EsmtError::KeyNotFound(key.to_string())),
+        };
+        let entry = root.delete(loc, key, self.height)?
+            .ok_or_else(|| EsmtError::KeyNotFound(key.to_string()))?;
+        self.len -= 1;
+        self.keys.remove(key);
+        self.settle(entry.unpack_object())
+    }
+
+    /// 将位置为oloc的对象移动到nloc，对象不存在时返回[`EsmtError::KeyNotFound`]
+    pub fn update(&mut self, key: &str, oloc: &[V; D], nloc: [V; D]) -> Result<(), EsmtError> {
+        self.update_rect(key, &Rect::new_point(*oloc), Rect::new_point(nloc))
+    }
+
+    /// 将位置为oloc的对象移动到矩形nloc，折线对象移动后成为矩形对象
+    pub fn update_rect(&mut self, key: &str, oloc: &Rect<V, D>, nloc: Rect<V, D>) -> Result<(), EsmtError> {
+        let split = self.split.clone();
+        let mut overflow = Overflow::new(split.as_ref());
+        let root = match &mut self.root {
+            Some(root) if self.keys.contains(key) => root,
+            _ => return Err(EsmtError::KeyNotFound(key.to_string())),
+        };
+        let res = match root.update(oloc, nloc, key, self.height, &mut overflow) {
+            Ok(true) => self.treat_root(&mut overflow),
+            res => res.map(|_| ()),
+        };
+        self.settle(res)
     }
 
-    pub fn delete(&mut self, key: &str, rect: &[V;D]) -> Option<ObjectEntry<V, D>> {
-        if let Some(root) = &mut self.root {
-            let loc = Rect::new_point(rect.clone());
-            let entry = root.delete(&loc, key, self.height);
-            if entry.is_none() {
-                return None;
+    pub fn update_hash(&mut self, key: &str, loc: &[V; D], value: HashValue) -> Result<(), EsmtError> {
+        self.update_hash_rect(key, &Rect::new_point(*loc), value)
+    }
+
+    /// 替换位置（矩形和折线对象为其MBR）为loc的对象的值哈希，对象的位置不变，
+    /// 只重新计算从叶子到根路径上节点的哈希。对象不存在时返回[`EsmtError::KeyNotFound`]
+    pub fn update_hash_rect(&mut self, key: &str, loc: &Rect<V, D>, value: HashValue) -> Result<(), EsmtError> {
+        let root = match &mut self.root {
+            Some(root) if self.keys.contains(key) => root,
+            _ => return Err(EsmtError::KeyNotFound(key.to_string())),
+        };
+        let res = root.update_hash(loc, key, value, self.height)
+            .and_then(|found| found.map(|_| ()).ok_or_else(|| EsmtError::KeyNotFound(key.to_string())));
+        self.settle(res)
+    }
+
+    /// 检查批量操作中的key都存在、没有重复，并且对象都位于locs中对应的位置上。
+    /// 只读取树，检查通过后批量操作中的每个对象都能找到
+    fn check_batch_items(&self, keys: &[&str], locs: &[&Rect<V, D>]) -> Result<(), EsmtError> {
+        let mut seen = HashSet::new();
+        for key in keys.iter().copied() {
+            if !self.keys.contains(key) {
+                return Err(EsmtError::KeyNotFound(key.to_string()));
             }
-            self.len -= 1;
-            self.keys.remove(key);
-            entry.map(|e| e.unpack_object())
-        } else {
-            None
-        }
-    }
-
-    /// assert key exist in the partion
-    pub fn update(&mut self, key: &str, oloc: &[V; D], nloc: [V; D]) {
-        if let Some(root) = &mut self.root {
-            let orect = Rect::new_point(oloc.clone());
-            let nrect = Rect::new_point(nloc);
-            let call_insert = root.update(&orect, nrect, key, self.height);
-            if call_insert {
-                let need_split = root.node.is_overflow();
-                if need_split {
-                    self.height += 1;
-                    let mut new_root = Node::new_with_height(self.height);
-                    let mut origin = self.root.take().unwrap().unpack_node();
-                    let another = origin.split_by_hilbert_sort();
-                    new_root.entry.push(ESMTEntry::ENode(origin));
-                    new_root.entry.push(ESMTEntry::ENode(another));
-                    new_root.recalculate_state_after_sort();
-                    self.root = Some(EfficientMRTreeNode::new(new_root));
-                } else {
-                    root.node.rehash()
-                }
+            if !seen.insert(key) {
+                return Err(EsmtError::DuplicateKey(key.to_string()));
+            }
+        }
+        let found = match &self.root {
+            Some(root) => root.batch_contains(keys, locs, self.height)?,
+            None => vec![false; keys.len()],
+        };
+        match found.iter().position(|f| !f) {
+            Some(k) => Err(EsmtError::KeyNotFound(keys[k].to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// 批量删除位置（矩形和折线对象为其MBR）为loc的对象，每个被修改的节点只重新计算一次哈希。
+    /// 返回的对象与items的顺序一致。有key不存在、重复或对象不在给定的位置上时返回错误，不做任何修改
+    pub fn batch_delete(&mut self, items: &[(String, Rect<V, D>)]) -> Result<Vec<ObjectEntry<V, D, H>>, EsmtError> {
+        let keys = items.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>();
+        let locs = items.iter().map(|(_, loc)| loc).collect::<Vec<_>>();
+        self.check_batch_items(&keys, &locs)?;
+        let root = match &mut self.root {
+            Some(root) => root,
+            None => return Ok(vec![]),
+        };
+        let removed = root.batch_delete(&keys, &locs, self.height);
+        let removed = self.settle(removed)?;
+        let mut res = Vec::with_capacity(items.len());
+        for (key, obj) in keys.into_iter().zip(removed) {
+            if let Some(obj) = obj {
+                self.len -= 1;
+                self.keys.remove(key);
+                res.push(obj);
             }
         }
+        if res.len() != items.len() {
+            return Err(EsmtError::CorruptedStructure("checked object is not found in batch delete"));
+        }
+        Ok(res)
+    }
+
+    /// 批量将对象从oloc移动到矩形nloc，新位置仍在原叶子节点中的对象原地修改，
+    /// 每个被修改的节点只重新计算一次哈希，其余对象随后重新插入。
+    /// 有key不存在、重复或对象不在oloc上时返回错误，不做任何修改
+    pub fn batch_update(&mut self, items: &[(String, Rect<V, D>, Rect<V, D>)]) -> Result<(), EsmtError> {
+        let keys = items.iter().map(|(key, _, _)| key.as_str()).collect::<Vec<_>>();
+        let olocs = items.iter().map(|(_, oloc, _)| oloc).collect::<Vec<_>>();
+        self.check_batch_items(&keys, &olocs)?;
+        let root = match &mut self.root {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+        let nlocs = items.iter().map(|(_, _, nloc)| nloc.clone()).collect::<Vec<_>>();
+        let res = root.batch_update(&keys, &olocs, &nlocs, self.height)
+            .and_then(|(found, reinsert)| {
+                for entry in reinsert {
+                    let loc = entry.mbr().clone();
+                    self.insert_impl(entry, &loc, self.height)?;
+                }
+                match found.iter().all(|f| *f) {
+                    true => Ok(()),
+                    false => Err(EsmtError::CorruptedStructure("checked object is not found in batch update")),
+                }
+            });
+        self.settle(res)
     }
 
-    pub fn merge_empty(&mut self) {
-        let root = self.root.take().unwrap().unpack_node();
-        let new_root = EfficientMRTreeNode::build_tree(EfficientMRTreeNode::compact(root));
+    /// 去掉已删除的对象并压缩重建整棵树，树为空时不做任何操作
+    pub fn merge_empty(&mut self) -> Result<(), EsmtError> {
+        let root = match self.root.take() {
+            Some(root) => root.unpack_node(),
+            None => return Ok(()),
+        };
+        let new_root = EfficientMRTreeNode::build_tree(EfficientMRTreeNode::compact(root, self.curve)?);
+        self.height = new_root.height;
         self.root = Some(EfficientMRTreeNode::new(new_root));
+        self.settle(Ok(()))
     }
 
     // !TODO: test correctness
-    pub fn merge_with_subtree(&mut self, mut another: PartionTree<V, D, C>) {
-        if another.root.is_none() {
-            return;
-        } else if self.root.is_none() {
-            let compacted_root =
-                EfficientMRTreeNode::build_tree(
-                    EfficientMRTreeNode::compact(
-                        another.root.take().unwrap().unpack_node()));
-            self.height = compacted_root.height;
-            self.len = another.len;
-            self.root = Some(EfficientMRTreeNode::new(compacted_root));
-            self.keys = another.keys;
-            return;
-        }
+    pub fn merge_with_subtree(&mut self, mut another: PartionTree<V, D, C, H>) -> Result<(), EsmtError> {
+        let (root, another_root) = match (self.root.take(), another.root.take()) {
+            (root, None) => {
+                self.root = root;
+                return Ok(());
+            },
+            (None, Some(another_root)) => {
+                let compacted_root =
+                    EfficientMRTreeNode::build_tree(
+                        EfficientMRTreeNode::compact(another_root.unpack_node(), self.curve)?);
+                self.height = compacted_root.height;
+                self.len = another.len;
+                self.root = Some(EfficientMRTreeNode::new(compacted_root));
+                self.keys = another.keys;
+                return self.settle(Ok(()));
+            },
+            (Some(root), Some(another_root)) => (root, another_root),
+        };
 
         let (mut large_tree, small_tree) = if self.height < another.height {
-            (another.root.take().unwrap().unpack_node(), self.root.take().unwrap().unpack_node())
+            (another_root.unpack_node(), root.unpack_node())
         } else {
-            (self.root.take().unwrap().unpack_node(), another.root.take().unwrap().unpack_node())
+            (root.unpack_node(), another_root.unpack_node())
         };
 
         let expected_repack_height = (large_tree.height as i32) - (small_tree.height as i32) - 1;
         if expected_repack_height >= 0 { // merge进来的子树的高度比自己低
             let mut reinsert = VecDeque::new();
             let (to_repack,_) =
-                EfficientMRTreeNode::delete_downcast(&mut large_tree, small_tree.mbr(), &mut reinsert, expected_repack_height as u32);
+                EfficientMRTreeNode::delete_downcast(&mut large_tree, small_tree.mbr(), &mut reinsert, expected_repack_height as u32)?;
 
             if large_tree.height != 0 && large_tree.entry.len() == 1 {
-                large_tree = large_tree.entry.pop().unwrap().unpack_node();
+                large_tree = large_tree.entry.swap_remove(0).unpack_node()?;
             }
 
-            let to_repack = to_repack.unwrap();
-            assert_eq!(to_repack.get_node().height, small_tree.height, "get different height subtrees");
+            let to_repack = to_repack
+                .ok_or(EsmtError::CorruptedStructure("no subtree to repack"))?
+                .unpack_node()?;
+            if to_repack.height != small_tree.height {
+                return Err(EsmtError::CorruptedStructure("get different height subtrees"));
+            }
             let to_compact = Node::new_with_entry(
                 small_tree.height + 1,
                 vec![
-                    ESMTEntry::ENode(to_repack.unpack_node()),
+                    ESMTEntry::ENode(to_repack),
                     ESMTEntry::ENode(small_tree),
                 ]
             );
-            let mut new_subtree = EfficientMRTreeNode::build_tree(EfficientMRTreeNode::compact(to_compact));
+            let mut new_subtree = EfficientMRTreeNode::build_tree(EfficientMRTreeNode::compact(to_compact, self.curve)?);
             // 根据new_subtree的高度和large_tree的高度要分类讨论
             if new_subtree.height > large_tree.height {
                 std::mem::swap(&mut large_tree, &mut new_subtree);
@@ -611,14 +979,8 @@ impl<V, const D: usize, const C: usize> PartionTree<V, D, C>
             self.root = Some(EfficientMRTreeNode::new(large_tree));
             while let Some(entry) = reinsert.pop_back() {
                 let entry_loc = entry.mbr().clone();
-                let expected_height_to_insert = if entry.is_node() {
-                    // println!("re-insert node");
-                    self.height - entry.get_node().height - 1
-                } else {
-                    // println!("re-insert object");
-                    self.height
-                };
-                self.insert_impl(entry, &entry_loc, expected_height_to_insert);
+                let expected_height_to_insert = self.insert_height(&entry);
+                self.insert_impl(entry, &entry_loc, expected_height_to_insert)?;
             }
             // update metadate
             self.len += another.len;
@@ -631,15 +993,16 @@ impl<V, const D: usize, const C: usize> PartionTree<V, D, C>
                     ESMTEntry::ENode(small_tree),
                 ]
             );
-            let new_root = EfficientMRTreeNode::build_tree(EfficientMRTreeNode::compact(to_compact));
+            let new_root = EfficientMRTreeNode::build_tree(EfficientMRTreeNode::compact(to_compact, self.curve)?);
             self.height = new_root.height;
             self.root = Some(EfficientMRTreeNode::new(new_root));
             self.len += another.len;
             self.keys.extend(another.keys);
-        }    
+        }
+        self.settle(Ok(()))
     }
 
-    pub fn clear(&mut self) -> PartionTree<V, D, C> {
+    pub fn clear(&mut self) -> PartionTree<V, D, C, H> {
         let root = self.root.take();
         let mut keys = HashSet::new();
         std::mem::swap(&mut keys, &mut self.keys);
@@ -649,36 +1012,34 @@ impl<V, const D: usize, const C: usize> PartionTree<V, D, C>
             height: self.height,
             len: self.len,
             keys: keys,
+            split: self.split.clone(),
+            curve: self.curve,
+            deferred: self.deferred,
         };
         self.height = 0;
         self.len = 0;
         partion
     }
 
-    pub fn range_query(&self, query: &Rect<V, D>) -> Option<VerifyObject<V, D>> {
-        if self.root.is_none() {
-            return None;
-        }
-        let root = self.root.as_ref().unwrap();
-        let vo = root.range_query(query, self.height);
-        if vo.is_empty() {
-            None
-        } else {
-            Some(vo)
-        }
+    /// 范围查询，树为空时返回[`EsmtError::EmptyTree`]；查询结果为空时返回的VO同样可以验证根哈希
+    pub fn range_query<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        self.query_root()?.range_query(query, self.height)
     }
 
-    pub fn traverse(&self) -> Option<VerifyObject<V, D>> {
-        if self.root.is_none() {
-            return None;
-        }
-        let root = self.root.as_ref().unwrap();
-        let vo = root.traverse(self.height);
-        if vo.is_empty() {
-            None
-        } else {
-            Some(vo)
-        }
+    /// 聚合范围查询，树为空时返回[`EsmtError::EmptyTree`]
+    pub fn count_query<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        Ok(self.query_root()?.node.count_query(query))
+    }
+
+    /// 半径查询，返回与center距离不超过radius的对象的VO，树为空时返回[`EsmtError::EmptyTree`]。
+    /// 使用[`VerifyObject::verify_circle`]验证
+    pub fn circle_query(&self, center: &[V; D], radius: V) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        self.range_query(&Circle::new(*center, radius))
+    }
+
+    /// 遍历树中所有对象，树为空时返回[`EsmtError::EmptyTree`]
+    pub fn traverse(&self) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        self.query_root()?.traverse(self.height)
     }
 
     pub fn display(&self) -> (Vec<(u32, Rect<V, D>)>, Vec<(bool, Rect<V, D>)>) {
@@ -693,7 +1054,8 @@ impl<V, const D: usize, const C: usize> PartionTree<V, D, C>
     }
 }
 
-pub struct PartionManager<V, const D: usize, const C: usize> 
+/// 所有分区、key索引和全局Merkle树使用同一个哈希后端`H`，默认为SHA3-256
+pub struct PartionManager<V, const D: usize, const C: usize, H = Sha3_256> 
     where
         V: MRTreeDefault,  
 {
@@ -702,11 +1064,20 @@ pub struct PartionManager<V, const D: usize, const C: usize>
     internal_pnum: usize,
     areas: Vec<Rect<V, D>>,
     centers: Vec<Rect<V, D>>,
-    partions: Vec<PartionTree<V, D, C>>,
-    key_2_loc: HashMap<String, [V; D]>,
+    partions: Vec<PartionTree<V, D, C, H>>,
+    /// 对象的位置，矩形和折线对象为其MBR
+    key_2_loc: HashMap<String, Rect<V, D>>,
+    /// 折线对象的顶点
+    key_2_polyline: HashMap<String, Vec<[V; D]>>,
+    /// 认证的key索引，按key排序保存对象的值哈希和摘要，参见[`PartionManager::get`]
+    key_index: KeyIndex<H>,
+    /// 延迟模式下key索引的修改只标记节点，在[`PartionManager::commit`]时一起计算
+    deferred: bool,
+    /// 批量插入时分区内排序使用的空间填充曲线
+    curve: Curve,
 }
 
-impl<V, const D: usize, const C: usize> PartionManager<V, D, C> 
+impl<V, const D: usize, const C: usize, H: HashBackend> PartionManager<V, D, C, H> 
     where
         V: MRTreeDefault + MRTreeFunc + ToPrimitive + FromPrimitive + Send + 'static,
 {
@@ -718,6 +1089,12 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
     /// | 1   3
     /// |_______ x
     pub fn new(area: Rect<V, D>, height: u32) -> Self {
+        Self::with_split_policy(area, height, HilbertSplit)
+    }
+
+    /// 所有分区使用同一个节点分裂策略，参见[`crate::split`]
+    pub fn with_split_policy<P: SplitPolicy<V, D> + 'static>(area: Rect<V, D>, height: u32, policy: P) -> Self {
+        let split: Arc<dyn SplitPolicy<V, D>> = Arc::new(policy);
         // assert_eq!(D, 2, "only support 2-d space now");
         let partion_cnt = ((Self::DEGREE.pow(height + 1) - 1) / (Self::DEGREE - 1)) as usize;
         let mut areas = Vec::with_capacity(partion_cnt);
@@ -727,7 +1104,7 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
         // 将2^D叉树根节点（最上层的partion）插入到数组中
         centers.push(Self::center(&area));
         areas.push(area.clone());
-        partions.push(PartionTree::new_with_area(area));
+        partions.push(PartionTree::new_with_split(area, split.clone()));
 
         // 处理其他的层
         for idx in 1..partion_cnt {
@@ -747,7 +1124,7 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
             }
             let cur_area = Rect::new(min, max);
             let cur_center = Self::center(&cur_area);
-            let partion = PartionTree::new_with_area(cur_area.clone());
+            let partion = PartionTree::new_with_split(cur_area.clone(), split.clone());
             centers.push(cur_center);
             areas.push(cur_area);
             partions.push(partion);
@@ -760,6 +1137,54 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
             centers,
             partions,
             key_2_loc: HashMap::new(),
+            key_2_polyline: HashMap::new(),
+            key_index: KeyIndex::new(),
+            deferred: false,
+            curve: Curve::default(),
+        }
+    }
+
+    #[inline]
+    pub fn curve(&self) -> Curve {
+        self.curve
+    }
+
+    /// 所有分区和key索引同时开启或关闭延迟计算哈希，参见[`PartionTree::set_deferred`]。关闭时立即commit
+    pub fn set_deferred(&mut self, deferred: bool) {
+        self.deferred = deferred;
+        for partion in self.partions.iter_mut() {
+            partion.set_deferred(deferred);
+        }
+        if !deferred {
+            self.key_index.commit();
+        }
+    }
+
+    /// 是否有分区或key索引存在尚未commit的修改
+    pub fn is_dirty(&self) -> bool {
+        self.partions.iter().any(|p| p.is_dirty()) || self.key_index.is_dirty()
+    }
+
+    /// 重新计算所有分区和key索引中dirty节点的哈希，之后才能读取[`PartionManager::get_hashes`]和全局根哈希
+    pub fn commit(&mut self) {
+        for partion in self.partions.iter_mut() {
+            partion.commit();
+        }
+        self.key_index.commit();
+    }
+
+    /// 非延迟模式下每个修改操作结束时重新计算key索引中被标记的节点
+    fn settle(&mut self) {
+        if !self.deferred {
+            self.key_index.commit();
+        }
+    }
+
+    /// 设置批量插入和各分区压缩重建时使用的空间填充曲线
+    pub fn set_curve(&mut self, curve: Curve) {
+        self.curve = curve;
+        for partion in self.partions.iter_mut() {
+            partion.set_curve(curve);
         }
     }
 
@@ -799,128 +1224,338 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
         parent
     }
 
-    /// assert key exist
-    fn get_pindex_with_key(&self, key: &String) -> Option<usize> {
-        if let Some(loc) = self.key_2_loc.get(key) {
-            let mut idx = self.point_index(loc);
-            while !self.partions[idx].contains(key) {
-                idx = (idx - 1) >> D;
+    /// 完全包含rect的最小分区。从根分区开始向下查找，rect跨越分区的中心时停在当前分区；
+    /// rect为点时与[`PartionManager::point_index`]相同
+    pub fn rect_index(&self, rect: &Rect<V, D>) -> usize {
+        let mut parent = 0usize;
+        for _ in 0..self.height {
+            let mut level_idx = 0usize;
+            for i in 0..D {
+                let center = self.centers[parent]._max[i];
+                let upper = rect._min[i] > center;
+                if upper != (rect._max[i] > center) {
+                    return parent;
+                }
+                level_idx = (level_idx << 1) | (upper as usize);
             }
-            Some(idx)
-        } else {
-            None
+            parent = ((parent << D) | level_idx) + 1;
         }
+        parent
     }
 
-    pub fn insert(&mut self, key: String, loc:[V; D], hash: HashValue) {
-        let partion_to_insert = self.point_index(&loc);
-        // 将新插入的数据对象添加到表中
-        self.key_2_loc.insert(key.clone(), loc.clone());
+    /// 所有分区中的对象数量
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.key_2_loc.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.key_2_loc.is_empty()
+    }
+
+    #[inline]
+    pub fn contains(&self, key: &str) -> bool {
+        self.key_2_loc.contains_key(key)
+    }
+
+    /// key所在的分区，key不存在时返回[`EsmtError::KeyNotFound`]
+    fn get_pindex_with_key(&self, key: &str) -> Result<usize, EsmtError> {
+        let loc = self.key_2_loc.get(key).ok_or_else(|| EsmtError::KeyNotFound(key.to_string()))?;
+        let mut idx = self.rect_index(loc);
+        while !self.partions[idx].contains(key) {
+            if idx == 0 {
+                return Err(EsmtError::CorruptedStructure("key is not in any partition"));
+            }
+            idx = (idx - 1) >> D;
+        }
+        Ok(idx)
+    }
+
+    /// 检查对象能否插入：key不能重复，位置必须在管理的区域内
+    fn check_insert(&self, key: &str, loc: &Rect<V, D>) -> Result<(), EsmtError> {
+        if self.key_2_loc.contains_key(key) {
+            return Err(EsmtError::DuplicateKey(key.to_string()));
+        }
+        if !self.areas[0].contains(loc) {
+            return Err(EsmtError::OutOfArea(key.to_string()));
+        }
+        Ok(())
+    }
 
-        self.insert_impl(key, loc, hash, partion_to_insert);
+    pub fn insert(&mut self, key: String, loc:[V; D], hash: HashValue) -> Result<(), EsmtError> {
+        self.insert_object(ObjectEntry::new(key, loc, hash))
     }
 
-    fn insert_impl(&mut self, key: String, loc:[V; D], hash: HashValue, index: usize) {
+    /// 插入任意形状的空间对象。跨越分区边界的对象插入到完全包含它的最小分区中，
+    /// 参见[`PartionManager::rect_index`]。
+    /// key已经存在时返回[`EsmtError::DuplicateKey`]，对象超出区域时返回[`EsmtError::OutOfArea`]
+    pub fn insert_object(&mut self, obj: ObjectEntry<V, D, H>) -> Result<(), EsmtError> {
+        let key = obj.key();
+        self.check_insert(&key, obj.loc())?;
+        let partion_to_insert = self.rect_index(obj.loc());
+        let loc = obj.loc().clone();
+        let polyline = obj.polyline().map(|vertices| vertices.to_vec());
+        let value = obj.value();
+        let digest = obj.hash();
+
+        self.insert_impl(obj, partion_to_insert)?;
+        // 将新插入的数据对象添加到表中
+        self.key_2_loc.insert(key.clone(), loc);
+        if let Some(vertices) = polyline {
+            self.key_2_polyline.insert(key.clone(), vertices);
+        }
+        self.key_index.insert(key, value, digest);
+        self.settle();
+        Ok(())
+    }
+
+    fn insert_impl(&mut self, obj: ObjectEntry<V, D, H>, index: usize) -> Result<(), EsmtError> {
         // 先处理需要merge的情况
-        self.merge(index, 1);
-        self.partions[index].insert(key, loc, hash);
+        self.merge(index, 1)?;
+        self.partions[index].insert_object(obj)
+    }
+
+    pub fn upsert(&mut self, key: String, loc: [V; D], hash: HashValue) -> Result<(), EsmtError> {
+        self.upsert_object(ObjectEntry::new(key, loc, hash))
     }
 
-    pub fn delete(&mut self, key: &String) -> Option<ObjectEntry<V, D>> {
-        let idx = self.get_pindex_with_key(key);
-        if let None = idx {
-            return None;
+    /// key不存在时插入对象，存在时用obj替换原来的对象，位置、形状和值哈希都以obj为准。
+    /// obj超出区域时返回[`EsmtError::OutOfArea`]，此时原来的对象保持不变
+    pub fn upsert_object(&mut self, obj: ObjectEntry<V, D, H>) -> Result<(), EsmtError> {
+        let key = obj.key();
+        if !self.areas[0].contains(obj.loc()) {
+            return Err(EsmtError::OutOfArea(key));
+        }
+        if self.contains(&key) {
+            self.delete(&key)?;
         }
-        let idx = idx.unwrap();
-        if let Some(oloc) = self.key_2_loc.remove(key) {
-            self.partions[idx].delete(key, &oloc)
+        self.insert_object(obj)
+    }
+
+    /// 只替换key对应对象的值哈希，位置不变，与[`PartionManager::update_hash`]相同
+    pub fn replace_value(&mut self, key: &str, hash: HashValue) -> Result<(), EsmtError> {
+        self.update_hash(key, hash)
+    }
+
+    /// 原地替换key对应对象的值哈希，只重新计算对象所在分区中到根路径上的哈希，
+    /// 并同步更新key索引。key不存在时返回[`EsmtError::KeyNotFound`]
+    pub fn update_hash(&mut self, key: &str, new_hash: HashValue) -> Result<(), EsmtError> {
+        let idx = self.get_pindex_with_key(key)?;
+        let loc = self.key_2_loc[key].clone();
+        self.partions[idx].update_hash_rect(key, &loc, new_hash)?;
+        let digest = self.key_object(key, new_hash).hash();
+        self.key_index.update(key, new_hash, digest);
+        self.settle();
+        Ok(())
+    }
+
+    /// 删除key对应的对象，key不存在时返回[`EsmtError::KeyNotFound`]
+    pub fn delete(&mut self, key: &str) -> Result<ObjectEntry<V, D, H>, EsmtError> {
+        let idx = self.get_pindex_with_key(key)?;
+        let oloc = self.key_2_loc[key].clone();
+        let obj = self.partions[idx].delete_rect(key, &oloc)?;
+        self.key_2_loc.remove(key);
+        self.key_index.remove(key);
+        self.key_2_polyline.remove(key);
+        self.settle();
+        Ok(obj)
+    }
+
+    pub fn update(&mut self, key: &str, nloc: [V; D]) -> Result<(), EsmtError> {
+        self.update_rect(key, Rect::new_point(nloc))
+    }
+
+    /// 将对象移动到矩形nloc。
+    /// key不存在时返回[`EsmtError::KeyNotFound`]，nloc超出区域时返回[`EsmtError::OutOfArea`]。
+    /// 折线对象返回[`EsmtError::PolylineMove`]，需要用[`PartionManager::upsert_object`]替换为新的折线
+    pub fn update_rect(&mut self, key: &str, nloc: Rect<V, D>) -> Result<(), EsmtError> {
+        let oidx = self.get_pindex_with_key(key)?;
+        if self.key_2_polyline.contains_key(key) {
+            return Err(EsmtError::PolylineMove(key.to_string()));
+        }
+        if !self.areas[0].contains(&nloc) {
+            return Err(EsmtError::OutOfArea(key.to_string()));
+        }
+        let nidx = self.rect_index(&nloc);
+        let oloc = self.key_2_loc[key].clone();
+        // 更新在同一分区中
+        if oidx == nidx {
+            self.partions[nidx].update_rect(key, &oloc, nloc.clone())?;
         } else {
-            None
+            let obj = self.partions[oidx].delete_rect(key, &oloc)?;
+            self.partions[nidx].insert_object(ObjectEntry::with_rect(key.to_string(), nloc.clone(), obj.value()))?;
+        }
+        // 更新表中的信息
+        self.record_move(key, nloc);
+        self.settle();
+        Ok(())
+    }
+
+    /// 对象移动到矩形nloc后更新表和key索引，被移动的对象不会是折线对象
+    fn record_move(&mut self, key: &str, nloc: Rect<V, D>) {
+        self.key_2_loc.insert(key.to_string(), nloc);
+        self.refresh_key_index(key);
+    }
+
+    /// 检查批量操作中的key都存在且没有重复，返回每个key所在的分区
+    fn batch_pindex<'a>(&self, keys: impl Iterator<Item = &'a String>) -> Result<Vec<usize>, EsmtError> {
+        let mut seen = HashSet::new();
+        let mut res = vec![];
+        for key in keys {
+            res.push(self.get_pindex_with_key(key)?);
+            if !seen.insert(key) {
+                return Err(EsmtError::DuplicateKey(key.clone()));
+            }
+        }
+        Ok(res)
+    }
+
+    /// 在修改任何分区之前，检查每个分区中的对象都位于表中记录的位置上
+    fn check_batch_groups<'a>(&self, groups: impl Iterator<Item = (usize, Vec<(&'a str, &'a Rect<V, D>)>)>) -> Result<(), EsmtError>
+        where
+            V: 'a,
+    {
+        for (pidx, group) in groups {
+            let (keys, locs): (Vec<_>, Vec<_>) = group.into_iter().unzip();
+            self.partions[pidx].check_batch_items(&keys, &locs)?;
+        }
+        Ok(())
+    }
+
+    /// 批量删除对象。按分区分组后在每个分区中批量标记删除，每个被修改的节点只重新计算一次哈希。
+    /// 返回的对象与keys的顺序一致。有key不存在或重复时不做任何修改；
+    /// 所有分区都先检查再修改，表与分区中的对象始终保持一致
+    pub fn batch_delete(&mut self, keys: &[String]) -> Result<Vec<ObjectEntry<V, D, H>>, EsmtError> {
+        let pindex = self.batch_pindex(keys.iter())?;
+        let mut groups = vec![Vec::new(); self.partions.len()];
+        for (k, (key, pidx)) in keys.iter().zip(pindex).enumerate() {
+            groups[pidx].push((k, key.clone(), self.key_2_loc[key].clone()));
         }
+        self.check_batch_groups(groups.iter().enumerate()
+            .filter(|(_, g)| !g.is_empty())
+            .map(|(pidx, g)| (pidx, g.iter().map(|(_, key, loc)| (key.as_str(), loc)).collect())))?;
+        let mut res = vec![None; keys.len()];
+        for (pidx, group) in groups.into_iter().enumerate().filter(|(_, g)| !g.is_empty()) {
+            let (order, items): (Vec<_>, Vec<_>) = group.into_iter().map(|(k, key, loc)| (k, (key, loc))).unzip();
+            let removed = self.partions[pidx].batch_delete(&items)?;
+            for (key, _) in items.iter() {
+                self.key_2_loc.remove(key);
+                self.key_2_polyline.remove(key);
+            }
+            self.key_index.remove_all(items.iter().map(|(key, _)| key));
+            for (k, obj) in order.into_iter().zip(removed) {
+                res[k] = Some(obj);
+            }
+        }
+        self.settle();
+        Ok(res.into_iter().flatten().collect())
     }
 
-    pub fn update(&mut self, key: &String, nloc: [V; D]) {
-        let nidx = self.point_index(&nloc);
-        let oidx = self.get_pindex_with_key(key).unwrap();
-        let some_data = self.key_2_loc.get(key).map(|loc| loc.clone());
-        if let Some(oloc) = some_data {
-            // 更新在同一分区中
+    /// 批量将对象移动到新的位置。留在原分区的对象在分区中批量修改，
+    /// 移到其他分区的对象先从原分区批量删除，再按新的分区分组批量插入。
+    /// 有key不存在、重复、是折线对象或新位置超出区域时不做任何修改，参见[`PartionManager::update_rect`]
+    pub fn batch_update(&mut self, items: &[(String, [V; D])]) -> Result<(), EsmtError> {
+        let pindex = self.batch_pindex(items.iter().map(|(key, _)| key))?;
+        if let Some((key, _)) = items.iter().find(|(key, _)| self.key_2_polyline.contains_key(key)) {
+            return Err(EsmtError::PolylineMove(key.clone()));
+        }
+        if let Some((key, _)) = items.iter().find(|(_, nloc)| !self.areas[0].contains(&Rect::new_point(*nloc))) {
+            return Err(EsmtError::OutOfArea(key.clone()));
+        }
+        let mut moves = vec![Vec::new(); self.partions.len()];
+        let mut leaves = vec![Vec::new(); self.partions.len()];
+        let mut arrivals = HashMap::new();
+        let mut sources = vec![Vec::new(); self.partions.len()];
+        for ((key, nloc), oidx) in items.iter().zip(pindex) {
+            let oloc = &self.key_2_loc[key];
+            let nloc = Rect::new_point(*nloc);
+            let nidx = self.rect_index(&nloc);
+            sources[oidx].push((key.as_str(), oloc));
             if oidx == nidx {
-                self.partions[nidx].update(key, &oloc, nloc);
+                moves[oidx].push((key.clone(), oloc.clone(), nloc));
             } else {
-                let obj = self.partions[oidx].delete(key, &oloc).unwrap();
-                // self.insert_impl(key.clone(), nloc.clone(), obj.hash(), nidx);
-                self.partions[nidx].insert(key.clone(), nloc.clone(), obj.hash());
+                leaves[oidx].push((key.clone(), oloc.clone()));
+                arrivals.insert(key.clone(), (nidx, nloc));
+            }
+        }
+        self.check_batch_groups(sources.into_iter().enumerate().filter(|(_, g)| !g.is_empty()))?;
+        // 每一组对象在分区中修改完成后立即更新表中的信息
+        for (pidx, group) in moves.into_iter().enumerate().filter(|(_, g)| !g.is_empty()) {
+            self.partions[pidx].batch_update(&group)?;
+            for (key, _, nloc) in group {
+                self.record_move(&key, nloc);
+            }
+        }
+        let mut groups = vec![Vec::new(); self.partions.len()];
+        for (pidx, group) in leaves.into_iter().enumerate().filter(|(_, g)| !g.is_empty()) {
+            for obj in self.partions[pidx].batch_delete(&group)? {
+                let key = obj.key();
+                let (nidx, nloc) = arrivals.remove(&key)
+                    .ok_or(EsmtError::CorruptedStructure("moved object is not in the batch"))?;
+                groups[nidx].push(ObjectEntry::with_rect(key, nloc, obj.value()));
+            }
+        }
+        // 移入同一个分区的对象一起插入，分区只重新计算一次哈希
+        for (nidx, group) in groups.into_iter().enumerate().filter(|(_, g)| !g.is_empty()) {
+            let moved = group.iter().map(|obj| (obj.key(), obj.loc().clone())).collect::<Vec<_>>();
+            self.merge(nidx, 1)?;
+            self.partions[nidx].batch_insert_objects(group)?;
+            for (key, nloc) in moved {
+                self.record_move(&key, nloc);
             }
-            // 更新表中的信息
-            let loc = self.key_2_loc.get_mut(key).unwrap();
-            *loc = nloc;
         }
+        self.settle();
+        Ok(())
     }
 
-    fn merge(&mut self, cur_partion: usize, threshold_mul: usize) {
+    fn merge(&mut self, cur_partion: usize, threshold_mul: usize) -> Result<(), EsmtError> {
         // 该partion不需要merge || 该partion是根partion
         if cur_partion == 0 || self.partions[cur_partion].len() < Self::BASIC_THRESHOLD * threshold_mul {
-            return;
+            return Ok(());
         }
         let parent = (cur_partion - 1) >> D;
         // 先merge上层的partion
-        self.merge(parent, threshold_mul << (D + D));
+        self.merge(parent, threshold_mul << (D + D))?;
         // 把自己merge上去
         let need_to_merge = self.partions[cur_partion].clear();
-        self.partions[parent].merge_with_subtree(need_to_merge);
+        self.partions[parent].merge_with_subtree(need_to_merge)
     }
 
-    pub fn range_query(&self, query: &Rect<V, D>) -> Vec<VerifyObject<V, D>> {
-        let mut res = vec![];
-        for p in self.partions.iter() {
-            if p.area.intersects(query) {
-                if let Some(vo) = p.range_query(query) {
-                    res.push(vo);
-                }
-            }
-        }
-        res
+    /// 在与查询相交的非空分区上调用query_fn，收集各分区的VO
+    fn collect_vo<'a, F>(&self, partions: impl Iterator<Item = &'a PartionTree<V, D, C, H>>, query_fn: F) -> Result<Vec<VerifyObject<V, D, H>>, EsmtError>
+        where
+            F: Fn(&PartionTree<V, D, C, H>) -> Result<VerifyObject<V, D, H>, EsmtError>,
+            V: 'a,
+            H: 'a,
+    {
+        partions.filter(|p| p.root.is_some())
+            .map(query_fn)
+            .collect()
     }
 
-    pub fn target_range_query(&self, targets: Vec<usize>, query: Rect<V, D>) -> Vec<VerifyObject<V, D>> {
-        let mut res = vec![];
-        for target in targets {
-            if let Some(vo) = self.partions[target].range_query(&query) {
-                res.push(vo);
-            }
-        }
-        res
+    pub fn range_query<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<Vec<VerifyObject<V, D, H>>, EsmtError> {
+        self.collect_vo(self.partions.iter().filter(|p| query.intersects(&p.area)), |p| p.range_query(query))
     }
 
-    pub fn target_traverse(&self, targets: Vec<usize>) -> Vec<VerifyObject<V, D>> {
-        let mut res = vec![];
-        for target in targets {
-            if let Some(vo) = self.partions[target].traverse() {
-                res.push(vo);
-            }
-        }
-        res
+    pub fn target_range_query(&self, targets: Vec<usize>, query: Rect<V, D>) -> Result<Vec<VerifyObject<V, D, H>>, EsmtError> {
+        self.collect_vo(targets.into_iter().map(|t| &self.partions[t]), |p| p.range_query(&query))
     }
 
-    pub fn traverse(&self, query: &Rect<V, D>) -> Vec<VerifyObject<V, D>> {
-        let mut res = vec![];
-        for p in self.partions.iter() {
-            if p.area.intersects(query) {
-                if let Some(vo) = p.traverse() {
-                    res.push(vo);
-                }
-            }
-        }
-        res
+    pub fn target_traverse(&self, targets: Vec<usize>) -> Result<Vec<VerifyObject<V, D, H>>, EsmtError> {
+        self.collect_vo(targets.into_iter().map(|t| &self.partions[t]), |p| p.traverse())
     }
 
-    pub fn edge_query(&self, query: &Rect<V, D>) -> Vec<VerifyObject<V, D>> {
+    pub fn traverse<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<Vec<VerifyObject<V, D, H>>, EsmtError> {
+        self.collect_vo(self.partions.iter().filter(|p| query.intersects(&p.area)), |p| p.traverse())
+    }
+
+    pub fn edge_query<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<Vec<VerifyObject<V, D, H>>, EsmtError> {
         let mut res = vec![];
         let mut qlist = Vec::with_capacity(self.partions.len());
         let mut tlist = Vec::with_capacity(self.partions.len());
         for (i, p) in self.partions.iter().enumerate() {
-            if p.area.intersects(query) {
+            if query.intersects(&p.area) {
                 if query.contains(&p.area) {
                     tlist.push(i);
                 } else {
@@ -928,17 +1563,77 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
                 }
             }
         }
-        for q in qlist {
-            if let Some(vo) = self.partions[q].range_query(query) {
-                res.push(vo);
+        res.extend(self.collect_vo(qlist.into_iter().map(|q| &self.partions[q]), |p| p.range_query(query))?);
+        res.extend(self.collect_vo(tlist.into_iter().map(|t| &self.partions[t]), |p| p.traverse())?);
+        Ok(res)
+    }
+
+    /// 带有全局证明的范围查询，结果可以用全局根哈希验证。查询可以是矩形或多边形
+    pub fn range_query_with_proof<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<PartitionVerifyObject<V, D, H>, EsmtError> {
+        self.query_with_proof(query, |p| p.range_query(query))
+    }
+
+    /// 带有全局证明的边缘查询，被查询完全包含的分区直接遍历
+    pub fn edge_query_with_proof<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<PartitionVerifyObject<V, D, H>, EsmtError> {
+        self.query_with_proof(query, |p| {
+            if query.contains(&p.area) {
+                p.traverse()
+            } else {
+                p.range_query(query)
             }
+        })
+    }
+
+    /// 带有全局证明的聚合范围查询，被查询完全包含的子树只返回其对象数量和摘要，
+    /// 使用[`PartitionVerifyObject::verify_count`]验证
+    pub fn count_query<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<PartitionVerifyObject<V, D, H>, EsmtError> {
+        self.query_with_proof(query, |p| p.count_query(query))
+    }
+
+    /// 带有全局证明的半径查询，使用[`PartitionVerifyObject::verify_circle`]验证
+    pub fn circle_query(&self, center: &[V; D], radius: V) -> Result<PartitionVerifyObject<V, D, H>, EsmtError> {
+        self.range_query_with_proof(&Circle::new(*center, radius))
+    }
+
+    /// 列出所有分区的范围和根哈希，只对与查询相交的非空分区调用query生成VO
+    fn query_with_proof<Q, F>(&self, query: &Q, query_fn: F) -> Result<PartitionVerifyObject<V, D, H>, EsmtError>
+        where
+            Q: QueryShape<V, D>,
+            F: Fn(&PartionTree<V, D, C, H>) -> Result<VerifyObject<V, D, H>, EsmtError>,
+    {
+        let mut res = PartitionVerifyObject::new(self.key_root()?);
+        for (p, area) in self.partions.iter().zip(self.areas.iter()) {
+            let vo = if p.root.is_some() && query.intersects(area) {
+                Some(query_fn(p)?)
+            } else {
+                None
+            };
+            res.push(area.clone(), p.root_hash()?, vo);
         }
-        for t in tlist {
-            if let Some(vo) = self.partions[t].traverse() {
-                res.push(vo);
-            }
+        Ok(res)
+    }
+
+    /// 跨分区的kNN查询。先在所有分区上做最优优先搜索确定第k近的距离，
+    /// 再为与point距离可能含有结果的非空分区生成VO
+    pub fn knn_query(&self, point: &[V; D], k: usize) -> Result<PartitionVerifyObject<V, D, H>, EsmtError> {
+        if self.is_dirty() {
+            return Err(EsmtError::Uncommitted);
         }
-        res
+        let point = Rect::new_point(*point);
+        let roots = self.partions.iter().filter_map(|p| p.root.as_ref().map(|r| &r.node));
+        let dists = knn_dists(roots, &point, k);
+        let mut bound = KnnBound::new(&dists, k);
+        let mut res = PartitionVerifyObject::new(self.key_root()?);
+        for (p, area) in self.partions.iter().zip(self.areas.iter()) {
+            let vo = match &p.root {
+                Some(root) if bound.as_ref().is_none_or(|b| b.reachable(area.rect_dist(&point))) => {
+                    Some(root.node.knn_query(&point, &mut bound)?)
+                },
+                _ => None,
+            };
+            res.push(area.clone(), p.root_hash()?, vo);
+        }
+        Ok(res)
     }
 
     #[inline]
@@ -949,7 +1644,7 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
         res
     }
 
-    pub fn parallel_query(&self, query: &Rect<V, D>, pool: &ThreadPool) -> (Duration, Vec<VerifyObject<V, D>>, Vec<f64>) {
+    pub fn parallel_query(&self, query: &Rect<V, D>, pool: &ThreadPool) -> (Duration, Vec<VerifyObject<V, D, H>>, Vec<f64>) {
         let res = vec![];
         // get list
         let mut qlist = Vec::with_capacity(self.partions.len());
@@ -997,7 +1692,21 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
         (eclipse, res, output_vec)
     }
 
-    pub fn batch_insert(&mut self, items: Vec<(String, [V; D], HashValue)>) {
+    /// 检查整批对象能否插入，任何一个对象不能插入时整批都不插入
+    fn check_batch(&self, items: &[(String, [V; D], HashValue)]) -> Result<(), EsmtError> {
+        let mut seen = HashSet::with_capacity(items.len());
+        for (key, loc, _) in items {
+            self.check_insert(key, &Rect::new_point(*loc))?;
+            if !seen.insert(key) {
+                return Err(EsmtError::DuplicateKey(key.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 批量插入点对象，每个叶子分区中的对象按空间填充曲线排序后直接打包成树
+    pub fn batch_insert(&mut self, items: Vec<(String, [V; D], HashValue)>) -> Result<(), EsmtError> {
+        self.check_batch(&items)?;
         let mut areas = vec![None; self.partions.len() - self.internal_pnum];
         let mut partion_set = vec![Vec::new(); self.partions.len() - self.internal_pnum];
         for item in items {
@@ -1015,27 +1724,34 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
             .map(|(pidx, (set, area))| {
                 let mut keys = vec![];
                 let mut keys_to_loc = vec![];
+                let mut indexed = vec![];
                 let to_sort = set.into_iter()
                     .map(|item| {
                         keys.push(item.0.clone());
                         keys_to_loc.push((item.0.clone(), item.1.clone()));
-                        ESMTEntry::Object(ObjectEntry::new(item.0, item.1, item.2))
+                        let obj = ObjectEntry::new(item.0, item.1, item.2);
+                        indexed.push((obj.key(), obj.value(), obj.hash()));
+                        ESMTEntry::Object(obj)
                     })
                     .collect();
-                let sorter: HilbertSorter<V, D, C> = HilbertSorter::new(&area.unwrap());
-                let sorted_set = sorter.sort(to_sort);
-                (pidx,EfficientMRTreeNode::build_tree(sorted_set), keys, keys_to_loc)
+                let sorter = self.curve.sorter(&area.unwrap());
+                let sorted_set = sort_by_curve(sorter.as_ref(), to_sort);
+                (pidx,EfficientMRTreeNode::build_tree(sorted_set), keys, keys_to_loc, indexed)
             })
             .collect::<Vec<_>>();
-        for (mut pidx, node, keys, k2l) in entry_to_insert {
+        self.key_index.extend(entry_to_insert.iter().flat_map(|(.., indexed)| indexed.iter().cloned()));
+        for (mut pidx, node, keys, k2l, _) in entry_to_insert {
             pidx += self.internal_pnum;
-            self.key_2_loc.extend(k2l);
-            self.merge(pidx, 1);
-            self.partions[pidx].insert_node(node, keys);
+            self.key_2_loc.extend(k2l.into_iter().map(|(k, loc)| (k, Rect::new_point(loc))));
+            self.merge(pidx, 1)?;
+            self.partions[pidx].insert_node(node, keys)?;
         }
+        self.settle();
+        Ok(())
     }
 
-    pub fn batch_iter_insert(&mut self, items: Vec<(String, [V; D], HashValue)>) {
+    pub fn batch_iter_insert(&mut self, items: Vec<(String, [V; D], HashValue)>) -> Result<(), EsmtError> {
+        self.check_batch(&items)?;
         let mut partion_set = vec![Vec::new(); self.partions.len() - self.internal_pnum];
         for item in items {
             let pidx = self.point_index(&item.1) - self.internal_pnum;
@@ -1045,48 +1761,142 @@ impl<V, const D: usize, const C: usize> PartionManager<V, D, C>
         for (mut pidx, node) in partion_set.into_iter().enumerate() {
             pidx += self.internal_pnum;
             for (key, loc, hash) in node {
-                self.insert(key, loc, hash);
+                self.insert(key, loc, hash)?;
             }
         }
+        Ok(())
     }
 
-    pub fn get_hashes(&self) -> Vec<Option<HashValue>> {
+    /// 所有分区的根哈希，有分区存在未commit的修改时返回[`EsmtError::Uncommitted`]
+    pub fn get_hashes(&self) -> Result<Vec<Option<HashValue>>, EsmtError> {
         let mut res = Vec::with_capacity(self.partions.len());
         for p in self.partions.iter() {
-            res.push(p.root_hash());
+            res.push(p.root_hash()?);
         }
-        res
+        Ok(res)
+    }
+
+    /// 全局Merkle树的叶子，每个分区对应一个叶子，顺序与`partions`一致，最后一个叶子为key索引的根
+    fn global_leaves(&self) -> Result<Vec<HashValue>, EsmtError> {
+        let mut leaves = self.partions.iter()
+            .zip(self.areas.iter())
+            .enumerate()
+            .map(|(idx, (p, area))| Ok(partition_leaf::<V, H, D>(idx, area, p.root_hash()?)))
+            .collect::<Result<Vec<_>, EsmtError>>()?;
+        leaves.push(self.key_root()?);
+        Ok(leaves)
+    }
+
+    /// 全局根哈希，承诺了每个分区的编号、范围和根哈希，以及key索引。
+    /// 有分区存在未commit的修改时返回[`EsmtError::Uncommitted`]
+    pub fn global_root(&self) -> Result<HashValue, EsmtError> {
+        Ok(merkle_root::<H>(self.global_leaves()?))
+    }
+
+    /// 生成第index个分区到全局根哈希的证明，index超出分区数量时为`None`
+    pub fn partition_proof(&self, index: usize) -> Result<Option<PartitionProof<V, D, H>>, EsmtError> {
+        if index >= self.partions.len() {
+            return Ok(None);
+        }
+        let leaves = self.global_leaves()?;
+        let count = leaves.len();
+        Ok(Some(PartitionProof::new(
+            index,
+            count,
+            self.areas[index].clone(),
+            self.partions[index].root_hash()?,
+            merkle_path::<H>(leaves, index),
+        )))
+    }
+
+    /// 由表中的位置和折线顶点还原key对应的对象，用于计算key索引中的对象摘要
+    fn key_object(&self, key: &str, value: HashValue) -> ObjectEntry<V, D, H> {
+        let loc = self.key_2_loc[key].clone();
+        let polyline = self.key_2_polyline.get(key).cloned();
+        ObjectEntry::from_parts(key.to_string(), loc, polyline, value)
+    }
+
+    /// 对象的位置或形状改变后，重新计算其在key索引中的摘要
+    fn refresh_key_index(&mut self, key: &str) {
+        if let Some(value) = self.key_index.value(key) {
+            let digest = self.key_object(key, value).hash();
+            self.key_index.update(key, value, digest);
+        }
+    }
+
+    /// key索引的根，承诺了所有key及其对象摘要。key索引有未commit的修改时返回[`EsmtError::Uncommitted`]
+    pub fn key_root(&self) -> Result<HashValue, EsmtError> {
+        if self.key_index.is_dirty() {
+            return Err(EsmtError::Uncommitted);
+        }
+        Ok(self.key_index.root())
+    }
+
+    /// 按key查找对象。key存在时返回对象所在分区中以对象位置为查询的VO，
+    /// 不存在时返回key索引中相邻key的证明
+    pub fn get(&self, key: &str) -> Result<KeyProof<V, D, H>, EsmtError> {
+        if let Some(loc) = self.key_2_loc.get(key) {
+            let idx = self.get_pindex_with_key(key)?;
+            let vo = self.partions[idx].range_query(loc)?;
+            let object = vo.iter()
+                .find_map(|ety| match ety {
+                    VerifyObjectEntry::Target(t) if t.match_key(key) => Some(t.clone()),
+                    _ => None,
+                })
+                .ok_or(EsmtError::CorruptedStructure("object is not at its recorded location"))?;
+            return Ok(KeyProof::Member {
+                object,
+                vo,
+                proof: self.partition_proof(idx)?.ok_or(EsmtError::CorruptedStructure("partition index out of range"))?,
+            });
+        }
+        let count = self.key_index.len();
+        // key在索引中应当插入的位置，两侧的叶子即为相邻的key
+        let pos = self.key_index.position(key).unwrap_or_else(|pos| pos);
+        let leaf = |index: usize| self.key_index.leaf(index)
+            .ok_or(EsmtError::CorruptedStructure("key index leaf is missing"));
+        let lower = pos.checked_sub(1).map(leaf).transpose()?;
+        let upper = (pos < count).then(|| leaf(pos)).transpose()?;
+        let global_leaves = self.global_leaves()?;
+        let global_count = global_leaves.len();
+        Ok(KeyProof::NonMember {
+            count,
+            lower,
+            upper,
+            global_count,
+            global_path: merkle_path::<H>(global_leaves, global_count - 1),
+        })
     }
 }
 
-struct PartitionPointerWrapper<V, const D: usize, const C: usize> 
+struct PartitionPointerWrapper<V, const D: usize, const C: usize, H> 
     where
         V: MRTreeDefault,
 {
-    ptr: * const PartionManager<V, D, C>,
+    ptr: * const PartionManager<V, D, C, H>,
 }
 
-impl<V, const D: usize, const C: usize> PartitionPointerWrapper<V, D, C> 
+impl<V, const D: usize, const C: usize, H: HashBackend> PartitionPointerWrapper<V, D, C, H> 
     where
         V: MRTreeDefault + MRTreeFunc + ToPrimitive + FromPrimitive,
 {
-    pub fn new(ptr: &PartionManager<V, D, C>) -> Self {
+    pub fn new(ptr: &PartionManager<V, D, C, H>) -> Self {
         Self { ptr }
     }
 
-    pub fn get(&self) -> &PartionManager<V, D, C> {
+    pub fn get(&self) -> &PartionManager<V, D, C, H> {
         unsafe {
             &(*self.ptr)
         }
     }
 }
 
-unsafe impl<V,const  D: usize, const C: usize> Send for PartitionPointerWrapper<V, D, C>
+unsafe impl<V,const  D: usize, const C: usize, H> Send for PartitionPointerWrapper<V, D, C, H>
     where
         V: MRTreeDefault,
 {}
 
-impl<V,const  D: usize, const C: usize> Clone for PartitionPointerWrapper<V, D, C>
+impl<V,const  D: usize, const C: usize, H: HashBackend> Clone for PartitionPointerWrapper<V, D, C, H>
     where
         V: MRTreeDefault,
 {
@@ -1095,17 +1905,24 @@ impl<V,const  D: usize, const C: usize> Clone for PartitionPointerWrapper<V, D,
     }
 } 
 
-impl<V,const  D: usize, const C: usize> Copy for PartitionPointerWrapper<V, D, C>
+impl<V,const  D: usize, const C: usize, H: HashBackend> Copy for PartitionPointerWrapper<V, D, C, H>
     where
         V: MRTreeDefault,
 {}
 #[cfg(test)]
 mod test {
-    use types::hash_value::HashValue;
-    use types::test_utils::{num_hash};
+    use std::cell::Cell;
+    use types::hash_value::{HashBackend, HashValue, Sha3_256};
+    use types::test_utils::{generate_points, num_hash};
     use crate::esmtree::PartionTree;
     use crate::esmtree::PartionManager;
-    use crate::shape::Rect;
+    use crate::error::EsmtError;
+    use crate::node::{ESMTEntry, KeyIndex, Node, ObjectEntry};
+    use crate::shape::{Polygon, QueryShape, Rect};
+    use crate::curve::Curve;
+    use types::hash_value::Keccak256;
+    use crate::split::{CurveSplit, RStarSplit};
+    use crate::verify::{KeyProof, PartitionVerifyObject, VerifyError, VerifyObjectEntry};
 
     #[derive(Debug)]
     enum Operator {
@@ -1114,6 +1931,27 @@ mod test {
         Update(usize),
         Merge,
     }
+
+    /// 在[0,100]x[0,100]上建立两层分区，依次插入n个随机点，第i个点的key为`key-{i}`
+    fn filled_manager(n: usize) -> (PartionManager<f64, 2, 4>, Vec<[f64; 2]>) {
+        let mut pm = PartionManager::new(Rect::new([0.0f64, 0.0], [100.0, 100.0]), 2);
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], n);
+        for (i, p) in points.iter().enumerate() {
+            pm.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        (pm, points)
+    }
+
+    /// 各分区VO中返回的目标对象
+    fn targets<const D: usize>(pvo: &PartitionVerifyObject<f64, D>) -> Vec<&ObjectEntry<f64, D>> {
+        pvo.touched()
+            .flat_map(|(_, _, vo)| vo.iter().filter_map(|e| match e {
+                VerifyObjectEntry::Target(t) => Some(t),
+                _ => None,
+            }))
+            .collect()
+    }
+
     #[test]
     fn test_root_hash() {
         // let points = generate_points([0usize, 0], [8usize, 8], 10);
@@ -1152,21 +1990,21 @@ mod test {
             Operator::Delete(1)
         ];
         let hash_str = vec![
-            "5b4d6fe0dd8fd7bc6de264d7c3db3ed25ae1306dbdf20843e91acaaf8b6728f5".to_string(), // i 0
-            "8186e82dd80cce7b15828191c85bf1f128bd6e1168f670361a65c9b14cd7b06d".to_string(), // i 1
-            "8d015b832a692a90b69409c6bdabcd122c05f198306dd481bf380c0a1d817e66".to_string(), // i 2
-            "0bd13fbae340f13bc8580b2d777c5393652a2e4fce220bb618b156b8cf97b90f".to_string(), // i 3
-            "7b5b68e400187a7c07f1af2043315dee22517f0919cfd1df1b21a319b0bb04e4".to_string(), // i 4
-            "902d1aaa9fdedf73a5cb2e289a941d7baed0db1263581e50e09643494c0b917d".to_string(), // i 5
-            "0af2fb57bcc0d167b87d4ac94cb22ae1e977d788225f6dc6cff8d2337a4fa572".to_string(), // i 6
-            "6fb829122401c6d92d7d860aa6f6329e2d8202d63259c27a6cd819f753ebad7d".to_string(), // i 7
-            "48e1e65014f7ea6e53398dc8718e7d10c98cc1f93e87f941d64336a548b9f65a".to_string(), // i 8
-            "2b3e36e150217da8d4fa8466dbbcbc8b4c2fc9822120d2d992639fada09dcc43".to_string(), // i 9
-            "9fbca63c043666257f91d43f329d3234f14582147d5b95250b2f095bed549712".to_string(), // u 5
-            "9fbca63c043666257f91d43f329d3234f14582147d5b95250b2f095bed549712".to_string(), // u 4
-            "8865cad780eedf83b2db2f6425cb5f6592729dee25da1dce2c8820823e32db7a".to_string(), // u 1
-            "200394f45e6ff302874ec2d8ee59c2272e701d6957172eba5b08d999ecfb6d08".to_string(), // u 0
-            "96112008a00abf1ef6c7ea6f0409eb477ff251dc09bb2ade7409aa85080690dc".to_string(), // m
+            "0ed9291d5cf6958a777ee421eed33e600ed287b6264f6175f6257e79b1dcaafa".to_string(), // i 0
+            "67ba77f98b89db6bfd0d1a48b312fb823b1329f8016a613e0c1bb5483f790803".to_string(), // i 1
+            "3ae0dcf3da25e18d5d995ba05b012c93289399e1022e7f2458c3b0eb4d3170c4".to_string(), // i 2
+            "3c9e64b2d89fe13000bdf7d75de78771950bb5605cbf395b74eeb2ed6fdee1ef".to_string(), // i 3
+            "562367d68bd51a3ee91f2cba1006add39f623672c7ec90cfaa4672732d767d50".to_string(), // i 4
+            "ec87cf6503873c1f4d22bd900580b5d9d224ad800af732a2c5ed956e7906232c".to_string(), // i 5
+            "709188c33d4f4b08aaa765a31275ac4d4453a5f40235ea6de0417e038f388cd6".to_string(), // i 6
+            "54ea4ad9ed7258e83822f60d801671a7d31e80110a5310dce8a6178aa017297d".to_string(), // i 7
+            "a7202d4eb1d663426e53ab6ddc6819529a2d994ea954e3159b52cdb1183d25d3".to_string(), // i 8
+            "921493e7ba056533922928c481c3aea37c9703a0d9eca828171e0300402ce420".to_string(), // i 9
+            "fde451688fe7e174d70a7e4b888651360f459acdb1e07e3dde085460175bc01e".to_string(), // u 5
+            "cb8389710aca6461e8efe92cb2126a56e200fd84cd42edd485148f4f000a7fc1".to_string(), // u 4
+            "6fa1200ea20ae33a7a758e25d255aa2b0fc972ffe03239a3adb5cdfd08ebefdc".to_string(), // u 1
+            "3b51b1e1717c30831a597c110f23a630224079614b88f9bf089f9af11bc7417a".to_string(), // u 0
+            "a7a7c480f9795e6f8ddeb54070080157c6d0b00731efcb31d69af6d1e44c172e".to_string(), // m
         ];
         let root_hashes = hash_str.into_iter()
             .map(|s| HashValue::from_slice(&hex::decode(s).unwrap()).unwrap())
@@ -1175,19 +2013,19 @@ mod test {
         for (op,hash) in ops.into_iter().zip(root_hashes.into_iter()) {
             match op {
                 Operator::Insert(i) => {
-                    tree.insert(format!("testkey-{}",i), points[i].clone(), num_hash(i as i32));
+                    tree.insert(format!("testkey-{}",i), points[i].clone(), num_hash(i as i32)).unwrap();
                 }
                 Operator::Delete(i) => {
-                    tree.delete(&format!("testkey-{}",i), &points[i]);
+                    tree.delete(&format!("testkey-{}",i), &points[i]).unwrap();
                 }
                 Operator::Update(i) => {
-                    tree.update(&format!("testkey-{}",i),&points[i], new_points[i].clone());
+                    tree.update(&format!("testkey-{}",i),&points[i], new_points[i].clone()).unwrap();
                 }
                 Operator::Merge => {
-                    tree.merge_empty();
+                    tree.merge_empty().unwrap();
                 }
             }
-            assert_eq!(tree.root_hash().unwrap(), hash);
+            assert_eq!(tree.root_hash().unwrap().unwrap(), hash);
             println!("{:?} passed", op);
         }
     }
@@ -1197,6 +2035,893 @@ mod test {
 
     }
 
+    #[test]
+    fn test_empty_query() {
+        let points = generate_points([0.0f64, 0.0], [100.0f64, 100.0], 200);
+        let mut tree = PartionTree::<f64, 2, 4>::new();
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("testkey-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash().unwrap().unwrap();
+        // 区域外的查询，结果为空
+        let query = Rect::new([200.0f64, 200.0], [300.0f64, 300.0]);
+        let vo = tree.range_query(&query).unwrap();
+        assert!(vo.verify(&query, root_hash).is_ok());
+        // 篡改根哈希
+        assert!(vo.verify(&query, num_hash(-1)).is_err());
+        for p in generate_points([0.0f64, 0.0], [90.0f64, 90.0], 20) {
+            let query = Rect::new(p, [p[0] + 10.0, p[1] + 10.0]);
+            let vo = tree.range_query(&query).unwrap();
+            assert!(vo.verify(&query, root_hash).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_global_root() {
+        let empty_root = filled_manager(0).0.global_root().unwrap();
+        let (mut pm, _) = filled_manager(60);
+        let global_root = pm.global_root().unwrap();
+        assert_ne!(empty_root, global_root);
+        // 每个分区（包括空分区）都能关联到全局根哈希
+        for idx in 0..pm.get_hashes().unwrap().len() {
+            let proof = pm.partition_proof(idx).unwrap().unwrap();
+            assert!(proof.verify_root(global_root).is_ok());
+            assert!(proof.verify_root(empty_root).is_err());
+        }
+        assert!(pm.partition_proof(pm.get_hashes().unwrap().len()).unwrap().is_none());
+
+        let query = Rect::new([20.0f64, 20.0], [60.0, 45.0]);
+        let idx = pm.point_index(&[30.0, 30.0]);
+        let proof = pm.partition_proof(idx).unwrap().unwrap();
+        let vo = pm.partions[idx].range_query(&query).unwrap();
+        assert!(proof.verify(&vo, &query, global_root).is_ok());
+        // 用其他分区的证明验证该VO失败
+        let other = pm.partition_proof(pm.point_index(&[90.0, 90.0])).unwrap().unwrap();
+        assert!(other.verify(&vo, &query, global_root).is_err());
+
+        pm.update("key-0", [50.0, 50.0]).unwrap();
+        assert_ne!(pm.global_root().unwrap(), global_root);
+    }
+
+    #[test]
+    fn test_query_with_proof() {
+        let area = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let mut pm: PartionManager<f64, 2, 4> = PartionManager::new(area, 2);
+        // 只在左下角插入数据，其余分区为空
+        let points = generate_points([0.0f64, 0.0], [45.0, 45.0], 80);
+        for (i, p) in points.iter().enumerate() {
+            pm.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let global_root = pm.global_root().unwrap();
+        let query = Rect::new([10.0f64, 10.0], [70.0, 30.0]);
+        let pvo = pm.range_query_with_proof(&query).unwrap();
+        assert_eq!(pvo.len(), pm.get_hashes().unwrap().len());
+        assert!(pvo.verify(&query, global_root).is_ok());
+        let expected = points.iter().filter(|p| query.contains(&Rect::new_point(**p))).count();
+        assert_eq!(targets(&pvo).len(), expected);
+        assert!(pvo.verify(&query, num_hash(-1)).is_err());
+
+        let edge = Rect::new([0.0f64, 0.0], [50.0, 50.0]);
+        let evo = pm.edge_query_with_proof(&edge).unwrap();
+        assert!(evo.verify(&edge, global_root).is_ok());
+
+        // 丢弃一个相交分区的VO，验证失败
+        let mut partial = PartitionVerifyObject::new(pm.key_root().unwrap());
+        let mut dropped = None;
+        for (idx, (area, root)) in pm.areas.iter().zip(pm.get_hashes().unwrap()).enumerate() {
+            let vo = pm.partions[idx].range_query(&query).ok().filter(|_| area.intersects(&query));
+            if vo.is_some() && dropped.is_none() {
+                dropped = Some(idx);
+                partial.push(area.clone(), root, None);
+            } else {
+                partial.push(area.clone(), root, vo);
+            }
+        }
+        let partition = dropped.unwrap();
+        assert_eq!(partial.verify(&query, global_root), Err(VerifyError::MissingPartitionVo { partition }));
+    }
+
+    #[test]
+    fn test_three_dimensional() {
+        // 三维数据（x, y, 时间）同样可以批量建树、分裂和合并
+        let area = Rect::new([0.0f64, 0.0, 0.0], [100.0, 100.0, 1000.0]);
+        let mut pm: PartionManager<f64, 3, 4> = PartionManager::new(area.clone(), 1);
+        let points = generate_points([0.0f64, 0.0, 0.0], [100.0, 100.0, 1000.0], 150);
+        let (batch, rest) = points.split_at(100);
+        pm.batch_insert(batch.iter().enumerate()
+            .map(|(i, p)| (format!("key-{}", i), *p, num_hash(i as i32)))
+            .collect()).unwrap();
+        for (i, p) in rest.iter().enumerate() {
+            pm.insert(format!("key-{}", i + 100), *p, num_hash((i + 100) as i32)).unwrap();
+        }
+        for i in 0..75 {
+            pm.delete(&format!("key-{}", i)).unwrap();
+        }
+        let global_root = pm.global_root().unwrap();
+        let query = Rect::new([20.0f64, 10.0, 100.0], [80.0, 60.0, 700.0]);
+        let pvo = pm.range_query_with_proof(&query).unwrap();
+        assert!(pvo.verify(&query, global_root).is_ok());
+        let expected = points.iter().skip(75).filter(|p| query.contains(&Rect::new_point(**p))).count();
+        assert_eq!(targets(&pvo).len(), expected);
+    }
+
+    #[test]
+    fn test_split_policy() {
+        let area = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let mut pm: PartionManager<f64, 2, 4> = PartionManager::with_split_policy(area, 1, RStarSplit::default());
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 150);
+        let moved = generate_points([0.0f64, 0.0], [100.0, 100.0], 30);
+        for (i, p) in points.iter().enumerate() {
+            pm.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        for (i, p) in moved.iter().enumerate() {
+            pm.update(&format!("key-{}", i), *p).unwrap();
+        }
+        for i in 30..60 {
+            pm.delete(&format!("key-{}", i)).unwrap();
+        }
+        let global_root = pm.global_root().unwrap();
+        let query = Rect::new([10.0f64, 20.0], [70.0, 90.0]);
+        let pvo = pm.range_query_with_proof(&query).unwrap();
+        assert!(pvo.verify(&query, global_root).is_ok());
+        let expected = moved.iter().chain(points.iter().skip(60))
+            .filter(|p| query.contains(&Rect::new_point(**p)))
+            .count();
+        assert_eq!(targets(&pvo).len(), expected);
+    }
+
+    #[test]
+    fn test_space_filling_curves() {
+        for curve in [Curve::ZOrder, Curve::Peano] {
+            let area = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+            let mut pm: PartionManager<f64, 2, 4> = PartionManager::with_split_policy(area, 1, CurveSplit(curve));
+            pm.set_curve(curve);
+            assert!(pm.partions.iter().all(|p| p.curve() == curve));
+            let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 150);
+            let (batch, rest) = points.split_at(100);
+            pm.batch_insert(batch.iter().enumerate()
+                .map(|(i, p)| (format!("key-{}", i), *p, num_hash(i as i32)))
+                .collect()).unwrap();
+            for (i, p) in rest.iter().enumerate() {
+                pm.insert(format!("key-{}", i + 100), *p, num_hash((i + 100) as i32)).unwrap();
+            }
+            for i in 0..75 {
+                pm.delete(&format!("key-{}", i)).unwrap();
+            }
+            let global_root = pm.global_root().unwrap();
+            let query = Rect::new([20.0f64, 10.0], [80.0, 60.0]);
+            let pvo = pm.range_query_with_proof(&query).unwrap();
+            assert!(pvo.verify(&query, global_root).is_ok());
+            let expected = points.iter().skip(75).filter(|p| query.contains(&Rect::new_point(**p))).count();
+            assert_eq!(targets(&pvo).len(), expected);
+        }
+    }
+
+    #[test]
+    fn test_hash_backend() {
+        let (sha3, points) = filled_manager(60);
+        let mut pm = PartionManager::<f64, 2, 4, Keccak256>::new(Rect::new([0.0f64, 0.0], [100.0, 100.0]), 2);
+        for (i, p) in points.iter().enumerate() {
+            pm.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let global_root = pm.global_root().unwrap();
+        let query = Rect::new([10.0f64, 20.0], [70.0, 90.0]);
+        assert!(pm.range_query_with_proof(&query).unwrap().verify(&query, global_root).is_ok());
+        assert!(pm.get("key-7").unwrap().verify("key-7", global_root).is_ok());
+        assert!(pm.get("key-x").unwrap().verify("key-x", global_root).is_ok());
+        // 相同数据在默认后端下的全局根哈希不同
+        assert_ne!(sha3.global_root().unwrap(), global_root);
+    }
+
+    #[test]
+    fn test_polygon_query() {
+        let (pm, points) = filled_manager(100);
+        let global_root = pm.global_root().unwrap();
+        // 跨越多个分区的三角形围栏，只有与围栏相交的分区给出VO
+        let fence = Polygon::new(vec![[5.0f64, 5.0], [95.0, 10.0], [10.0, 90.0]]);
+        let expected = points.iter().filter(|p| fence.contains_point(p)).count();
+        let pvo = pm.range_query_with_proof(&fence).unwrap();
+        assert!(pvo.verify(&fence, global_root).is_ok());
+        assert_eq!(targets(&pvo).len(), expected);
+        assert!(pvo.touched().all(|(_, area, _)| fence.intersects(area)));
+        assert!(pvo.verify(fence.mbr(), global_root).is_err());
+        assert_eq!(pm.count_query(&fence).unwrap().verify_count(&fence, global_root), Ok(expected as u64));
+    }
+
+    #[test]
+    fn test_circle_query() {
+        let (pm, points) = filled_manager(100);
+        let global_root = pm.global_root().unwrap();
+        let center = [40.0f64, 60.0];
+        let radius = 25.0;
+        let pvo = pm.circle_query(&center, radius).unwrap();
+        assert!(pvo.verify_circle(&center, radius, global_root).is_ok());
+        let expected = points.iter()
+            .filter(|p| Rect::new_point(**p).rect_dist(&Rect::new_point(center)) <= radius * radius)
+            .count();
+        assert_eq!(targets(&pvo).len(), expected);
+        // 扩大半径后，被剪枝的兄弟节点或未给出VO的分区与圆相交
+        assert!(pvo.verify_circle(&center, 80.0, global_root).is_err());
+        assert!(pvo.verify_circle(&center, radius, num_hash(-1)).is_err());
+    }
+
+    #[test]
+    fn test_extent_objects() {
+        let (mut pm, points) = filled_manager(60);
+        // 跨越根分区中心的矩形留在根分区，只跨越第二层中心的矩形进入第一层分区
+        assert_eq!(pm.rect_index(&Rect::new([40.0, 40.0], [60.0, 45.0])), 0);
+        assert_eq!(pm.rect_index(&Rect::new([20.0, 10.0], [30.0, 20.0])), 1);
+        assert_eq!(pm.rect_index(&Rect::new([10.0, 10.0], [20.0, 20.0])), pm.point_index(&[10.0, 10.0]));
+        assert_eq!(pm.rect_index(&Rect::new_point([70.0, 80.0])), pm.point_index(&[70.0, 80.0]));
+        let rects = [
+            Rect::new([40.0f64, 40.0], [60.0, 45.0]),
+            Rect::new([20.0, 10.0], [30.0, 20.0]),
+            Rect::new([70.0, 70.0], [72.0, 71.0]),
+        ];
+        for (i, r) in rects.iter().enumerate() {
+            pm.insert_object(ObjectEntry::with_rect(format!("rect-{}", i), r.clone(), num_hash(1000 + i as i32))).unwrap();
+        }
+        let road = vec![[5.0f64, 90.0], [45.0, 60.0], [55.0, 62.0], [95.0, 95.0]];
+        pm.insert_object(ObjectEntry::with_polyline("road".to_string(), road.clone(), num_hash(2000)).unwrap()).unwrap();
+        assert!(pm.partions[0].contains("road"));
+        assert!(pm.partions[0].contains("rect-0"));
+        assert!(pm.partions[1].contains("rect-1"));
+        let global_root = pm.global_root().unwrap();
+
+        // 按key查找折线对象，精确形状随对象返回
+        let proof = pm.get("road").unwrap();
+        assert_eq!(proof.object().unwrap().polyline().unwrap(), &road[..]);
+        assert!(proof.verify("road", global_root).is_ok());
+
+        // 只返回被查询完全包含的对象，部分相交的对象作为兄弟节点
+        let query = Rect::new([0.0f64, 0.0], [65.0, 65.0]);
+        let pvo = pm.range_query_with_proof(&query).unwrap();
+        assert!(pvo.verify(&query, global_root).is_ok());
+        let keys = targets(&pvo).iter().map(|t| t.key()).collect::<Vec<_>>();
+        assert!(keys.contains(&"rect-0".to_string()) && keys.contains(&"rect-1".to_string()));
+        assert!(!keys.contains(&"road".to_string()) && !keys.contains(&"rect-2".to_string()));
+        let expected = points.iter().filter(|p| query.contains(&Rect::new_point(**p))).count() + 2;
+        assert_eq!(keys.len(), expected);
+        assert_eq!(pm.count_query(&query).unwrap().verify_count(&query, global_root), Ok(expected as u64));
+
+        // 移动和删除有范围的对象，折线对象不能只移动位置
+        pm.update_rect("rect-0", Rect::new([80.0, 80.0], [90.0, 90.0])).unwrap();
+        assert!(!pm.partions[0].contains("rect-0"));
+        assert_eq!(pm.update("road", [10.0, 10.0]), Err(EsmtError::PolylineMove("road".to_string())));
+        assert_eq!(pm.get("road").unwrap().object().unwrap().polyline().unwrap(), &road[..]);
+        let moved = road.iter().map(|p| [p[0], p[1] - 5.0]).collect::<Vec<_>>();
+        pm.upsert_object(ObjectEntry::with_polyline("road".to_string(), moved.clone(), num_hash(2001)).unwrap()).unwrap();
+        assert_eq!(pm.get("road").unwrap().object().unwrap().polyline().unwrap(), &moved[..]);
+        assert!(pm.delete("road").is_ok());
+        assert!(pm.delete("rect-1").is_ok());
+        let global_root = pm.global_root().unwrap();
+        assert!(pm.get("road").unwrap().verify("road", global_root).is_ok());
+        assert!(pm.get("road").unwrap().object().is_none());
+        let proof = pm.get("rect-0").unwrap();
+        assert_eq!(proof.object().unwrap().loc()._min, [80.0, 80.0]);
+        assert!(proof.verify("rect-0", global_root).is_ok());
+        let pvo = pm.range_query_with_proof(&query).unwrap();
+        assert!(pvo.verify(&query, global_root).is_ok());
+    }
+
+    #[test]
+    fn test_count_query() {
+        let (mut pm, points) = filled_manager(80);
+        let queries = [
+            Rect::new([10.0f64, 10.0], [70.0, 30.0]),
+            Rect::new([0.0f64, 0.0], [100.0, 100.0]),
+            Rect::new([200.0f64, 200.0], [300.0, 300.0]),
+        ];
+        let count = |points: &[[f64; 2]], deleted: &[usize], query: &Rect<f64, 2>| {
+            points.iter().enumerate()
+                .filter(|(i, p)| !deleted.contains(i) && query.contains(&Rect::new_point(**p)))
+                .count() as u64
+        };
+        let global_root = pm.global_root().unwrap();
+        for query in queries.iter() {
+            let pvo = pm.count_query(query).unwrap();
+            assert_eq!(pvo.verify_count(query, global_root), Ok(count(&points, &[], query)));
+            assert!(pvo.verify_count(query, num_hash(-1)).is_err());
+        }
+        // 完全包含整个区域时，分区根哈希不承诺根节点自身的mbr和数量，所以每个分区的VO只展开根节点这一层：
+        // LevelBegin、至多C个带数量的兄弟节点和LevelEnd，与对象数量无关；范围查询的VO至少包含每个对象
+        let full = &queries[1];
+        let count_pvo = pm.count_query(full).unwrap();
+        let touched = count_pvo.touched().count();
+        let count_size = count_pvo.touched().map(|(_, _, vo)| vo.iter().count()).sum::<usize>();
+        assert!(count_pvo.touched().all(|(_, _, vo)| vo.iter().all(|e| !matches!(e, VerifyObjectEntry::Target(_)))));
+        assert!(count_size <= touched * (4 + 2));
+        let range_size = pm.range_query_with_proof(full).unwrap().touched().map(|(_, _, vo)| vo.iter().count()).sum::<usize>();
+        assert!(range_size >= points.len() + 2 * touched);
+
+        // 删除部分对象后，数量和范围查询都与剩余对象一致
+        let deleted = (0..80).step_by(3).collect::<Vec<_>>();
+        for i in deleted.iter() {
+            assert!(pm.delete(&format!("key-{}", i)).is_ok());
+        }
+        let global_root = pm.global_root().unwrap();
+        for query in queries.iter() {
+            let pvo = pm.count_query(query).unwrap();
+            assert_eq!(pvo.verify_count(query, global_root), Ok(count(&points, &deleted, query)));
+            let pvo = pm.range_query_with_proof(query).unwrap();
+            assert!(pvo.verify(query, global_root).is_ok());
+            assert_eq!(targets(&pvo).len() as u64, count(&points, &deleted, query));
+        }
+    }
+
+    #[test]
+    fn test_knn_query() {
+        let (pm, points) = filled_manager(100);
+        let global_root = pm.global_root().unwrap();
+        for q in [[10.0f64, 10.0], [50.0, 50.0], [99.0, 1.0]] {
+            let query = Rect::new_point(q);
+            let mut expected = points.iter().map(|p| Rect::new_point(*p).rect_dist(&query)).collect::<Vec<_>>();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for k in [1usize, 10, 50] {
+                let pvo = pm.knn_query(&q, k).unwrap();
+                assert!(pvo.verify_knn(&q, k, global_root).is_ok());
+                let mut dists = targets(&pvo).iter().map(|t| t.loc().rect_dist(&query)).collect::<Vec<_>>();
+                dists.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                assert_eq!(dists, expected[..k].to_vec());
+                assert!(pvo.verify_knn(&q, k + 1, global_root).is_err());
+                // 跨分区的kNN不能只用部分分区的VO
+                if pvo.touched().count() > 1 {
+                    let mut partial = PartitionVerifyObject::new(pm.key_root().unwrap());
+                    let first = pvo.touched().next().unwrap().0;
+                    for (idx, (area, root)) in pm.areas.iter().zip(pm.get_hashes().unwrap()).enumerate() {
+                        let vo = pvo.touched().find(|(i, _, _)| *i == idx && idx != first).map(|(_, _, vo)| vo.clone());
+                        partial.push(area.clone(), root, vo);
+                    }
+                    assert!(partial.verify_knn(&q, k, global_root).is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        // 空的key索引也能证明key不存在
+        let (pm, _) = filled_manager(0);
+        let proof = pm.get("key-0").unwrap();
+        assert!(proof.object().is_none());
+        assert!(proof.verify("key-0", pm.global_root().unwrap()).is_ok());
+
+        let (mut pm, points) = filled_manager(60);
+        let global_root = pm.global_root().unwrap();
+        for i in [0usize, 17, 59] {
+            let key = format!("key-{}", i);
+            let proof = pm.get(&key).unwrap();
+            let obj = proof.object().unwrap();
+            assert_eq!(obj.value(), num_hash(i as i32));
+            assert_eq!(obj.loc()._min, points[i]);
+            assert!(proof.verify(&key, global_root).is_ok());
+            assert!(proof.verify(&format!("key-{}", (i + 1) % 60), global_root).is_err());
+            assert!(proof.verify(&key, num_hash(-1)).is_err());
+        }
+        // 小于所有key、位于两个key之间、大于所有key
+        for key in ["a", "key-17a", "key-x"] {
+            let proof = pm.get(key).unwrap();
+            assert!(proof.object().is_none());
+            assert!(proof.verify(key, global_root).is_ok());
+            assert!(proof.verify("key-17", global_root).is_err());
+        }
+        // 不相邻的两个叶子不能证明二者之间的key不存在
+        let neighbours = |key: &str| match pm.get(key).unwrap() {
+            KeyProof::NonMember { count, lower, upper, global_count, global_path } => (count, lower, upper, global_count, global_path),
+            KeyProof::Member { .. } => unreachable!(),
+        };
+        let (count, lower, _, global_count, global_path) = neighbours("key-10a");
+        let (_, _, upper, ..) = neighbours("key-20a");
+        let forged: KeyProof<f64, 2> = KeyProof::NonMember { count, lower, upper, global_count, global_path };
+        assert!(matches!(forged.verify("key-15", global_root), Err(VerifyError::NeighborsNotAdjacent { .. })));
+        // 删除后可以证明key不存在，更新位置后证明新的位置
+        pm.delete("key-17").unwrap();
+        pm.update("key-40", [1.0, 1.0]).unwrap();
+        let global_root = pm.global_root().unwrap();
+        assert!(pm.get("key-17").unwrap().verify("key-17", global_root).is_ok());
+        let proof = pm.get("key-40").unwrap();
+        assert_eq!(proof.object().unwrap().loc()._min, [1.0, 1.0]);
+        assert!(proof.verify("key-40", global_root).is_ok());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut tree = PartionTree::<f64, 2, 4>::new();
+        assert_eq!(tree.range_query(&Rect::new([0.0, 0.0], [1.0, 1.0])).err(), Some(EsmtError::EmptyTree));
+        assert!(tree.merge_empty().is_ok());
+        tree.insert("key-0".to_string(), [1.0, 1.0], num_hash(0)).unwrap();
+        assert_eq!(tree.insert("key-0".to_string(), [2.0, 2.0], num_hash(1)), Err(EsmtError::DuplicateKey("key-0".to_string())));
+        assert_eq!(tree.update("key-1", &[1.0, 1.0], [2.0, 2.0]), Err(EsmtError::KeyNotFound("key-1".to_string())));
+        assert_eq!(tree.len(), 1);
+
+        let area = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let mut pm: PartionManager<f64, 2, 4> = PartionManager::new(area, 2);
+        assert!(pm.range_query_with_proof(&Rect::new([0.0, 0.0], [100.0, 100.0])).is_ok());
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 100);
+        for (i, p) in points.iter().enumerate() {
+            pm.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let global_root = pm.global_root().unwrap();
+        // 失败的操作不改变任何分区和key索引
+        assert_eq!(pm.insert("key-3".to_string(), [1.0, 1.0], num_hash(-1)), Err(EsmtError::DuplicateKey("key-3".to_string())));
+        assert_eq!(pm.insert("key-x".to_string(), [101.0, 1.0], num_hash(-1)), Err(EsmtError::OutOfArea("key-x".to_string())));
+        assert_eq!(pm.delete("key-x").err(), Some(EsmtError::KeyNotFound("key-x".to_string())));
+        assert_eq!(pm.update("key-x", [1.0, 1.0]), Err(EsmtError::KeyNotFound("key-x".to_string())));
+        assert_eq!(pm.update("key-3", [1.0, -1.0]), Err(EsmtError::OutOfArea("key-3".to_string())));
+        let batch = vec![
+            ("key-y".to_string(), [1.0, 1.0], num_hash(-1)),
+            ("key-y".to_string(), [2.0, 2.0], num_hash(-2)),
+        ];
+        assert_eq!(pm.batch_insert(batch), Err(EsmtError::DuplicateKey("key-y".to_string())));
+        let batch = vec![
+            ("key-y".to_string(), [1.0, 1.0], num_hash(-1)),
+            ("key-z".to_string(), [200.0, 2.0], num_hash(-2)),
+        ];
+        assert_eq!(pm.batch_iter_insert(batch), Err(EsmtError::OutOfArea("key-z".to_string())));
+        assert_eq!(pm.global_root().unwrap(), global_root);
+        assert!(pm.get("key-y").unwrap().object().is_none());
+
+        let query = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let pvo = pm.range_query_with_proof(&query).unwrap();
+        assert!(pvo.verify(&query, global_root).is_ok());
+        pm.update("key-3", [99.0, 99.0]).unwrap();
+        assert_eq!(pm.delete("key-3").unwrap().value(), num_hash(3));
+        assert_eq!(pm.delete("key-3").err(), Some(EsmtError::KeyNotFound("key-3".to_string())));
+    }
+
+    #[test]
+    fn test_upsert() {
+        let (mut pm, points) = filled_manager(40);
+        let full = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let found = |pm: &PartionManager<f64, 2, 4>| {
+            let pvo = pm.range_query_with_proof(&full).unwrap();
+            assert!(pvo.verify(&full, pm.global_root().unwrap()).is_ok());
+            targets(&pvo).iter().map(|t| (t.key(), t.loc()._min, t.value())).collect::<Vec<_>>()
+        };
+
+        // 重复插入失败，树中只有一个对象
+        assert_eq!(pm.insert("key-0".to_string(), [1.0, 1.0], num_hash(-1)), Err(EsmtError::DuplicateKey("key-0".to_string())));
+        // upsert移动并替换对象，包括移动到其他分区
+        pm.upsert("key-0".to_string(), [99.0, 99.0], num_hash(-1)).unwrap();
+        pm.upsert("key-1".to_string(), points[1], num_hash(-2)).unwrap();
+        pm.upsert("key-new".to_string(), [50.0, 50.0], num_hash(-3)).unwrap();
+        assert_eq!(pm.upsert("key-2".to_string(), [101.0, 1.0], num_hash(-4)), Err(EsmtError::OutOfArea("key-2".to_string())));
+        assert_eq!(pm.len(), 41);
+        let res = found(&pm);
+        assert_eq!(res.len(), 41);
+        assert_eq!(res.iter().filter(|(k, _, _)| k == "key-0").collect::<Vec<_>>(), vec![&("key-0".to_string(), [99.0, 99.0], num_hash(-1))]);
+        assert_eq!(res.iter().filter(|(k, _, _)| k == "key-1").collect::<Vec<_>>(), vec![&("key-1".to_string(), points[1], num_hash(-2))]);
+        assert_eq!(res.iter().filter(|(k, _, _)| k == "key-2").count(), 1);
+
+        // 替换值不改变位置，key索引同步更新
+        pm.replace_value("key-3", num_hash(-5)).unwrap();
+        assert_eq!(pm.replace_value("key-x", num_hash(-5)), Err(EsmtError::KeyNotFound("key-x".to_string())));
+        let proof = pm.get("key-3").unwrap();
+        assert_eq!(proof.object().unwrap().value(), num_hash(-5));
+        assert_eq!(proof.object().unwrap().loc()._min, points[3]);
+        assert!(proof.verify("key-3", pm.global_root().unwrap()).is_ok());
+
+        // 原位置上被替换的过期对象不影响之后的删除
+        pm.upsert("key-1".to_string(), points[1], num_hash(-6)).unwrap();
+        pm.delete("key-1").unwrap();
+        assert!(!pm.contains("key-1"));
+        assert!(found(&pm).iter().all(|(k, _, _)| k != "key-1"));
+        assert_eq!(pm.partions.iter().map(|p| p.len()).sum::<usize>(), pm.len());
+    }
+
+    #[test]
+    fn test_update_hash() {
+        // 原地替换值哈希后的树与直接插入新值的树相同
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 60);
+        let build = |changed: usize| {
+            let mut tree = PartionTree::<f64, 2, 4>::new();
+            let mut pm: PartionManager<f64, 2, 4> = PartionManager::new(Rect::new([0.0, 0.0], [100.0, 100.0]), 2);
+            for (i, p) in points.iter().enumerate() {
+                let hash = if i == changed { num_hash(-1) } else { num_hash(i as i32) };
+                tree.insert(format!("key-{}", i), *p, hash).unwrap();
+                pm.insert(format!("key-{}", i), *p, hash).unwrap();
+            }
+            (tree, pm)
+        };
+        let (mut tree, mut pm) = build(usize::MAX);
+        tree.update_hash("key-42", &points[42], num_hash(-1)).unwrap();
+        pm.update_hash("key-42", num_hash(-1)).unwrap();
+        let (expected_tree, expected_pm) = build(42);
+        assert_eq!(tree.root_hash(), expected_tree.root_hash());
+        assert_eq!(pm.get_hashes().unwrap(), expected_pm.get_hashes().unwrap());
+        assert_eq!(pm.global_root().unwrap(), expected_pm.global_root().unwrap());
+        assert_eq!(tree.update_hash("key-x", &points[42], num_hash(-1)), Err(EsmtError::KeyNotFound("key-x".to_string())));
+        assert_eq!(pm.update_hash("key-x", num_hash(-1)), Err(EsmtError::KeyNotFound("key-x".to_string())));
+
+        let proof = pm.get("key-42").unwrap();
+        assert_eq!(proof.object().unwrap().value(), num_hash(-1));
+        assert!(proof.verify("key-42", pm.global_root().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_batch_delete() {
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 80);
+        let build = || {
+            let mut tree = PartionTree::<f64, 2, 4>::new();
+            let mut pm: PartionManager<f64, 2, 4> = PartionManager::new(Rect::new([0.0, 0.0], [100.0, 100.0]), 2);
+            for (i, p) in points.iter().enumerate() {
+                tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+                pm.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+            }
+            (tree, pm)
+        };
+        let deleted = (0..80).step_by(4).collect::<Vec<_>>();
+        let (mut tree, mut pm) = build();
+        let (mut expected_tree, mut expected_pm) = build();
+        for i in deleted.iter() {
+            expected_tree.delete(&format!("key-{}", i), &points[*i]).unwrap();
+            expected_pm.delete(&format!("key-{}", i)).unwrap();
+        }
+
+        // 批量删除与逐个删除得到相同的哈希
+        let items = deleted.iter().map(|i| (format!("key-{}", i), Rect::new_point(points[*i]))).collect::<Vec<_>>();
+        let removed = tree.batch_delete(&items).unwrap();
+        assert!(removed.iter().zip(deleted.iter()).all(|(obj, i)| obj.value() == num_hash(*i as i32)));
+        assert_eq!(tree.root_hash(), expected_tree.root_hash());
+        assert_eq!(tree.len(), 60);
+        let keys = deleted.iter().map(|i| format!("key-{}", i)).collect::<Vec<_>>();
+        assert_eq!(pm.batch_delete(&keys).unwrap().len(), deleted.len());
+        assert_eq!(pm.get_hashes().unwrap(), expected_pm.get_hashes().unwrap());
+        assert_eq!(pm.global_root().unwrap(), expected_pm.global_root().unwrap());
+        assert_eq!(pm.len(), 60);
+
+        // key不存在或重复时不做任何修改
+        let global_root = pm.global_root().unwrap();
+        assert_eq!(pm.batch_delete(&["key-1".to_string(), "key-0".to_string()]).err(), Some(EsmtError::KeyNotFound("key-0".to_string())));
+        assert_eq!(pm.batch_delete(&["key-1".to_string(), "key-1".to_string()]).err(), Some(EsmtError::DuplicateKey("key-1".to_string())));
+        assert_eq!(tree.batch_delete(&items[..1]).err(), Some(EsmtError::KeyNotFound("key-0".to_string())));
+        assert_eq!(pm.global_root().unwrap(), global_root);
+        assert!(pm.contains("key-1"));
+
+        // 有对象不在给定的位置上时，其余对象也不会被删除
+        let root_hash = tree.root_hash();
+        let wrong = [
+            ("key-1".to_string(), Rect::new_point(points[1])),
+            ("key-2".to_string(), Rect::new_point([-1.0, -1.0])),
+        ];
+        assert_eq!(tree.batch_delete(&wrong).err(), Some(EsmtError::KeyNotFound("key-2".to_string())));
+        assert_eq!(tree.root_hash(), root_hash);
+        assert_eq!(tree.len(), 60);
+        assert!(tree.contains("key-1"));
+    }
+
+    /// 按相反的顺序重新插入所有key得到的key索引的根，用于检查增量维护的结果与修改顺序无关
+    fn rebuilt_key_root(pm: &PartionManager<f64, 2, 4>) -> HashValue {
+        let mut keys = pm.key_2_loc.keys().collect::<Vec<_>>();
+        keys.sort();
+        let mut index = KeyIndex::<Sha3_256>::new();
+        for key in keys.into_iter().rev() {
+            let value = pm.key_index.value(key).unwrap();
+            index.insert(key.clone(), value, pm.key_object(key, value).hash());
+        }
+        index.commit();
+        assert_eq!(index.len(), pm.key_index.len());
+        index.root()
+    }
+
+    thread_local! {
+        static DIGESTS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// 统计当前线程中计算摘要次数的SHA3-256
+    #[derive(Clone)]
+    struct CountingSha3(Sha3_256);
+
+    impl HashBackend for CountingSha3 {
+        const OUTPUT_LEN: usize = 32;
+
+        fn new() -> Self {
+            Self(Sha3_256::new())
+        }
+
+        fn update(&mut self, bytes: &[u8]) {
+            self.0.update(bytes);
+        }
+
+        fn finish(self, out: &mut [u8]) {
+            DIGESTS.with(|d| d.set(d.get() + 1));
+            self.0.finish(out);
+        }
+
+        fn salt_digest() -> HashValue {
+            Sha3_256::salt_digest()
+        }
+    }
+
+    fn digests() -> usize {
+        DIGESTS.with(|d| d.get())
+    }
+
+    #[test]
+    fn test_key_index_hashes_per_insert() {
+        let n = 512;
+        let mut index = KeyIndex::<CountingSha3>::new();
+        for i in 0..n {
+            index.insert(format!("key-{}", i), num_hash(i), HashValue::zero());
+            index.commit();
+        }
+        // 每次插入只计算优先级、新叶子、路径上的节点和key索引的根
+        let log_n = (usize::BITS - (n as usize).leading_zeros()) as usize;
+        let mut total = 0;
+        for i in n..n + 64 {
+            let start = digests();
+            index.insert(format!("key-{}", i), num_hash(i), HashValue::zero());
+            index.commit();
+            let cost = digests() - start;
+            assert!(cost <= 4 * log_n, "insert {} computed {} digests", i, cost);
+            total += cost;
+        }
+        assert!(total <= 64 * 2 * log_n, "{} digests for 64 inserts", total);
+        // 修改和删除同样只计算路径上的节点
+        let start = digests();
+        index.update("key-7", num_hash(-7), HashValue::zero());
+        index.commit();
+        assert!(digests() - start <= 4 * log_n);
+        let start = digests();
+        index.remove("key-8");
+        index.commit();
+        assert!(digests() - start <= 4 * log_n);
+    }
+
+    #[test]
+    fn test_key_index_incremental() {
+        let area = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let mut pm: PartionManager<f64, 2, 4> = PartionManager::new(area, 2);
+        assert_eq!(pm.key_root(), Ok(rebuilt_key_root(&pm)));
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 60);
+        let batch = points.iter().enumerate()
+            .filter(|(i, _)| i % 2 == 0)
+            .map(|(i, p)| (format!("key-{}", i), *p, num_hash(i as i32)))
+            .collect::<Vec<_>>();
+        pm.batch_insert(batch).unwrap();
+        assert_eq!(pm.key_root(), Ok(rebuilt_key_root(&pm)));
+        for (i, p) in points.iter().enumerate().filter(|(i, _)| i % 2 == 1) {
+            pm.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        assert_eq!(pm.key_root(), Ok(rebuilt_key_root(&pm)));
+        pm.delete("key-0").unwrap();
+        pm.delete("key-59").unwrap();
+        pm.update("key-5", [1.0, 1.0]).unwrap();
+        pm.update_hash("key-7", num_hash(-7)).unwrap();
+        pm.upsert("key-8".to_string(), [2.0, 2.0], num_hash(-8)).unwrap();
+        pm.upsert("new-key".to_string(), [3.0, 3.0], num_hash(-9)).unwrap();
+        assert_eq!(pm.key_root(), Ok(rebuilt_key_root(&pm)));
+        let keys = (10..30).map(|i| format!("key-{}", i)).collect::<Vec<_>>();
+        pm.batch_delete(&keys).unwrap();
+        assert_eq!(pm.key_root(), Ok(rebuilt_key_root(&pm)));
+        let items = (30..50).map(|i| (format!("key-{}", i), [points[i][0] * 0.5, points[i][1]])).collect::<Vec<_>>();
+        pm.batch_update(&items).unwrap();
+        assert_eq!(pm.key_root(), Ok(rebuilt_key_root(&pm)));
+        let all = pm.key_2_loc.keys().cloned().collect::<Vec<_>>();
+        pm.batch_delete(&all).unwrap();
+        assert_eq!(pm.key_root(), Ok(rebuilt_key_root(&pm)));
+    }
+
+    #[test]
+    fn test_batch_insert_objects() {
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 40);
+        let objs = |range: std::ops::Range<usize>| range
+            .map(|i| ObjectEntry::new(format!("key-{}", i), points[i], num_hash(i as i32)))
+            .collect::<Vec<_>>();
+        let mut batch = PartionTree::<f64, 2, 4>::new();
+        batch.batch_insert_objects(objs(0..20)).unwrap();
+        batch.batch_insert_objects(objs(20..40)).unwrap();
+        assert_eq!(batch.len(), 40);
+        assert!(!batch.is_dirty());
+        let area = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let vo = batch.range_query(&area).unwrap();
+        assert!(vo.verify(&area, batch.root_hash().unwrap().unwrap()).is_ok());
+        assert_eq!(vo.iter().filter(|e| matches!(e, VerifyObjectEntry::Target(_))).count(), 40);
+        // key已经存在或在批量中重复时不做任何修改
+        let root_hash = batch.root_hash();
+        assert_eq!(batch.batch_insert_objects(objs(39..40)).err(), Some(EsmtError::DuplicateKey("key-39".to_string())));
+        let dup = vec![
+            ObjectEntry::new("new".to_string(), [1.0, 1.0], num_hash(-1)),
+            ObjectEntry::new("new".to_string(), [2.0, 2.0], num_hash(-2)),
+        ];
+        assert_eq!(batch.batch_insert_objects(dup).err(), Some(EsmtError::DuplicateKey("new".to_string())));
+        assert_eq!(batch.root_hash(), root_hash);
+        assert_eq!(batch.len(), 40);
+    }
+
+    #[test]
+    fn test_batch_update() {
+        let area = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let (mut pm, mut points) = filled_manager(90);
+        let mut tree = PartionTree::<f64, 2, 4>::new();
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        // 一部分对象在原位置附近移动，一部分移动到其他分区
+        let moved = generate_points([0.0f64, 0.0], [100.0, 100.0], 30);
+        let mut items = vec![];
+        let mut tree_items = vec![];
+        for (j, i) in (0..90).step_by(3).enumerate() {
+            let nloc = if j % 2 == 0 { [points[i][0] * 0.999, points[i][1] * 0.999] } else { moved[j] };
+            items.push((format!("key-{}", i), nloc));
+            tree_items.push((format!("key-{}", i), Rect::new_point(points[i]), Rect::new_point(nloc)));
+            points[i] = nloc;
+        }
+        pm.batch_update(&items).unwrap();
+        tree.batch_update(&tree_items).unwrap();
+        assert_eq!(pm.len(), 90);
+        assert_eq!(tree.len(), 90);
+        assert_eq!(pm.partions.iter().map(|p| p.len()).sum::<usize>(), 90);
+
+        // 查询结果中每个对象只出现一次且位于新的位置
+        let global_root = pm.global_root().unwrap();
+        let pvo = pm.range_query_with_proof(&area).unwrap();
+        assert!(pvo.verify(&area, global_root).is_ok());
+        let mut found = targets(&pvo).iter().map(|t| (t.key(), t.loc()._min)).collect::<Vec<_>>();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected = points.iter().enumerate().map(|(i, p)| (format!("key-{}", i), *p)).collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(found, expected);
+        let vo = tree.range_query(&area).unwrap();
+        assert!(vo.verify(&area, tree.root_hash().unwrap().unwrap()).is_ok());
+        assert_eq!(vo.iter().filter(|e| matches!(e, VerifyObjectEntry::Target(_))).count(), 90);
+        let proof = pm.get("key-3").unwrap();
+        assert_eq!(proof.object().unwrap().loc()._min, points[3]);
+        assert!(proof.verify("key-3", global_root).is_ok());
+
+        // 移动后的对象可以在新位置删除
+        tree.delete("key-3", &points[3]).unwrap();
+        pm.delete("key-3").unwrap();
+
+        // key不存在、重复或新位置超出区域时不做任何修改
+        let global_root = pm.global_root().unwrap();
+        assert_eq!(pm.batch_update(&[("key-1".to_string(), [1.0, 1.0]), ("key-3".to_string(), [1.0, 1.0])]), Err(EsmtError::KeyNotFound("key-3".to_string())));
+        assert_eq!(pm.batch_update(&[("key-1".to_string(), [1.0, 1.0]), ("key-1".to_string(), [2.0, 2.0])]), Err(EsmtError::DuplicateKey("key-1".to_string())));
+        assert_eq!(pm.batch_update(&[("key-1".to_string(), [1.0, 1.0]), ("key-2".to_string(), [200.0, 2.0])]), Err(EsmtError::OutOfArea("key-2".to_string())));
+        assert_eq!(pm.global_root().unwrap(), global_root);
+        // 折线对象不能只移动位置
+        pm.insert_object(ObjectEntry::with_polyline("road".to_string(), vec![[1.0, 1.0], [9.0, 3.0]], num_hash(-1)).unwrap()).unwrap();
+        let global_root = pm.global_root().unwrap();
+        assert_eq!(pm.batch_update(&[("key-1".to_string(), [1.0, 1.0]), ("road".to_string(), [2.0, 2.0])]), Err(EsmtError::PolylineMove("road".to_string())));
+        assert_eq!(pm.global_root().unwrap(), global_root);
+        // 有对象不在给定的原位置上时，其余对象也不会被移动
+        let root_hash = tree.root_hash();
+        let wrong = [
+            ("key-1".to_string(), Rect::new_point(points[1]), Rect::new_point([1.0, 1.0])),
+            ("key-2".to_string(), Rect::new_point([-1.0, -1.0]), Rect::new_point([2.0, 2.0])),
+        ];
+        assert_eq!(tree.batch_update(&wrong), Err(EsmtError::KeyNotFound("key-2".to_string())));
+        assert_eq!(tree.root_hash(), root_hash);
+        assert_eq!(tree.range_query(&Rect::new_point(points[1])).unwrap().iter()
+            .filter(|e| matches!(e, VerifyObjectEntry::Target(t) if t.match_key("key-1"))).count(), 1);
+    }
+
+    #[test]
+    fn test_deferred_commit() {
+        let area = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 120);
+        let moved = generate_points([0.0f64, 0.0], [100.0, 100.0], 20);
+        let mut eager: PartionManager<f64, 2, 4> = PartionManager::new(area.clone(), 2);
+        let mut deferred: PartionManager<f64, 2, 4> = PartionManager::new(area.clone(), 2);
+        let mut tree = PartionTree::<f64, 2, 4>::new();
+        let mut eager_tree = PartionTree::<f64, 2, 4>::new();
+        deferred.set_deferred(true);
+        tree.set_deferred(true);
+        // 同一批修改在两种模式下得到相同的哈希
+        let apply = |pm: &mut PartionManager<f64, 2, 4>, tree: &mut PartionTree<f64, 2, 4>| {
+            for (i, p) in points.iter().enumerate() {
+                pm.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+                tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+            }
+            for i in (0..120).step_by(7) {
+                pm.delete(&format!("key-{}", i)).unwrap();
+                tree.delete(&format!("key-{}", i), &points[i]).unwrap();
+            }
+            for (j, i) in (1..120).step_by(8).filter(|i| i % 7 != 0).enumerate() {
+                pm.update(&format!("key-{}", i), moved[j]).unwrap();
+                tree.update(&format!("key-{}", i), &points[i], moved[j]).unwrap();
+            }
+            pm.update_hash("key-2", num_hash(-1)).unwrap();
+            tree.update_hash("key-2", &points[2], num_hash(-1)).unwrap();
+        };
+        apply(&mut eager, &mut eager_tree);
+        apply(&mut deferred, &mut tree);
+        assert!(!eager.is_dirty() && !eager_tree.is_dirty());
+        assert!(deferred.is_dirty() && tree.is_dirty());
+
+        // commit之前不能读取根哈希，也不能生成VO
+        assert_eq!(tree.root_hash(), Err(EsmtError::Uncommitted));
+        assert_eq!(deferred.get_hashes(), Err(EsmtError::Uncommitted));
+        assert_eq!(deferred.global_root(), Err(EsmtError::Uncommitted));
+        assert_eq!(deferred.partition_proof(0).err(), Some(EsmtError::Uncommitted));
+        assert_eq!(tree.range_query(&area).err(), Some(EsmtError::Uncommitted));
+        assert_eq!(deferred.range_query_with_proof(&area).err(), Some(EsmtError::Uncommitted));
+        assert_eq!(deferred.knn_query(&[50.0, 50.0], 3).err(), Some(EsmtError::Uncommitted));
+        assert_eq!(deferred.key_root(), Err(EsmtError::Uncommitted));
+        assert!(deferred.get("key-1").is_err());
+
+        tree.commit();
+        deferred.commit();
+        assert!(!deferred.is_dirty() && !tree.is_dirty());
+        assert_eq!(tree.root_hash(), eager_tree.root_hash());
+        assert_eq!(deferred.get_hashes().unwrap(), eager.get_hashes().unwrap());
+        let global_root = deferred.global_root().unwrap();
+        assert_eq!(global_root, eager.global_root().unwrap());
+        assert!(deferred.range_query_with_proof(&area).unwrap().verify(&area, global_root).is_ok());
+        let vo = tree.range_query(&area).unwrap();
+        assert!(vo.verify(&area, tree.root_hash().unwrap().unwrap()).is_ok());
+
+        // 关闭延迟模式时立即commit
+        tree.delete("key-3", &points[3]).unwrap();
+        assert!(tree.is_dirty());
+        tree.set_deferred(false);
+        assert!(!tree.is_dirty());
+        eager_tree.delete("key-3", &points[3]).unwrap();
+        assert_eq!(tree.root_hash(), eager_tree.root_hash());
+    }
+
+    /// 子树中dirty节点的数量
+    fn dirty_nodes<H: HashBackend>(node: &Node<f64, 2, 4, H>) -> usize {
+        if !node.dirty {
+            return 0;
+        }
+        let children = node.entry.iter()
+            .map(|e| match e {
+                ESMTEntry::ENode(n) => dirty_nodes(n),
+                ESMTEntry::Object(_) => 0,
+            })
+            .sum::<usize>();
+        children + 1
+    }
+
+    /// commit时每个dirty节点和key索引中被标记的节点只计算一次哈希
+    fn commit_once(pm: &mut PartionManager<f64, 2, 4, CountingSha3>) {
+        let pending = pm.partions.iter()
+            .filter_map(|p| p.root.as_ref())
+            .map(|r| dirty_nodes(&r.node))
+            .sum::<usize>() + pm.key_index.pending();
+        assert!(pending > 0);
+        let start = digests();
+        pm.commit();
+        assert_eq!(digests() - start, pending);
+        let start = digests();
+        pm.commit();
+        assert_eq!(digests(), start);
+    }
+
+    #[test]
+    fn test_deferred_digests() {
+        let area = Rect::new([0.0f64, 0.0], [100.0, 100.0]);
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 80);
+        let mut pm: PartionManager<f64, 2, 4, CountingSha3> = PartionManager::new(area.clone(), 2);
+        pm.set_deferred(true);
+        // 延迟模式下插入只计算对象摘要和key的优先级，节点分裂时也不计算哈希
+        for (i, p) in points.iter().enumerate() {
+            let start = digests();
+            pm.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+            assert_eq!(digests() - start, 2);
+        }
+        commit_once(&mut pm);
+        // 删除不计算摘要，移动和修改值哈希只计算分区和key索引中的对象摘要
+        for i in (0..80).step_by(7) {
+            let start = digests();
+            pm.delete(&format!("key-{}", i)).unwrap();
+            assert_eq!(digests(), start);
+        }
+        for i in (1..80).step_by(5).filter(|i| i % 7 != 0) {
+            let start = digests();
+            pm.update(&format!("key-{}", i), points[79 - i]).unwrap();
+            assert_eq!(digests() - start, 2);
+        }
+        let start = digests();
+        pm.update_hash("key-2", num_hash(-2)).unwrap();
+        assert_eq!(digests() - start, 2);
+        commit_once(&mut pm);
+        let global_root = pm.global_root().unwrap();
+        assert!(pm.range_query_with_proof(&area).unwrap().verify(&area, global_root).is_ok());
+        assert!(pm.get("key-7").unwrap().verify("key-7", global_root).is_ok());
+    }
+
     #[test]
     fn test_level_info() {
         let pm: PartionManager<f32, 2, 3> = PartionManager::new(Rect::new([1.0f32, 3.0f32], [14.0f32, 8.0f32]), 1);
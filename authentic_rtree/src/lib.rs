@@ -2,6 +2,10 @@ extern crate core;
 
 pub mod shape;
 pub mod node;
+pub mod curve;
+pub mod split;
 pub mod mrtree;
 pub mod esmtree;
-pub mod verify;
\ No newline at end of file
+pub mod verify;
+pub mod codec;
+pub mod error;
\ No newline at end of file
@@ -1,9 +1,12 @@
 use std::cell::RefCell;
-use std::sync::Mutex;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 
-use types::hash_value::{HashValue};
-use crate::node::{ESMTEntry, FromPrimitive, MRTreeDefault, MRTreeFunc, Node, ObjectEntry, ToPrimitive};
-use crate::shape::Rect;
+use types::hash_value::{HashBackend, HashValue, Sha3_256};
+use crate::error::EsmtError;
+use crate::node::{ESMTEntry, FromPrimitive, KnnBound, MRTreeDefault, MRTreeFunc, Node, ObjectEntry, ToPrimitive, knn_dists};
+use crate::shape::{Circle, QueryShape, Rect};
+use crate::split::{HilbertSplit, Overflow, SplitPolicy};
 use crate::verify::{VerifyObject, VerifyObjectEntry, SiblingObject};
 use once_cell::sync::Lazy;
 
@@ -35,18 +38,18 @@ pub static NODE_SPLIT: Lazy<Mutex<MetricsCnt>> = Lazy::new(|| {
     Mutex::new(MetricsCnt::new())
 });
 
-struct MerkleRTreeNode<V, const D: usize, const C: usize>
+struct MerkleRTreeNode<V, const D: usize, const C: usize, H>
     where
         V: MRTreeDefault,
 {
-    node: Node<V, D, C>,
+    node: Node<V, D, C, H>,
 }
 
-impl<V, const D: usize, const C: usize> MerkleRTreeNode<V, D, C>
+impl<V, const D: usize, const C: usize, H: HashBackend> MerkleRTreeNode<V, D, C, H>
     where
         V: MRTreeDefault + MRTreeFunc + ToPrimitive + FromPrimitive,
 {
-    pub fn new(node: Node<V, D, C>) -> Self {
+    pub fn new(node: Node<V, D, C, H>) -> Self {
         Self {
             node
         }
@@ -58,8 +61,13 @@ impl<V, const D: usize, const C: usize> MerkleRTreeNode<V, D, C>
         }
     }
 
-    /// 插入，重新计算当前层的mbr以及下一层的hash
-    fn insert_by_mrt(node: &mut Node<V, D, C>, obj: ESMTEntry<V, D, C>, loc: &Rect<V, D>, height: u32) {
+    /// 插入，重新计算当前层的mbr并标记下一层的节点，哈希在操作结束时计算
+    fn insert_by_mrt(node: &mut Node<V, D, C, H>,
+                     obj: ESMTEntry<V, D, C, H>,
+                     loc: &Rect<V, D>,
+                     height: u32,
+                     overflow: &mut Overflow<V, D, C, H>,
+    ) -> Result<(), EsmtError> {
         (*NODE_TRAVERSE).lock().unwrap().add();
         if height == 0 {
             if node.entry.is_empty() {
@@ -71,48 +79,57 @@ impl<V, const D: usize, const C: usize> MerkleRTreeNode<V, D, C>
             }
         } else {
             let subtree_idx = node.choose_subtree(&loc);
-            let node_mut = node.entry[subtree_idx].get_node_mut();
+            let node_mut = node.entry[subtree_idx].get_node_mut()?;
             // node_mut.insert_by_mrt(obj, loc, height - 1);
-            Self::insert_by_mrt(node_mut, obj, loc, height - 1);
+            Self::insert_by_mrt(node_mut, obj, loc, height - 1, overflow)?;
             // need to split
             if node_mut.entry.len() > Node::<V, D, C>::CAPACITY {
-                (*NODE_SPLIT).lock().unwrap().add();
-                // 分裂并重新计算mbr
-                let new_node = node_mut.split_by_hilbert_sort();
-                node.mbr.expand(new_node.mbr());
-                node.mbr.expand(node_mut.mbr());
-                node.entry.push(ESMTEntry::ENode(new_node));
+                // 分裂并重新计算mbr；强制重插时节点已经重新计算了状态
+                if let Some(new_node) = overflow.treat(node_mut) {
+                    (*NODE_SPLIT).lock().unwrap().add();
+                    node.mbr.expand(new_node.mbr());
+                    node.mbr.expand(node_mut.mbr());
+                    node.entry.push(ESMTEntry::ENode(new_node));
+                }
+            } else {
+                node_mut.mark_dirty();
+            }
+            if overflow.has_pending() {
+                // 条目被移出后路径上的mbr可能缩小
+                node.recalculate_mbr();
             } else {
-                node_mut.rehash();
+                node.mbr.expand(&loc);
             }
-            node.mbr.expand(&loc);
         }
+        Ok(())
     }
 
-    pub fn insert(&mut self, obj: ESMTEntry<V, D, C>, loc: &Rect<V, D>, height: u32) {
-        Self::insert_by_mrt(&mut self.node, obj, loc, height);
+    pub fn insert(&mut self, obj: ESMTEntry<V, D, C, H>, loc: &Rect<V, D>, height: u32, overflow: &mut Overflow<V, D, C, H>) -> Result<(), EsmtError> {
+        Self::insert_by_mrt(&mut self.node, obj, loc, height, overflow)
     }
 
-    /// 删除时会重新计算每一层的mbr以及hash；是否发生下溢由上一层进行判断
-    fn delete_by_mrt(node: &mut Node<V, D, C>,
+    /// 删除时会重新计算每一层的mbr并标记路径上的节点；是否发生下溢由上一层进行判断
+    fn delete_by_mrt(node: &mut Node<V, D, C, H>,
                      rect: &Rect<V, D>,
                      key: &str,
-                     reinsert: &mut Vec<ESMTEntry<V, D, C>>,
+                     reinsert: &mut Vec<ESMTEntry<V, D, C, H>>,
                      height: u32,
-    ) -> (Option<ESMTEntry<V, D, C>>, bool) {
+    ) -> Result<(Option<ESMTEntry<V, D, C, H>>, bool), EsmtError> {
         if height == 0 {
             for i in 0..node.entry.len() {
-                if node.entry[i].get_object().match_key(key) {
+                let obj = node.entry[i].get_object()?;
+                // key相同但位置不同的对象不删除，避免按旧位置删除已经移动的对象
+                if obj.match_key(key) && obj.loc()._min == rect._min && obj.loc()._max == rect._max {
                     let to_delete = node.entry.swap_remove(i);
                     let recalced = node.mbr.on_edge(to_delete.mbr());
                     if recalced {
                         node.recalculate_mbr();
                     }
-                    node.rehash();
-                    return (
+                    node.mark_dirty();
+                    return Ok((
                         Some(to_delete),
                         recalced
-                    );
+                    ));
                 }
             }
         } else {
@@ -120,91 +137,104 @@ impl<V, const D: usize, const C: usize> MerkleRTreeNode<V, D, C>
                 if !rect.intersects(node.entry[i].mbr()) {
                     continue;
                 }
-                let child = node.entry[i].get_node_mut();
+                let child = node.entry[i].get_node_mut()?;
                 // let (removed, mut recalced) = node.delete_by_mrt(rect, key, reinsert, height - 1);
                 let (removed, mut recalced) =
-                    Self::delete_by_mrt(child, rect, key, reinsert, height - 1);
+                    Self::delete_by_mrt(child, rect, key, reinsert, height - 1)?;
                 if removed.is_none() {
                     continue;
                 }
                 if child.entry.len() < Node::<V, D, C>::MIN_FANOUT {
                     reinsert.extend(child.entry.drain(..));
                     let underflow_node = node.entry.swap_remove(i);
-                    recalced = node.mbr.on_edge(underflow_node.mbr());
+                    recalced |= node.mbr.on_edge(underflow_node.mbr());
                 }
                 if recalced {
                     node.recalculate_mbr();
                 }
-                node.rehash();
-                return (removed, recalced);
+                node.mark_dirty();
+                return Ok((removed, recalced));
             }
         }
-        (None, false)
+        Ok((None, false))
     }
 
     pub fn delete(&mut self,
                   rect: &Rect<V, D>,
                   key: &str,
-                  reinsert: &mut Vec<ESMTEntry<V, D, C>>,
+                  reinsert: &mut Vec<ESMTEntry<V, D, C, H>>,
                   height: u32,
-    ) -> (Option<ESMTEntry<V, D, C>>, bool) {
+    ) -> Result<(Option<ESMTEntry<V, D, C, H>>, bool), EsmtError> {
         Self::delete_by_mrt(&mut self.node, rect, key, reinsert, height)
     }
 
-    pub fn range_query(&self, query: &Rect<V, D>, height: u32) -> VerifyObject<V, D> {
-        Self::range_query_impl(&self.node, query, height)
+    /// 替换对象的值哈希时mbr不变，只重新计算找到对象的路径上的哈希
+    fn update_hash_by_mrt(node: &mut Node<V, D, C, H>,
+                          rect: &Rect<V, D>,
+                          key: &str,
+                          value: HashValue,
+                          height: u32,
+    ) -> Result<bool, EsmtError> {
+        let found = if height == 0 {
+            match node.entry.iter_mut().find(|e| matches!(e, ESMTEntry::Object(o) if o.match_key(key))) {
+                Some(ety) => {
+                    ety.get_object_mut()?.update_value(value);
+                    true
+                },
+                None => false,
+            }
+        } else {
+            let mut found = false;
+            for ety in node.entry.iter_mut() {
+                if rect.intersects(ety.mbr()) && Self::update_hash_by_mrt(ety.get_node_mut()?, rect, key, value, height - 1)? {
+                    found = true;
+                    break;
+                }
+            }
+            found
+        };
+        if found {
+            node.mark_dirty();
+        }
+        Ok(found)
+    }
+
+    pub fn update_hash(&mut self, rect: &Rect<V, D>, key: &str, value: HashValue, height: u32) -> Result<bool, EsmtError> {
+        Self::update_hash_by_mrt(&mut self.node, rect, key, value, height)
+    }
+
+    pub fn range_query<Q: QueryShape<V, D>>(&self, queries: &[Q], height: u32) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        Self::range_query_impl(&self.node, queries, height)
     }
 
-    fn range_query_impl(node: &Node<V, D, C>, query: &Rect<V, D>, height: u32) -> VerifyObject<V, D> {
+    /// 同时回答多个范围查询：与任一查询相交的节点都只展开一次，VO的大小取决于访问路径的并集
+    fn range_query_impl<Q: QueryShape<V, D>>(node: &Node<V, D, C, H>, queries: &[Q], height: u32) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        // 每个被访问的节点都完整地输出一层，查询结果为空时也能重新计算根哈希
         let mut vo = VerifyObject::new();
+        vo.push(VerifyObjectEntry::LevelBegin);
         if height == 0 {
-            let exist_vec = node.entry.iter()
-                .map(|ety| query.contains(ety.mbr()))
-                .collect::<Vec<_>>();
-            let exist_flag = exist_vec.iter().fold(false, |acc, e| acc || *e);
-            if exist_flag {
-                vo.push(VerifyObjectEntry::LevelBegin);
-                for i in 0..exist_vec.len() {
-                    if exist_vec[i] {
-                        vo.push(VerifyObjectEntry::Target(node.entry[i].get_object().clone()));
-                    } else {
-                        vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(node.entry[i].get_object())));
-                    }
+            for ety in node.entry.iter() {
+                if queries.iter().any(|q| q.contains(ety.mbr())) {
+                    vo.push(VerifyObjectEntry::Target(ety.get_object()?.clone()));
+                } else {
+                    vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(ety.get_object()?)));
                 }
-                vo.push(VerifyObjectEntry::LevlEnd);
             }
         } else {
-            let mut exist_vec = vec![];
-            let mut temp_vo = VerifyObject::new();
             for ety in node.entry.iter() {
-                if query.intersects(ety.mbr()) {
-                    let sub_vo = Self::range_query_impl(ety.get_node(), query, height - 1);
-                    if sub_vo.is_empty() {
-                        exist_vec.push(false);
-                    } else {
-                        exist_vec.push(true);
-                        temp_vo.extend(sub_vo);
-                    }
+                if queries.iter().any(|q| q.intersects(ety.mbr())) {
+                    vo.extend(Self::range_query_impl(ety.get_node()?, queries, height - 1)?);
                 } else {
-                    exist_vec.push(false);
+                    vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(ety.get_node()?)));
                 }
             }
-            if !temp_vo.is_empty() {
-                vo.push(VerifyObjectEntry::LevelBegin);
-                vo.extend(temp_vo);
-                for i in 0..exist_vec.len() {
-                    if !exist_vec[i] {
-                        vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(node.entry[i].get_node())));
-                    }
-                }
-                vo.push(VerifyObjectEntry::LevlEnd);
-            }
         }
-        vo
+        vo.push(VerifyObjectEntry::LevlEnd);
+        Ok(vo)
     }
 
     #[inline]
-    pub fn unpack_node(self) -> Node<V, D, C> {
+    pub fn unpack_node(self) -> Node<V, D, C, H> {
         self.node
     }
 }
@@ -288,24 +318,34 @@ impl<V, const D: usize, const C: usize> MerkleRTreeNode<V, D, C>
 //     }
 // }
 
-pub struct MerkleRTree<V, const D: usize, const C: usize>
+/// `H`为计算对象和节点摘要的哈希后端，默认为SHA3-256，参见[`HashBackend`]
+pub struct MerkleRTree<V, const D: usize, const C: usize, H = Sha3_256>
     where
         V: MRTreeDefault,
 {
-    root: Option<MerkleRTreeNode<V, D, C>>,
+    root: Option<MerkleRTreeNode<V, D, C, H>>,
     height: u32,
     len: usize,
+    keys: HashSet<String>,
+    split: Arc<dyn SplitPolicy<V, D>>,
 }
 
-impl<V, const D: usize, const C: usize> MerkleRTree<V, D, C>
+impl<V, const D: usize, const C: usize, H: HashBackend> MerkleRTree<V, D, C, H>
     where
         V: MRTreeDefault + MRTreeFunc + ToPrimitive + FromPrimitive,
 {
     pub fn new() -> Self {
+        Self::with_split_policy(HilbertSplit)
+    }
+
+    /// 使用指定的节点分裂策略，参见[`crate::split`]
+    pub fn with_split_policy<P: SplitPolicy<V, D> + 'static>(policy: P) -> Self {
         Self {
             root: None,
             height: 0,
             len: 0,
+            keys: HashSet::new(),
+            split: Arc::new(policy),
         }
     }
 
@@ -323,104 +363,177 @@ impl<V, const D: usize, const C: usize> MerkleRTree<V, D, C>
         }
     }
 
-    pub fn insert(&mut self, key: String, loc:[V; D], hash: HashValue) {
+    #[inline]
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    pub fn insert(&mut self, key: String, loc:[V; D], hash: HashValue) -> Result<(), EsmtError> {
+        self.insert_object(ObjectEntry::new(key, loc, hash))
+    }
+
+    /// 插入任意形状的空间对象，矩形和折线对象以其MBR参与索引。key已经存在时返回[`EsmtError::DuplicateKey`]
+    pub fn insert_object(&mut self, obj: ObjectEntry<V, D, H>) -> Result<(), EsmtError> {
+        let key = obj.key();
+        if self.keys.contains(&key) {
+            return Err(EsmtError::DuplicateKey(key));
+        }
         if self.root.is_none() {
             self.root = Some(MerkleRTreeNode::new_with_height(0));
         }
-        let obj = ESMTEntry::Object(ObjectEntry::new(key, loc, hash));
+        let obj = ESMTEntry::Object(obj);
         let obj_loc = obj.mbr().clone();
-        self.insert_impl(obj, &obj_loc, self.height);
+        self.insert_impl(obj, &obj_loc, self.height)?;
         self.len += 1;
+        self.keys.insert(key);
+        self.settle();
+        Ok(())
+    }
+
+    /// 每个修改操作结束时自底向上计算被标记节点的哈希，每个节点只计算一次
+    fn settle(&mut self) {
+        if let Some(root) = self.root.as_mut() {
+            root.node.commit();
+        }
+    }
+
+    fn insert_impl(&mut self, entry: ESMTEntry<V, D, C, H>, loc: &Rect<V, D>, height: u32) -> Result<(), EsmtError> {
+        let split = self.split.clone();
+        let mut overflow = Overflow::new(split.as_ref());
+        self.insert_entry(entry, loc, height, &mut overflow)?;
+        // 强制重插的条目，重插过程中可能再次溢出
+        while let Some(entry) = overflow.pop() {
+            let entry_loc = entry.mbr().clone();
+            let height = self.insert_height(&entry);
+            self.insert_entry(entry, &entry_loc, height, &mut overflow)?;
+        }
+        Ok(())
     }
 
-    fn insert_impl(&mut self, entry: ESMTEntry<V, D, C>, loc: &Rect<V, D>, height: u32) {
-        let root = self.root.as_mut().unwrap();
-        root.insert(entry, loc, height);
+    /// 条目应当插入的深度：对象插入叶子节点，子树插入到高度比它大1的节点中
+    fn insert_height(&self, entry: &ESMTEntry<V, D, C, H>) -> u32 {
+        match entry {
+            ESMTEntry::ENode(n) => self.height - n.height - 1,
+            ESMTEntry::Object(_) => self.height,
+        }
+    }
+
+    fn insert_entry(&mut self,
+                    entry: ESMTEntry<V, D, C, H>,
+                    loc: &Rect<V, D>,
+                    height: u32,
+                    overflow: &mut Overflow<V, D, C, H>,
+    ) -> Result<(), EsmtError> {
+        let root = self.root.as_mut().ok_or(EsmtError::EmptyTree)?;
+        root.insert(entry, loc, height, overflow)?;
         let need_split = root.node.is_overflow();
         if need_split {
             self.height += 1;
             let mut new_root = Node::new_with_height(self.height);
-            let mut origin = self.root.take().unwrap().unpack_node();
-            let another = origin.split_by_hilbert_sort();
+            let mut origin = self.root.take().ok_or(EsmtError::EmptyTree)?.unpack_node();
+            let another = origin.split_with(overflow.policy());
             new_root.entry.push(ESMTEntry::ENode(origin));
             new_root.entry.push(ESMTEntry::ENode(another));
             new_root.recalculate_state_after_sort();
             self.root = Some(MerkleRTreeNode::new(new_root));
         } else {
-            root.node.rehash()
+            root.node.mark_dirty()
         }
+        Ok(())
     }
 
-    pub fn delete(&mut self, key: &str, rect: &[V;D]) -> Option<ObjectEntry<V, D>> {
-        if let Some(root) = &mut self.root {
-            let oloc = Rect::new_point(rect.clone());
-            let mut reinsert = Vec::new();
-            let (removed, _) = root.delete(&oloc, key, &mut reinsert, self.height);
-            if removed.is_none() {
-                return None;
-            }
-            self.len -= 1;
-            if self.height == 0 {
-                if self.len == 0 {
-                    self.root = None;
-                }
-            } else {
-                if root.node.entry.len() == 1 {
-                    println!("root downcast. original height: {}", self.height);
-                    let new_root = root.node.entry.pop().unwrap().unpack_node();
-                    self.height = new_root.height;
-                    self.root = Some(MerkleRTreeNode::new(new_root));
-                }
-            }
-            // reinsert
-            if !reinsert.is_empty() {
-                // println!("need re-insert");
-                self.reinsert(reinsert);
+    pub fn delete(&mut self, key: &str, rect: &[V;D]) -> Result<ObjectEntry<V, D, H>, EsmtError> {
+        self.delete_rect(key, &Rect::new_point(*rect))
+    }
+
+    /// 删除位置（矩形和折线对象为其MBR）为loc的对象，对象不存在时返回[`EsmtError::KeyNotFound`]
+    pub fn delete_rect(&mut self, key: &str, loc: &Rect<V, D>) -> Result<ObjectEntry<V, D, H>, EsmtError> {
+        let root = self.root.as_mut().ok_or_else(|| EsmtError::KeyNotFound(key.to_string()))?;
+        let mut reinsert = Vec::new();
+        let (removed, _) = root.delete(loc, key, &mut reinsert, self.height)?;
+        let removed = removed.ok_or_else(|| EsmtError::KeyNotFound(key.to_string()))?;
+        self.len -= 1;
+        self.keys.remove(key);
+        if self.height == 0 {
+            if self.len == 0 {
+                self.root = None;
             }
-            removed.map(|entry| entry.unpack_object())
         } else {
-            None
+            if root.node.entry.len() == 1 {
+                println!("root downcast. original height: {}", self.height);
+                let new_root = root.node.entry.swap_remove(0).unpack_node()?;
+                self.height = new_root.height;
+                self.root = Some(MerkleRTreeNode::new(new_root));
+            }
+        }
+        // reinsert
+        if !reinsert.is_empty() {
+            // println!("need re-insert");
+            self.reinsert(reinsert)?;
         }
+        self.settle();
+        removed.unpack_object()
     }
 
-    pub fn update_loc(&mut self, key:&str, oloc: &[V; D], nloc: [V; D]) {
-        let old = self.delete(key, oloc);
-        if old.is_none() {
-            println!("obj {} not exist. loc: {:?}", key, oloc);
-        } else {
-            let mut obj = old.unwrap();
-            let new_loc = Rect::new_point(nloc);
-            obj.update_loc(new_loc.clone());
-            self.insert_impl(ESMTEntry::Object(obj), &new_loc, self.height);
+    /// 将位置为oloc的对象移动到nloc，对象不存在时返回[`EsmtError::KeyNotFound`]
+    pub fn update_loc(&mut self, key:&str, oloc: &[V; D], nloc: [V; D]) -> Result<(), EsmtError> {
+        let mut obj = self.delete(key, oloc)?;
+        obj.update_loc(Rect::new_point(nloc));
+        self.insert_object(obj)
+    }
+
+    pub fn update_hash(&mut self, key: &str, loc: &[V; D], value: HashValue) -> Result<(), EsmtError> {
+        self.update_hash_rect(key, &Rect::new_point(*loc), value)
+    }
+
+    /// 原地替换位置为loc的对象的值哈希，树的结构不变，只重新计算从叶子到根路径上的哈希。
+    /// 对象不存在时返回[`EsmtError::KeyNotFound`]
+    pub fn update_hash_rect(&mut self, key: &str, loc: &Rect<V, D>, value: HashValue) -> Result<(), EsmtError> {
+        let root = self.root.as_mut().ok_or_else(|| EsmtError::KeyNotFound(key.to_string()))?;
+        if !root.update_hash(loc, key, value, self.height)? {
+            return Err(EsmtError::KeyNotFound(key.to_string()));
         }
+        self.settle();
+        Ok(())
     }
 
-    fn reinsert(&mut self, reinsert_list: Vec<ESMTEntry<V, D, C>>) {
+    fn reinsert(&mut self, reinsert_list: Vec<ESMTEntry<V, D, C, H>>) -> Result<(), EsmtError> {
         for entry in reinsert_list.into_iter().rev() {
             // println!("start reinsert. Current height: {}", self.height);
             let entry_loc = entry.mbr().clone();
-            let expected_height_to_insert = if entry.is_node() {
-                // println!("re-insert node");
-                self.height - entry.get_node().height - 1
-            } else {
-                // println!("re-insert object");
-                self.height
-            };
-            self.insert_impl(entry, &entry_loc, expected_height_to_insert);
+            let expected_height_to_insert = self.insert_height(&entry);
+            self.insert_impl(entry, &entry_loc, expected_height_to_insert)?;
         }
+        Ok(())
     }
 
-    pub fn range_query(&self, query: &Rect<V, D>) -> Option<VerifyObject<V, D>> {
-        if self.root.is_none() {
-            return None;
-        }
-        let root = self.root.as_ref().unwrap();
-        let vo = root.range_query(query, self.height);
-        if vo.is_empty() {
-            None
-        } else {
-            Some(vo)
-        }
+    /// 范围查询，树为空时返回[`EsmtError::EmptyTree`]；查询结果为空时返回的VO同样可以验证根哈希。
+    /// 查询可以是矩形，也可以是[`Polygon`](crate::shape::Polygon)等任意[`QueryShape`]
+    pub fn range_query<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        let root = self.root.as_ref().ok_or(EsmtError::EmptyTree)?;
+        root.range_query(std::slice::from_ref(query), self.height)
+    }
+
+    /// 批量范围查询，所有查询共用一个VO，重复的上层节点和兄弟节点只出现一次。
+    /// 使用[`VerifyObject::verify_batch`]验证并按查询拆分结果，树为空时返回[`EsmtError::EmptyTree`]
+    pub fn batch_range_query(&self, queries: &[Rect<V, D>]) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        let root = self.root.as_ref().ok_or(EsmtError::EmptyTree)?;
+        root.range_query(queries, self.height)
+    }
+
+    /// 半径查询，返回与center距离不超过radius的对象的VO，树为空时返回[`EsmtError::EmptyTree`]。
+    /// 使用[`VerifyObject::verify_circle`]验证
+    pub fn circle_query(&self, center: &[V; D], radius: V) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        self.range_query(&Circle::new(*center, radius))
+    }
+
+    /// kNN查询，返回距离point最近的k个对象的VO，树为空时返回[`EsmtError::EmptyTree`]
+    pub fn knn_query(&self, point: &[V; D], k: usize) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        let root = self.root.as_ref().ok_or(EsmtError::EmptyTree)?;
+        let point = Rect::new_point(*point);
+        let dists = knn_dists([&root.node], &point, k);
+        let mut bound = KnnBound::new(&dists, k);
+        root.node.knn_query(&point, &mut bound)
     }
 
     pub fn display(&self) -> (Vec<(u32, Rect<V, D>)>, Vec<(bool, Rect<V, D>)>) {
@@ -449,14 +562,18 @@ impl<V, const D: usize, const C: usize> MerkleRTree<V, D, C>
 
 #[cfg(test)]
 mod test {
-    use std::collections::BTreeSet;
     use std::time::Instant;
-    use crate::shape::Rect;
+    use crate::shape::{Polygon, Rect};
     use rand::{thread_rng, Rng};
-    use types::hash_value::{HashValue};
-    use types::test_utils::{calc_hash, num_hash};
-    use crate::node::{ESMTEntry, HilbertSorter, Integer,ObjectEntry, UnsignedInteger};
+    use types::hash_value::{Blake3, HashBackend, HashValue, Keccak256, Sha3_256, leaf_hash};
+    use crate::error::EsmtError;
+    use types::test_utils::{generate_points, num_hash};
+    use crate::curve::HilbertSorter;
+    use crate::node::{ESMTEntry, Integer,ObjectEntry, UnsignedInteger, entry_record, merkle_parent, node_digest, partition_leaf};
     use crate::mrtree::MerkleRTree as Tree;
+    use crate::codec::WireFormat;
+    use crate::split::{HilbertSplit, LinearSplit, QuadraticSplit, RStarSplit, SplitPolicy};
+    use crate::verify::{SiblingObject, StreamVerifier, VerifyError, VerifyObject, VerifyObjectEntry};
 
     #[test]
     fn test_efficient() {
@@ -535,18 +652,19 @@ mod test {
             hashes.push(num_hash(i));
         }
         let mut root_hashes = vec![];
-        let mut node_set = BTreeSet::new();
-        // 插入0，1，2
+        let mut records = vec![];
+        // 插入0，1，2，根节点为叶子节点
         for i in 0..3usize {
-            node_set.insert(hashes[i]);
-            root_hashes.push(calc_hash(&node_set));
+            let obj: ObjectEntry<usize, 2> = ObjectEntry::new(format!("test-{}", i), points[i], hashes[i]);
+            records.push(entry_record(&obj.hash(), obj.loc(), None, 1));
+            root_hashes.push(node_digest::<Sha3_256>(records.clone()));
         }
         let h = vec![
-            "0bd13fbae340f13bc8580b2d777c5393652a2e4fce220bb618b156b8cf97b90f".to_string(),
-            "7b5b68e400187a7c07f1af2043315dee22517f0919cfd1df1b21a319b0bb04e4".to_string(),
-            "902d1aaa9fdedf73a5cb2e289a941d7baed0db1263581e50e09643494c0b917d".to_string(),
-            "106175f02bfa4344275457c2da1d9b4cc2d3016a4fd4fc73492a894bbaa2b8aa".to_string(),
-            "c9d49706741c3453968f696ff6324e21b7078fcf6171546fa8bad7ef32821593".to_string(),
+            "1b4dc949ab12874b9a2abb65024eb009e3151e6ae419f5537b9ffb0ccd7f8e25".to_string(),
+            "238392a86e91d125af2660595c0cd28d57d829e8296e24e48740ec2029f127e6".to_string(),
+            "d88a15d5f2a9e68c6c7d5c7ae4a2890ae19e88ae89b6330aea098c70ea0a8476".to_string(),
+            "632c44d3b8a18aca8e76bc506ec057060e849568c5e6fc52d866d3e4b8016216".to_string(),
+            "f5f73b731263e45eb4099c85c065620c23ecca26df57ea22d2fb9c985fc50fe1".to_string(),
         ];
         for s in h {
             let bytes = hex::decode(s).unwrap();
@@ -554,22 +672,22 @@ mod test {
         }
         let mut tree = Tree::<UnsignedInteger, 2, 3>::new();
         for (idx, (node_hash, expected_root_hash)) in hashes.into_iter().zip(root_hashes.into_iter()).enumerate() {
-            tree.insert(format!("test-{}", idx), points[idx].clone(), node_hash);
+            tree.insert(format!("test-{}", idx), points[idx].clone(), node_hash).unwrap();
             assert_eq!(expected_root_hash, tree.root_hash().unwrap());
             println!("test-{} pass", idx);
         }
         let delete_hash = vec![
-            "58296e1fbd0b2e93fde939693fa3d0252003bf97c80a46dabc50f0de1c894e33".to_string(),
-            "67a4b78b7ff4ec7ad62ce52e1bf3d8936d689733d3bab11d46fed4476ce94196".to_string(),
-            "98accee0abe3a5f21925ee48cd7b416b4fa0e4975770910c3b76080f4faa48d0".to_string(),
-            "e2b98de357e138652895953ae972d1ac997bc6524b3c17b3e064b8d048054a1a".to_string(),
-            "091f7d99a6262d675fb9e2e0d6a9fe5edfdb5bef5d67fa0f10aa2898f06809f1".to_string(),
-            "5c6e11d3d89adb9fc6753c15098fcd4b4818569979e057f51b5a3fd8beabd194".to_string(),
-            "2529b265927d4abf94dcc7381d2e436b200f2abba89fa04537164133df51ae16".to_string(),
+            "396fbd9b56cc7ece8cc965b0173723c8ca42fae8b54697a99049a9925528c18a".to_string(),
+            "3de4a8a3d4f0eac74368f52e876f9597bafae151f74dcf9704515a73f24faae1".to_string(),
+            "7e1497ef075014af601e7513f4214eb323c61b188bc8319ac134f501aed44cdd".to_string(),
+            "8094be1dfe836d4b95b6c1a567ce55ee373994d6ff33a30e6481e41d9daac4d2".to_string(),
+            "18f77547d05eb4b17ab684307529e930bdf651912bee983a302b53c33e31b209".to_string(),
+            "17572f87b1212d7b3029aaebbbce61ec48e6bcbaf5b3dcde09ee50d65d83fd62".to_string(),
+            "0b52345753a6f36cde1256bcd07771fd470a4a086ecd81357007345f2a2f2345".to_string(),
         ];
 
         for (i, expect_root_hash_str) in delete_hash.into_iter().enumerate() {
-            tree.delete(&format!("test-{}",i), &points[i].clone());
+            tree.delete(&format!("test-{}",i), &points[i].clone()).unwrap();
             let expected_hash = HashValue::from_slice(&hex::decode(expect_root_hash_str).unwrap()).unwrap();
             assert_eq!(expected_hash, tree.root_hash().unwrap());
             println!("test-del-{} pass", i);
@@ -591,36 +709,508 @@ mod test {
             [1, 1]
         ];
         let hash_str = vec![
-            "5b4d6fe0dd8fd7bc6de264d7c3db3ed25ae1306dbdf20843e91acaaf8b6728f5".to_string(), // i 0
-            "8186e82dd80cce7b15828191c85bf1f128bd6e1168f670361a65c9b14cd7b06d".to_string(), // i 1
-            "8d015b832a692a90b69409c6bdabcd122c05f198306dd481bf380c0a1d817e66".to_string(), // i 2
-            "0bd13fbae340f13bc8580b2d777c5393652a2e4fce220bb618b156b8cf97b90f".to_string(), // i 3
-            "7b5b68e400187a7c07f1af2043315dee22517f0919cfd1df1b21a319b0bb04e4".to_string(), // i 4
-            "902d1aaa9fdedf73a5cb2e289a941d7baed0db1263581e50e09643494c0b917d".to_string(), // i 5
-            "0af2fb57bcc0d167b87d4ac94cb22ae1e977d788225f6dc6cff8d2337a4fa572".to_string(), // i 6
-            "6fb829122401c6d92d7d860aa6f6329e2d8202d63259c27a6cd819f753ebad7d".to_string(), // i 7
-            "48e1e65014f7ea6e53398dc8718e7d10c98cc1f93e87f941d64336a548b9f65a".to_string(), // i 8
-            "2b3e36e150217da8d4fa8466dbbcbc8b4c2fc9822120d2d992639fada09dcc43".to_string(), // i 9
+            "c711a3d12f40b948de0c40abe8a5b2ff316c69a4d4d092157d47b66c8ecdfe98".to_string(), // i 0
+            "7bdffa43292bce77b32421a486f911f6ebce9d8a65ae7c7f1f33663806bc6182".to_string(), // i 1
+            "6bc2a163e14a4e98276eb5f19f3b244d7670c346c1dd6d4bb6b2509e46b8b661".to_string(), // i 2
+            "f9e57c092dc3cfa715f4857fa00d6f8d4aa740c36e60a10960b9ce21c0e29422".to_string(), // i 3
+            "cceffc80fb25451ba41dccb1d03f80fb9925e1dc73d4de43a2e0d37e04cab44c".to_string(), // i 4
+            "a9573555a38751b6accc771d5e286fb66c8c9020f0403d93a3ff838b09985ca3".to_string(), // i 5
+            "01289c2652655a7be725880d286dd65662b05cb03ccaff5045dfddbe7cd45def".to_string(), // i 6
+            "32a86c479f9de0e96644cd83e645293adca4859cca8f22584408908d8653bf62".to_string(), // i 7
+            "3f15c0583462b31a6d51e2bd6486271c5acf8ce3e057b474922be77767c76e13".to_string(), // i 8
+            "4e585d21a1e156e1bb8b61e8a78b7080d1761a840c018f44fd1365ab2554c8a4".to_string(), // i 9
         ];
         let root_hashes = hash_str.into_iter()
             .map(|s| HashValue::from_slice(&hex::decode(s).unwrap()).unwrap())
             .collect::<Vec<_>>();
         let mut tree = Tree::<UnsignedInteger, 2, 3>::new();
         for (idx, p) in points.iter().enumerate() {
-            tree.insert(format!("key-{}", idx), p.clone(), num_hash(idx as i32));
+            tree.insert(format!("key-{}", idx), p.clone(), num_hash(idx as i32)).unwrap();
             assert_eq!(tree.root_hash().unwrap(), root_hashes[idx]);
             println!("test-{} pass", idx);
         }
+        let root_hash = tree.root_hash().unwrap();
+        // 查询结果为空时也要返回可以验证的VO
         let query1 = Rect::new([5usize, 1],[7usize, 6]);
-        let vo1 = tree.range_query(&query1);
-        assert_eq!(vo1.is_none(), true);
+        let vo1 = tree.range_query(&query1).unwrap();
+        assert!(vo1.iter().all(|e| !matches!(e, VerifyObjectEntry::Target(_))));
+        assert!(vo1.verify(&query1, root_hash).is_ok());
 
         let query2 = Rect::new([3usize, 3],[7usize, 4]);
-        let vo2 = tree.range_query(&query2);
-        assert_eq!(vo2.is_none(), true);
+        let vo2 = tree.range_query(&query2).unwrap();
+        assert!(vo2.verify(&query2, root_hash).is_ok());
 
         let query3 = Rect::new([3usize, 4],[7usize, 8]);
         let vo3 = tree.range_query(&query3).unwrap();
         vo3.display();
+        assert!(vo3.verify(&query3, root_hash).is_ok());
+
+        // 篡改结果对象的位置（仍在查询范围内）无法通过验证
+        let mut forged = VerifyObject::new();
+        for ety in vo3.iter() {
+            match ety.clone() {
+                VerifyObjectEntry::Target(mut t) if t.match_key("key-3") => {
+                    t.update_loc(Rect::new_point([5usize, 5]));
+                    forged.push(VerifyObjectEntry::Target(t));
+                },
+                other => forged.push(other),
+            }
+        }
+        assert!(forged.verify(&query3, root_hash).is_err());
+
+        // 空的VO不能通过验证
+        assert!(VerifyObject::<usize, 2>::new().verify(&query1, root_hash).is_err());
+    }
+
+    #[test]
+    fn test_knn_query() {
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 120);
+        let mut tree = Tree::<f64, 2, 4>::new();
+        assert!(matches!(tree.knn_query(&[0.0, 0.0], 3), Err(EsmtError::EmptyTree)));
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash().unwrap();
+        let q = [37.5f64, 61.2];
+        let query = Rect::new_point(q);
+        let mut expected = points.iter().map(|p| Rect::new_point(*p).rect_dist(&query)).collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for k in [0usize, 1, 7, 30] {
+            let vo = tree.knn_query(&q, k).unwrap();
+            assert!(vo.verify_knn(&q, k, root_hash).is_ok());
+            let mut dists = vo.iter()
+                .filter_map(|e| match e {
+                    VerifyObjectEntry::Target(t) => Some(t.loc().rect_dist(&query)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            dists.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(dists, expected[..k].to_vec());
+            // 用更大的k验证时结果数量不足
+            assert!(vo.verify_knn(&q, k + 1, root_hash).is_err());
+        }
+        // 对象不足k个时返回所有对象
+        let vo = tree.knn_query(&q, 200).unwrap();
+        assert_eq!(vo.iter().filter(|e| matches!(e, VerifyObjectEntry::Target(_))).count(), 120);
+        assert!(vo.verify_knn(&q, 200, root_hash).is_ok());
+
+        // 丢弃最近的结果，用兄弟节点代替，验证失败
+        let vo = tree.knn_query(&q, 5).unwrap();
+        let mut forged = VerifyObject::new();
+        for ety in vo.iter() {
+            match ety {
+                VerifyObjectEntry::Target(t) if t.loc().rect_dist(&query) == expected[0] => {
+                    forged.push(VerifyObjectEntry::Sibling(SiblingObject::from(t)));
+                },
+                other => forged.push(other.clone()),
+            }
+        }
+        assert!(forged.verify_knn(&q, 4, root_hash).is_err());
+    }
+
+    #[test]
+    fn test_malformed_vo() {
+        let mut tree = Tree::<usize, 2, 3>::new();
+        for (i, p) in generate_points([0usize, 0], [100, 100], 50).into_iter().enumerate() {
+            tree.insert(format!("key-{}", i), p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash().unwrap();
+        let query = Rect::new([20usize, 20], [60, 60]);
+        let vo = tree.range_query(&query).unwrap();
+        let entries = vo.iter().cloned().collect::<Vec<_>>();
+        let rebuild = |entries: &[VerifyObjectEntry<usize, 2>]| {
+            let mut vo = VerifyObject::new();
+            entries.iter().cloned().for_each(|e| vo.push(e));
+            vo
+        };
+        assert_eq!(VerifyObject::<usize, 2>::new().verify(&query, root_hash), Err(VerifyError::EmptyProof));
+        // 截断的VO
+        assert_eq!(
+            rebuild(&entries[..entries.len() - 1]).verify(&query, root_hash),
+            Err(VerifyError::UnclosedLevel { depth: 1 })
+        );
+        // 多余的LevlEnd
+        let mut extra = entries.clone();
+        extra.push(VerifyObjectEntry::LevlEnd);
+        assert_eq!(
+            rebuild(&extra).verify(&query, root_hash),
+            Err(VerifyError::UnmatchedLevelEnd { index: entries.len() })
+        );
+        // 根节点之外的条目
+        let mut outside = entries.clone();
+        outside.push(entries.iter().find(|e| matches!(e, VerifyObjectEntry::Sibling(_))).unwrap().clone());
+        assert_eq!(
+            rebuild(&outside).verify(&query, root_hash),
+            Err(VerifyError::EntryOutsideLevel { index: entries.len() })
+        );
+        // 空层
+        let empty = [VerifyObjectEntry::LevelBegin, VerifyObjectEntry::LevlEnd];
+        assert_eq!(rebuild(&empty).verify(&query, root_hash), Err(VerifyError::EmptyLevel { index: 1, depth: 1 }));
+        // 错误的根哈希报告期望值和计算值
+        match vo.verify(&query, num_hash(-1)) {
+            Err(VerifyError::RootMismatch { expected, computed }) => {
+                assert_eq!(expected, num_hash(-1));
+                assert_eq!(computed, root_hash);
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // 与查询相交的兄弟节点报告其位置和深度
+        let other = Rect::new([0usize, 0], [100, 100]);
+        match vo.verify(&other, root_hash) {
+            Err(VerifyError::SiblingIntersectsQuery { index, depth }) => {
+                assert!(matches!(entries[index], VerifyObjectEntry::Sibling(_)));
+                assert!(depth >= 1);
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // 任意截断都只返回错误，不会panic
+        for len in 0..entries.len() {
+            assert!(rebuild(&entries[..len]).verify(&query, root_hash).is_err());
+            assert!(rebuild(&entries[len..]).verify(&query, root_hash).is_err() || len == 0);
+        }
+    }
+
+    #[test]
+    fn test_batch_range_query() {
+        let mut tree = Tree::<usize, 2, 4>::new();
+        assert!(matches!(tree.batch_range_query(&[]), Err(EsmtError::EmptyTree)));
+        // 保证第一个查询独有的区域和下面多出的查询中都有对象
+        let points = generate_points([0usize, 0], [1000, 1000], 300).into_iter().chain([[10, 350], [50, 50]]);
+        for (i, p) in points.enumerate() {
+            tree.insert(format!("key-{}", i), p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash().unwrap();
+        let queries = (0..12usize)
+            .map(|i| Rect::new([i * 40, 300], [i * 40 + 150, 450]))
+            .collect::<Vec<_>>();
+        let vo = tree.batch_range_query(&queries).unwrap();
+        let results = vo.verify_batch(&queries, root_hash).unwrap();
+        assert_eq!(results.len(), queries.len());
+        let mut separate_size = 0;
+        for (query, res) in queries.iter().zip(results.iter()) {
+            let single = tree.range_query(query).unwrap();
+            assert!(single.verify(query, root_hash).is_ok());
+            separate_size += single.encode().len();
+            let mut expected = single.iter()
+                .filter_map(|e| match e {
+                    VerifyObjectEntry::Target(t) => Some(t.key()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let mut keys = res.iter().map(|o| o.key()).collect::<Vec<_>>();
+            expected.sort();
+            keys.sort();
+            assert_eq!(keys, expected);
+        }
+        // 重叠的路径只出现一次
+        assert!(vo.encode().len() < separate_size / 2);
+
+        // 缺少一个查询时，该查询独有的结果不属于任何查询
+        assert!(matches!(vo.verify_batch(&queries[1..], root_hash), Err(VerifyError::TargetOutsideQuery { .. })));
+        // 多出一个查询时，被剪枝的兄弟节点与之相交
+        let mut more = queries.clone();
+        more.push(Rect::new([0usize, 0], [100, 100]));
+        assert!(matches!(vo.verify_batch(&more, root_hash), Err(VerifyError::SiblingIntersectsQuery { .. })));
+        assert!(vo.verify_batch(&queries, num_hash(-1)).is_err());
+    }
+
+    #[test]
+    fn test_polygon_query() {
+        let mut tree = Tree::<usize, 2, 4>::new();
+        let points = generate_points([0usize, 0], [1000, 1000], 200);
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash().unwrap();
+        // 凹多边形的地理围栏
+        let fence = Polygon::new(vec![[100usize, 100], [800, 150], [500, 400], [900, 850], [150, 700]]);
+        let vo = tree.range_query(&fence).unwrap();
+        assert!(vo.verify(&fence, root_hash).is_ok());
+        let mut keys = vo.iter()
+            .filter_map(|e| match e {
+                VerifyObjectEntry::Target(t) => Some(t.key().clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut expected = points.iter()
+            .enumerate()
+            .filter(|(_, p)| fence.contains_point(p))
+            .map(|(i, _)| format!("key-{}", i))
+            .collect::<Vec<_>>();
+        keys.sort();
+        expected.sort();
+        assert!(!expected.is_empty());
+        assert_eq!(keys, expected);
+
+        // 按外接矩形验证时，外接矩形内但多边形外的对象被遗漏
+        assert!(matches!(vo.verify(fence.mbr(), root_hash), Err(VerifyError::SiblingIntersectsQuery { .. })));
+        // 外接矩形的查询结果含有多边形外的对象
+        let bbox_vo = tree.range_query(fence.mbr()).unwrap();
+        assert!(matches!(bbox_vo.verify(&fence, root_hash), Err(VerifyError::TargetOutsideQuery { .. })));
+        assert!(vo.verify(&fence, num_hash(-1)).is_err());
+    }
+
+    #[test]
+    fn test_circle_query() {
+        let mut tree = Tree::<usize, 2, 4>::new();
+        assert!(matches!(tree.circle_query(&[0, 0], 10), Err(EsmtError::EmptyTree)));
+        let points = generate_points([0usize, 0], [1000, 1000], 200);
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash().unwrap();
+        for (center, radius) in [([500usize, 500], 120usize), ([0, 0], 300), ([990, 10], 60), ([2000, 2000], 100)] {
+            let vo = tree.circle_query(&center, radius).unwrap();
+            assert!(vo.verify_circle(&center, radius, root_hash).is_ok());
+            let returned = vo.iter().filter(|e| matches!(e, VerifyObjectEntry::Target(_))).count();
+            let expected = points.iter()
+                .filter(|p| Rect::new_point(**p).rect_dist(&Rect::new_point(center)) <= radius * radius)
+                .count();
+            assert_eq!(returned, expected);
+            assert!(vo.verify_circle(&center, radius, num_hash(-1)).is_err());
+        }
+        // 缩小半径时结果对象不在圆内，扩大半径时被剪枝的兄弟节点与圆相交
+        let vo = tree.circle_query(&[500, 500], 120).unwrap();
+        assert!(matches!(vo.verify_circle(&[500, 500], 60, root_hash), Err(VerifyError::TargetOutsideQuery { .. })));
+        assert!(matches!(vo.verify_circle(&[500, 500], 240, root_hash), Err(VerifyError::SiblingIntersectsQuery { .. })));
+    }
+
+    #[test]
+    fn test_extent_objects() {
+        let mut tree = Tree::<f64, 2, 4>::new();
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 100);
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let inside = Rect::new([20.0f64, 20.0], [30.0, 25.0]);
+        let crossing = Rect::new([35.0f64, 35.0], [60.0, 38.0]);
+        tree.insert_object(ObjectEntry::with_rect("inside".to_string(), inside.clone(), num_hash(1000))).unwrap();
+        tree.insert_object(ObjectEntry::with_rect("crossing".to_string(), crossing.clone(), num_hash(1001))).unwrap();
+        let line = vec![[10.0f64, 10.0], [15.0, 30.0], [35.0, 20.0]];
+        tree.insert_object(ObjectEntry::with_polyline("line".to_string(), line.clone(), num_hash(1002)).unwrap()).unwrap();
+        assert!(ObjectEntry::<f64, 2>::with_polyline("empty".to_string(), vec![], num_hash(0)).is_none());
+        let root_hash = tree.root_hash().unwrap();
+
+        // 与查询部分相交的对象不是结果，作为兄弟节点也能通过验证
+        let query = Rect::new([5.0f64, 5.0], [40.0, 40.0]);
+        let vo = tree.range_query(&query).unwrap();
+        assert!(vo.verify(&query, root_hash).is_ok());
+        let targets = vo.iter()
+            .filter_map(|e| match e {
+                VerifyObjectEntry::Target(t) => Some(t.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let keys = targets.iter().map(|t| t.key()).collect::<Vec<_>>();
+        assert!(keys.contains(&"inside".to_string()));
+        assert!(!keys.contains(&"crossing".to_string()));
+        let polyline = targets.iter().find(|t| t.match_key("line")).unwrap();
+        assert_eq!(polyline.polyline().unwrap(), &line[..]);
+        assert_eq!(polyline.loc()._min, [10.0, 10.0]);
+        assert_eq!(polyline.loc()._max, [35.0, 30.0]);
+        // 但被查询包含的对象不能被剪枝
+        let bigger = Rect::new([5.0f64, 5.0], [60.0, 40.0]);
+        assert!(matches!(vo.verify(&bigger, root_hash), Err(VerifyError::SiblingIntersectsQuery { .. })));
+
+        assert!(tree.delete_rect("crossing", &crossing).is_ok());
+        assert!(tree.delete_rect("line", &Rect::new([10.0, 10.0], [35.0, 30.0])).is_ok());
+        assert!(matches!(tree.delete_rect("missing", &crossing), Err(EsmtError::KeyNotFound(_))));
+        let vo = tree.range_query(&bigger).unwrap();
+        vo.verify(&bigger, tree.root_hash().unwrap()).unwrap();
+    }
+
+    fn check_split_policy<P: SplitPolicy<f64, 2> + 'static>(policy: P) {
+        let mut tree = Tree::<f64, 2, 6>::with_split_policy(policy);
+        let points = generate_points([0.0f64, 0.0], [100.0, 100.0], 200);
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        for (i, p) in points.iter().enumerate().take(80) {
+            assert!(tree.delete(&format!("key-{}", i), p).is_ok());
+        }
+        assert_eq!(tree.len(), 120);
+        let root_hash = tree.root_hash().unwrap();
+        for query in [Rect::new([0.0f64, 0.0], [100.0, 100.0]), Rect::new([20.0f64, 35.0], [55.0, 60.0])] {
+            let vo = tree.range_query(&query).unwrap();
+            assert!(vo.verify(&query, root_hash).is_ok());
+            let expected = points.iter().skip(80).filter(|p| query.contains(&Rect::new_point(**p))).count();
+            let returned = vo.iter().filter(|e| matches!(e, VerifyObjectEntry::Target(_))).count();
+            assert_eq!(expected, returned);
+        }
+    }
+
+    #[test]
+    fn test_split_policies() {
+        check_split_policy(HilbertSplit);
+        check_split_policy(QuadraticSplit);
+        check_split_policy(LinearSplit);
+        check_split_policy(RStarSplit::default());
+    }
+
+    /// 以哈希后端H建树并验证范围查询的VO，返回根哈希
+    fn check_hash_backend<H: HashBackend>(points: &[[usize; 2]], query: &Rect<usize, 2>) -> (HashValue, Vec<u8>) {
+        let mut tree = Tree::<usize, 2, 4, H>::new();
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash().unwrap();
+        let vo = tree.range_query(query).unwrap();
+        assert!(vo.verify(query, root_hash).is_ok());
+        // 解码后的VO同样按该后端验证
+        let bytes = vo.encode();
+        assert!(VerifyObject::<usize, 2, H>::decode(&bytes).unwrap().verify(query, root_hash).is_ok());
+        (root_hash, bytes)
+    }
+
+    #[test]
+    fn test_hash_backends() {
+        let points = generate_points([0usize, 0], [100, 100], 60);
+        let query = Rect::new([10, 10], [60, 60]);
+        let (sha3, _) = check_hash_backend::<Sha3_256>(&points, &query);
+        let (keccak, keccak_vo) = check_hash_backend::<Keccak256>(&points, &query);
+        let (blake3, _) = check_hash_backend::<Blake3>(&points, &query);
+        // 默认后端为SHA3-256
+        let mut tree = Tree::<usize, 2, 4>::new();
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        assert_eq!(tree.root_hash(), Some(sha3));
+        assert_ne!(sha3, keccak);
+        assert_ne!(sha3, blake3);
+        assert_ne!(keccak, blake3);
+        // 用其他后端验证时重新计算的摘要不一致
+        let wrong = VerifyObject::<usize, 2, Sha3_256>::decode(&keccak_vo).unwrap();
+        assert!(matches!(wrong.verify(&query, keccak), Err(VerifyError::RootMismatch { .. })));
+    }
+
+    #[test]
+    fn test_domain_separation() {
+        // 构造与对象摘要输入完全相同的节点承诺，两者的摘要仍然不同
+        let key = b"key";
+        let loc = [1u8, 2, 3, 4];
+        let value = num_hash(7);
+        let mut record = vec![];
+        record.extend_from_slice(&(key.len() as u64).to_le_bytes());
+        record.extend_from_slice(key);
+        record.extend_from_slice(&(loc.len() as u64).to_le_bytes());
+        record.extend_from_slice(&loc);
+        record.extend_from_slice(value.as_ref());
+        let leaf = leaf_hash::<Sha3_256>(key, &loc, &value);
+        assert_ne!(node_digest::<Sha3_256>(vec![record]), leaf);
+        // 全局Merkle树的内部节点与分区树的节点同理
+        let (left, right) = (num_hash(1), num_hash(2));
+        let concat = [left.as_ref().as_slice(), right.as_ref().as_slice()].concat();
+        assert_ne!(merkle_parent::<Sha3_256>(&left, &right), node_digest::<Sha3_256>(vec![concat]));
+        assert_ne!(partition_leaf::<usize, Sha3_256, 2>(0, &Rect::new([0, 0], [1, 1]), None), leaf);
+
+        // 把一个子树的摘要冒充为对象时无法通过验证
+        let mut tree = Tree::<usize, 2, 4>::new();
+        for (i, p) in generate_points([0usize, 0], [100, 100], 50).into_iter().enumerate() {
+            tree.insert(format!("key-{}", i), p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash().unwrap();
+        let query = Rect::new([0usize, 0], [100, 100]);
+        let vo = tree.range_query(&query).unwrap();
+        assert!(vo.verify(&query, root_hash).is_ok());
+        let mut forged = VerifyObject::<usize, 2>::new();
+        forged.push(VerifyObjectEntry::LevelBegin);
+        forged.push(VerifyObjectEntry::Sibling(SiblingObject::new(query.clone(), root_hash, None, 0)));
+        forged.push(VerifyObjectEntry::LevlEnd);
+        assert!(matches!(forged.verify(&query, root_hash), Err(VerifyError::RootMismatch { .. })));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_errors() {
+        let mut tree = Tree::<usize, 2, 4>::new();
+        assert_eq!(tree.range_query(&Rect::new([0, 0], [10, 10])).err(), Some(EsmtError::EmptyTree));
+        assert_eq!(tree.delete("key-0", &[1, 1]).err(), Some(EsmtError::KeyNotFound("key-0".to_string())));
+        let points = generate_points([0usize, 0], [100, 100], 50);
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(format!("key-{}", i), *p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash();
+        // 失败的操作不改变树
+        assert_eq!(tree.insert("key-3".to_string(), [1, 1], num_hash(-1)), Err(EsmtError::DuplicateKey("key-3".to_string())));
+        assert_eq!(tree.delete("key-x", &[1, 1]).err(), Some(EsmtError::KeyNotFound("key-x".to_string())));
+        assert_eq!(tree.update_loc("key-x", &[1, 1], [2, 2]), Err(EsmtError::KeyNotFound("key-x".to_string())));
+        assert_eq!(tree.len(), 50);
+        assert_eq!(tree.root_hash(), root_hash);
+
+        tree.update_loc("key-3", &points[3], [2, 2]).unwrap();
+        assert_eq!(tree.len(), 50);
+        assert!(tree.delete("key-3", &points[3]).is_err());
+        assert_eq!(tree.delete("key-3", &[2, 2]).unwrap().value(), num_hash(3));
+        assert!(!tree.contains("key-3"));
+        assert_eq!(tree.len(), 49);
+    }
+
+    #[test]
+    fn test_update_hash() {
+        // 原地替换值哈希后的树与直接插入新值的树相同
+        let points = generate_points([0usize, 0], [100, 100], 60);
+        let build = |changed: usize| {
+            let mut tree = Tree::<usize, 2, 4>::new();
+            for (i, p) in points.iter().enumerate() {
+                let hash = if i == changed { num_hash(-1) } else { num_hash(i as i32) };
+                tree.insert(format!("key-{}", i), *p, hash).unwrap();
+            }
+            tree
+        };
+        let mut tree = build(usize::MAX);
+        tree.update_hash("key-42", &points[42], num_hash(-1)).unwrap();
+        assert_eq!(tree.root_hash(), build(42).root_hash());
+        assert_eq!(tree.len(), 60);
+        assert_eq!(tree.update_hash("key-x", &points[42], num_hash(-1)), Err(EsmtError::KeyNotFound("key-x".to_string())));
+
+        let query = Rect::new_point(points[42]);
+        let vo = tree.range_query(&query).unwrap();
+        assert!(vo.verify(&query, tree.root_hash().unwrap()).is_ok());
+        assert!(vo.iter().any(|e| matches!(e, VerifyObjectEntry::Target(t) if t.match_key("key-42") && t.value() == num_hash(-1))));
+    }
+
+    #[test]
+    fn test_stream_verify() {
+        let mut tree = Tree::<usize, 2, 4>::new();
+        for (i, p) in generate_points([0usize, 0], [1000, 1000], 150).into_iter().enumerate() {
+            tree.insert(format!("key-{}", i), p, num_hash(i as i32)).unwrap();
+        }
+        let root_hash = tree.root_hash().unwrap();
+        let query = Rect::new([100usize, 200], [600, 500]);
+        let vo = tree.range_query(&query).unwrap();
+        let expected = vo.iter()
+            .filter_map(|e| match e {
+                VerifyObjectEntry::Target(t) => Some(t.key()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // 结果在所在的层闭合时返回，顺序与VO中一致
+        let mut verifier = StreamVerifier::new(query.clone(), root_hash);
+        let mut results = vec![];
+        let mut early = false;
+        let total = vo.iter().count();
+        for (idx, ety) in vo.clone().into_iter().enumerate() {
+            let objs = verifier.push(ety).unwrap();
+            early |= !objs.is_empty() && idx + 1 < total;
+            results.extend(objs.into_iter().map(|o| o.key()));
+        }
+        assert!(verifier.finish().is_ok());
+        assert!(early);
+        assert_eq!(results, expected);
+
+        // 与完整验证得到相同的错误
+        let verifier = StreamVerifier::new(query.clone(), num_hash(-1));
+        assert_eq!(
+            verifier.verify_all(vo.clone().into_iter(), |_| {}),
+            Err(VerifyError::RootMismatch { expected: num_hash(-1), computed: root_hash })
+        );
+        let other = Rect::new([0usize, 0], [1000, 1000]);
+        let verifier = StreamVerifier::new(other.clone(), root_hash);
+        assert_eq!(verifier.verify_all(vo.clone().into_iter(), |_| {}).err(), vo.verify(&other, root_hash).err());
+        let verifier = StreamVerifier::new(query.clone(), root_hash);
+        assert_eq!(
+            verifier.verify_all(vo.clone().into_iter().take(total - 1), |_| {}),
+            Err(VerifyError::UnclosedLevel { depth: 1 })
+        );
+    }
+
+}
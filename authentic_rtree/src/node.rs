@@ -1,8 +1,13 @@
-use std::collections::{BTreeSet, VecDeque};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
 use std::fmt::Debug;
+use std::marker::PhantomData;
 use std::ops::{Add, Div, Mul, Sub};
-use types::hash_value::{ESMTHasher, HashValue};
-use crate::shape::Rect;
+use types::hash_value::{ESMTHasher, HashBackend, HashDomain, HashValue, Sha3_256, leaf_hash};
+use crate::error::EsmtError;
+use crate::shape::{QueryShape, Rect};
+use crate::split::SplitPolicy;
+use crate::verify::{KeyIndexLeaf, KeyIndexStep, SiblingObject, VerifyObject, VerifyObjectEntry};
 
 pub trait FromPrimitive: Sized {
     fn from_i32(i: i32) -> Self;
@@ -10,9 +15,23 @@ pub trait FromPrimitive: Sized {
 
 pub trait  ToPrimitive: Sized {
     fn to_usize(self) -> usize;
+    fn to_f64(self) -> f64;
 }
 
-pub trait MRTreeDefault: Default + Debug + Copy {}
+/// 坐标的规范字节编码（小端序），用于将mbr纳入哈希计算
+pub trait ToBytes {
+    fn write_bytes(&self, buf: &mut Vec<u8>);
+}
+
+/// 从[`ToBytes`]的编码中还原坐标，用于VO的反序列化
+pub trait FromBytes: Sized {
+    /// 编码后的字节数
+    const BYTES: usize;
+    /// `bytes`的长度必须为[`FromBytes::BYTES`]，无法还原时返回`None`
+    fn read_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+pub trait MRTreeDefault: Default + Debug + Copy + ToBytes + FromBytes {}
 pub trait MRTreeFunc:
 PartialOrd + Sub<Output = Self> + Add<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Sized {
 }
@@ -29,6 +48,28 @@ impl ToPrimitive for usize {
     fn to_usize(self) -> usize {
         self
     }
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl ToBytes for usize {
+    #[inline]
+    fn write_bytes(&self, buf: &mut Vec<u8>) {
+        // 与平台无关，统一编码为u64
+        buf.extend_from_slice(&(*self as u64).to_le_bytes());
+    }
+}
+
+impl FromBytes for usize {
+    const BYTES: usize = 8;
+
+    #[inline]
+    fn read_bytes(bytes: &[u8]) -> Option<Self> {
+        let v = u64::from_le_bytes(bytes.try_into().ok()?);
+        usize::try_from(v).ok()
+    }
 }
 
 impl MRTreeDefault for usize {}
@@ -46,6 +87,28 @@ impl ToPrimitive for f32 {
     fn to_usize(self) -> usize {
         self as usize
     }
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl ToBytes for f32 {
+    #[inline]
+    fn write_bytes(&self, buf: &mut Vec<u8>) {
+        // +0.0与-0.0编码相同
+        let v = if *self == 0.0 { 0.0f32 } else { *self };
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+impl FromBytes for f32 {
+    const BYTES: usize = 4;
+
+    #[inline]
+    fn read_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(f32::from_le_bytes(bytes.try_into().ok()?))
+    }
 }
 
 impl MRTreeDefault for f32{}
@@ -63,6 +126,27 @@ impl ToPrimitive for f64 {
     fn to_usize(self) -> usize {
         self as usize
     }
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl ToBytes for f64 {
+    #[inline]
+    fn write_bytes(&self, buf: &mut Vec<u8>) {
+        let v = if *self == 0.0 { 0.0f64 } else { *self };
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+impl FromBytes for f64 {
+    const BYTES: usize = 8;
+
+    #[inline]
+    fn read_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(f64::from_le_bytes(bytes.try_into().ok()?))
+    }
 }
 
 impl MRTreeDefault for f64{}
@@ -80,6 +164,26 @@ impl ToPrimitive for i32 {
     fn to_usize(self) -> usize {
         self as usize
     }
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl ToBytes for i32 {
+    #[inline]
+    fn write_bytes(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl FromBytes for i32 {
+    const BYTES: usize = 4;
+
+    #[inline]
+    fn read_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(i32::from_le_bytes(bytes.try_into().ok()?))
+    }
 }
 
 impl MRTreeDefault for i32{}
@@ -89,58 +193,617 @@ pub type Float = f32;
 pub type UnsignedInteger = usize;
 pub type Integer = i32;
 
+/// 子条目在父节点摘要中的承诺：哈希 || mbr || 高度 || 对象数量。空间对象没有高度，
+/// 数量为1（已删除的对象为0）。
+pub(crate) fn entry_record<V, const D: usize>(hash: &HashValue, mbr: &Rect<V, D>, height: Option<u32>, count: u64) -> Vec<u8>
+    where
+        V: MRTreeDefault,
+{
+    let mut record = Vec::with_capacity(HashValue::LENGTH + 2 * D * 8 + 12);
+    record.extend_from_slice(hash.as_ref());
+    mbr.write_bytes(&mut record);
+    if let Some(h) = height {
+        record.extend_from_slice(&h.to_le_bytes());
+    }
+    record.extend_from_slice(&count.to_le_bytes());
+    record
+}
+
+/// 节点摘要：对所有子条目的承诺排序后依次哈希，与子条目的顺序无关。
+/// 使用[`HashDomain::Node`]标签，与对象摘要区分
+pub(crate) fn node_digest<H: HashBackend>(mut records: Vec<Vec<u8>>) -> HashValue {
+    records.sort();
+    records
+        .iter()
+        .fold(ESMTHasher::<H>::tagged(HashDomain::Node), |hasher, record| {
+            hasher.update(record)
+        })
+        .finish()
+}
+
+/// 分区在全局Merkle树中的叶子：分区编号 || 分区范围 || 是否非空 || 分区根哈希
+pub(crate) fn partition_leaf<V, H, const D: usize>(index: usize, area: &Rect<V, D>, root: Option<HashValue>) -> HashValue
+    where
+        V: MRTreeDefault,
+        H: HashBackend,
+{
+    let mut buf = vec![];
+    buf.extend_from_slice(&(index as u64).to_le_bytes());
+    area.write_bytes(&mut buf);
+    buf.push(root.is_some() as u8);
+    buf.extend_from_slice(root.unwrap_or_default().as_ref());
+    ESMTHasher::<H>::tagged(HashDomain::Partition).update(&buf).finish()
+}
+
+/// 全局Merkle树中相邻两个节点的父节点哈希
+pub(crate) fn merkle_parent<H: HashBackend>(left: &HashValue, right: &HashValue) -> HashValue {
+    ESMTHasher::<H>::tagged(HashDomain::Global)
+        .update(left.as_ref())
+        .update(right.as_ref())
+        .finish()
+}
+
+/// Merkle树两两合并得到上一层，落单的最后一个节点直接提升
+pub(crate) fn merkle_level<H: HashBackend>(level: &[HashValue]) -> Vec<HashValue> {
+    level.chunks(2)
+        .map(|pair| {
+            if pair.len() == 2 {
+                merkle_parent::<H>(&pair[0], &pair[1])
+            } else {
+                pair[0]
+            }
+        })
+        .collect()
+}
+
+/// Merkle树的根，没有叶子时为全零
+pub(crate) fn merkle_root<H: HashBackend>(mut level: Vec<HashValue>) -> HashValue {
+    if level.is_empty() {
+        return HashValue::zero();
+    }
+    while level.len() > 1 {
+        level = merkle_level::<H>(&level);
+    }
+    level[0]
+}
+
+/// 第index个叶子到根的路径：自底向上的兄弟哈希，直接提升的层不占位置
+pub(crate) fn merkle_path<H: HashBackend>(mut level: Vec<HashValue>, mut index: usize) -> Vec<HashValue> {
+    let mut path = vec![];
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            path.push(level[sibling]);
+        }
+        level = merkle_level::<H>(&level);
+        index >>= 1;
+    }
+    path
+}
+
+/// 由叶子、叶子位置、叶子总数和路径重新计算Merkle树的根，路径长度不匹配时返回`None`
+pub(crate) fn merkle_path_root<H: HashBackend>(leaf: HashValue, mut index: usize, mut width: usize, path: &[HashValue]) -> Option<HashValue> {
+    if index >= width {
+        return None;
+    }
+    let mut hash = leaf;
+    let mut path = path.iter();
+    while width > 1 {
+        if index % 2 == 1 {
+            hash = merkle_parent::<H>(path.next()?, &hash);
+        } else if index + 1 < width {
+            hash = merkle_parent::<H>(&hash, path.next()?);
+        }
+        index >>= 1;
+        width = width / 2 + width % 2;
+    }
+    if path.next().is_some() {
+        return None;
+    }
+    Some(hash)
+}
+
+/// key索引的叶子：key的长度 || key || 对象摘要
+pub(crate) fn key_leaf<H: HashBackend>(key: &str, digest: &HashValue) -> HashValue {
+    ESMTHasher::<H>::tagged(HashDomain::KeyLeaf)
+        .update(&(key.len() as u64).to_le_bytes())
+        .update(key.as_bytes())
+        .update(digest.as_ref())
+        .finish()
+}
+
+/// key索引在全局Merkle树中的叶子：key的数量 || key索引的Merkle根
+pub(crate) fn key_index_root<H: HashBackend>(count: usize, root: &HashValue) -> HashValue {
+    ESMTHasher::<H>::tagged(HashDomain::KeyIndex)
+        .update(&(count as u64).to_le_bytes())
+        .update(root.as_ref())
+        .finish()
+}
+
+/// key索引中树堆节点的哈希：左子树的大小 || 左子树的哈希 || 节点自身的叶子 || 右子树的大小 || 右子树的哈希，
+/// 空子树的大小为0、哈希为全零
+pub(crate) fn key_node<H: HashBackend>(left: (usize, &HashValue), leaf: &HashValue, right: (usize, &HashValue)) -> HashValue {
+    ESMTHasher::<H>::tagged(HashDomain::KeyNode)
+        .update(&(left.0 as u64).to_le_bytes())
+        .update(left.1.as_ref())
+        .update(leaf.as_ref())
+        .update(&(right.0 as u64).to_le_bytes())
+        .update(right.1.as_ref())
+        .finish()
+}
+
+/// key在树堆中的优先级，只由key决定
+fn key_priority<H: HashBackend>(key: &str) -> HashValue {
+    ESMTHasher::<H>::tagged(HashDomain::KeyPriority)
+        .update(key.as_bytes())
+        .finish()
+}
+
+/// key索引中的一项：key、对象的值哈希和对象摘要
+#[derive(Clone)]
+pub(crate) struct KeyIndexEntry {
+    pub(crate) key: String,
+    pub(crate) value: HashValue,
+    pub(crate) digest: HashValue,
+}
+
+/// key索引树堆中的节点。修改只标记路径上的节点，哈希在[`KeyIndex::commit`]时计算
+#[derive(Clone)]
+struct KeyNode {
+    entry: KeyIndexEntry,
+    priority: HashValue,
+    /// 节点自身的叶子，对象摘要改变后为`None`
+    leaf: Option<HashValue>,
+    /// 子树中的key数量
+    count: usize,
+    hash: HashValue,
+    dirty: bool,
+    left: Option<Box<KeyNode>>,
+    right: Option<Box<KeyNode>>,
+}
+
+impl KeyNode {
+    #[inline]
+    fn size(node: &Option<Box<KeyNode>>) -> usize {
+        node.as_ref().map_or(0, |n| n.count)
+    }
+
+    /// 子树的大小和哈希，空子树为`(0, 全零)`
+    #[inline]
+    fn summary(node: &Option<Box<KeyNode>>) -> (usize, HashValue) {
+        node.as_ref().map_or((0, HashValue::zero()), |n| (n.count, n.hash))
+    }
+
+    #[inline]
+    fn precedes(&self, other: &KeyNode) -> bool {
+        (self.priority, &self.entry.key) > (other.priority, &other.entry.key)
+    }
+
+    fn touch(&mut self) {
+        self.count = Self::size(&self.left) + Self::size(&self.right) + 1;
+        self.dirty = true;
+    }
+
+    fn rotate_right(slot: &mut Option<Box<KeyNode>>) {
+        if let Some(mut node) = slot.take() {
+            match node.left.take() {
+                Some(mut left) => {
+                    node.left = left.right.take();
+                    node.touch();
+                    left.right = Some(node);
+                    left.touch();
+                    *slot = Some(left);
+                }
+                None => *slot = Some(node),
+            }
+        }
+    }
+
+    fn rotate_left(slot: &mut Option<Box<KeyNode>>) {
+        if let Some(mut node) = slot.take() {
+            match node.right.take() {
+                Some(mut right) => {
+                    node.right = right.left.take();
+                    node.touch();
+                    right.left = Some(node);
+                    right.touch();
+                    *slot = Some(right);
+                }
+                None => *slot = Some(node),
+            }
+        }
+    }
+
+    /// 插入新节点时返回`true`，key已经存在时替换其值哈希和对象摘要
+    fn insert(slot: &mut Option<Box<KeyNode>>, fresh: Box<KeyNode>) -> bool {
+        let node = match slot {
+            None => {
+                *slot = Some(fresh);
+                return true;
+            }
+            Some(node) => node,
+        };
+        let inserted = match fresh.entry.key.cmp(&node.entry.key) {
+            Ordering::Equal => {
+                node.entry.value = fresh.entry.value;
+                node.entry.digest = fresh.entry.digest;
+                node.leaf = None;
+                false
+            }
+            Ordering::Less => {
+                let inserted = Self::insert(&mut node.left, fresh);
+                if node.left.as_ref().is_some_and(|l| l.precedes(node)) {
+                    Self::rotate_right(slot);
+                    return inserted;
+                }
+                inserted
+            }
+            Ordering::Greater => {
+                let inserted = Self::insert(&mut node.right, fresh);
+                if node.right.as_ref().is_some_and(|r| r.precedes(node)) {
+                    Self::rotate_left(slot);
+                    return inserted;
+                }
+                inserted
+            }
+        };
+        node.touch();
+        inserted
+    }
+
+    fn remove(slot: &mut Option<Box<KeyNode>>, key: &str) -> Option<KeyIndexEntry> {
+        let node = slot.as_mut()?;
+        let removed = match key.cmp(node.entry.key.as_str()) {
+            Ordering::Less => Self::remove(&mut node.left, key),
+            Ordering::Greater => Self::remove(&mut node.right, key),
+            Ordering::Equal => {
+                let mut node = slot.take()?;
+                *slot = Self::merge(node.left.take(), node.right.take());
+                return Some(node.entry);
+            }
+        };
+        if removed.is_some() {
+            node.touch();
+        }
+        removed
+    }
+
+    /// 合并两棵树堆，left中的key都小于right中的key
+    fn merge(left: Option<Box<KeyNode>>, right: Option<Box<KeyNode>>) -> Option<Box<KeyNode>> {
+        match (left, right) {
+            (None, r) => r,
+            (l, None) => l,
+            (Some(mut l), Some(mut r)) => {
+                if l.precedes(&r) {
+                    l.right = Self::merge(l.right.take(), Some(r));
+                    l.touch();
+                    Some(l)
+                } else {
+                    r.left = Self::merge(Some(l), r.left.take());
+                    r.touch();
+                    Some(r)
+                }
+            }
+        }
+    }
+
+    fn update(slot: &mut Option<Box<KeyNode>>, key: &str, value: HashValue, digest: HashValue) -> bool {
+        let node = match slot {
+            None => return false,
+            Some(node) => node,
+        };
+        let found = match key.cmp(node.entry.key.as_str()) {
+            Ordering::Less => Self::update(&mut node.left, key, value, digest),
+            Ordering::Greater => Self::update(&mut node.right, key, value, digest),
+            Ordering::Equal => {
+                node.entry.value = value;
+                node.entry.digest = digest;
+                node.leaf = None;
+                true
+            }
+        };
+        if found {
+            node.dirty = true;
+        }
+        found
+    }
+
+    /// 后序计算所有被标记节点的哈希，每个节点只计算一次
+    fn commit<H: HashBackend>(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        for child in [&mut self.left, &mut self.right].into_iter().flatten() {
+            child.commit::<H>();
+        }
+        let leaf = *self.leaf.get_or_insert_with(|| key_leaf::<H>(&self.entry.key, &self.entry.digest));
+        let (left, right) = (Self::summary(&self.left), Self::summary(&self.right));
+        self.hash = key_node::<H>((left.0, &left.1), &leaf, (right.0, &right.1));
+        self.dirty = false;
+    }
+
+    /// 下次提交时子树中需要计算的摘要数量
+    #[cfg(test)]
+    fn pending(&self) -> usize {
+        if !self.dirty {
+            return 0;
+        }
+        let children = [&self.left, &self.right].into_iter().flatten().map(|c| c.pending()).sum::<usize>();
+        children + 1 + self.leaf.is_none() as usize
+    }
+}
+
+/// 认证的key索引：以key排序、以key的哈希为优先级的Merkle树堆。
+/// 同一组key得到的树堆形状唯一，与插入和删除的顺序无关；每个节点承诺两棵子树的大小和哈希，
+/// 因此证明路径同时确定了叶子在所有key中的位置。插入、删除和修改只标记根到该key路径上的
+/// O(log n)个节点，[`KeyIndex::commit`]时只重新计算被标记的节点
+#[derive(Clone)]
+pub(crate) struct KeyIndex<H> {
+    root: Option<Box<KeyNode>>,
+    /// 最近一次提交时的根，承诺了key的数量和树堆的根哈希
+    committed: HashValue,
+    dirty: bool,
+    _hasher: PhantomData<fn() -> H>,
+}
+
+impl<H: HashBackend> KeyIndex<H> {
+    pub(crate) fn new() -> Self {
+        Self {
+            root: None,
+            committed: key_index_root::<H>(0, &HashValue::zero()),
+            dirty: false,
+            _hasher: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        KeyNode::size(&self.root)
+    }
+
+    #[inline]
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// key存在时返回其位置，否则返回key应当插入的位置
+    pub(crate) fn position(&self, key: &str) -> Result<usize, usize> {
+        let mut rank = 0;
+        let mut cur = &self.root;
+        while let Some(node) = cur {
+            match key.cmp(node.entry.key.as_str()) {
+                Ordering::Less => cur = &node.left,
+                Ordering::Equal => return Ok(rank + KeyNode::size(&node.left)),
+                Ordering::Greater => {
+                    rank += KeyNode::size(&node.left) + 1;
+                    cur = &node.right;
+                }
+            }
+        }
+        Err(rank)
+    }
+
+    pub(crate) fn value(&self, key: &str) -> Option<HashValue> {
+        let mut cur = &self.root;
+        while let Some(node) = cur {
+            match key.cmp(node.entry.key.as_str()) {
+                Ordering::Less => cur = &node.left,
+                Ordering::Equal => return Some(node.entry.value),
+                Ordering::Greater => cur = &node.right,
+            }
+        }
+        None
+    }
+
+    /// 最近一次提交时key索引的根，承诺了key的数量和所有叶子
+    #[inline]
+    pub(crate) fn root(&self) -> HashValue {
+        self.committed
+    }
+
+    /// 第index个叶子及其到树堆根的路径，需要在提交之后调用
+    pub(crate) fn leaf(&self, mut index: usize) -> Option<KeyIndexLeaf> {
+        let mut path = vec![];
+        let mut cur = self.root.as_ref()?;
+        let rank = index;
+        loop {
+            let left = KeyNode::size(&cur.left);
+            let (from_left, next, sibling) = match index.cmp(&left) {
+                Ordering::Equal => break,
+                Ordering::Less => (true, &cur.left, &cur.right),
+                Ordering::Greater => {
+                    index -= left + 1;
+                    (false, &cur.right, &cur.left)
+                }
+            };
+            let (sibling_count, sibling) = KeyNode::summary(sibling);
+            path.push(KeyIndexStep::new(from_left, cur.leaf?, sibling_count, sibling));
+            cur = next.as_ref()?;
+        }
+        path.reverse();
+        Some(KeyIndexLeaf::new(rank, cur.entry.key.clone(), cur.entry.digest,
+                               KeyNode::summary(&cur.left), KeyNode::summary(&cur.right), path))
+    }
+
+    /// 插入或替换key对应的项
+    pub(crate) fn insert(&mut self, key: String, value: HashValue, digest: HashValue) {
+        let priority = key_priority::<H>(&key);
+        let fresh = Box::new(KeyNode {
+            entry: KeyIndexEntry { key, value, digest },
+            priority,
+            leaf: None,
+            count: 1,
+            hash: HashValue::zero(),
+            dirty: true,
+            left: None,
+            right: None,
+        });
+        KeyNode::insert(&mut self.root, fresh);
+        self.dirty = true;
+    }
+
+    /// 插入或替换一批项
+    pub(crate) fn extend(&mut self, items: impl IntoIterator<Item = (String, HashValue, HashValue)>) {
+        for (key, value, digest) in items {
+            self.insert(key, value, digest);
+        }
+    }
+
+    /// 删除key对应的项，key不存在时返回`None`
+    pub(crate) fn remove(&mut self, key: &str) -> Option<KeyIndexEntry> {
+        let removed = KeyNode::remove(&mut self.root, key);
+        self.dirty |= removed.is_some();
+        removed
+    }
+
+    /// 删除一批key
+    pub(crate) fn remove_all<'a>(&mut self, keys: impl IntoIterator<Item = &'a String>) {
+        for key in keys {
+            self.remove(key);
+        }
+    }
+
+    /// 修改已存在的key对应的值哈希和对象摘要，key不存在时返回`false`
+    pub(crate) fn update(&mut self, key: &str, value: HashValue, digest: HashValue) -> bool {
+        let found = KeyNode::update(&mut self.root, key, value, digest);
+        self.dirty |= found;
+        found
+    }
+
+    /// 重新计算上次提交之后被标记的节点，并更新key索引的根
+    pub(crate) fn commit(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        if let Some(root) = self.root.as_mut() {
+            root.commit::<H>();
+        }
+        let (count, root) = KeyNode::summary(&self.root);
+        self.committed = key_index_root::<H>(count, &root);
+        self.dirty = false;
+    }
+
+    /// 下次提交时需要计算的摘要数量：被标记的节点、改变的叶子和key索引的根
+    #[cfg(test)]
+    pub(crate) fn pending(&self) -> usize {
+        if !self.dirty {
+            return 0;
+        }
+        self.root.as_ref().map_or(0, |r| r.pending()) + 1
+    }
+}
+
 /// `ObjectEntry`表示`ESMT`中的一个空间对象，只存在于叶子节点中。
 #[derive(Clone)]
-pub struct ObjectEntry<V, const D: usize>
+pub struct ObjectEntry<V, const D: usize, H = Sha3_256>
     where
         V: MRTreeDefault,
 {
     /// key: 空间对象在区块链数据库中的索引键值，如账户。
     key: String,
-    /// 空间对象的空间位置
+    /// 空间对象的空间位置。点和矩形对象为其本身，折线对象为其顶点的MBR
     loc: Rect<V, D>,
+    /// 折线对象按顺序保存的顶点，作为精确形状计入对象摘要；点和矩形对象为`None`
+    polyline: Option<Vec<[V; D]>>,
     /// 空间对象在区块链中所有状态集合的哈希值，如账户的哈希值
+    value: HashValue,
+    /// 空间对象的摘要，由key、loc和value共同计算，参见[`leaf_hash`]
     hash: HashValue,
     /// 空间对象是否需要压缩，用于lazy update
     stale: bool,
+    _hasher: PhantomData<fn() -> H>,
 }
 
-pub(crate) struct Node<V, const D: usize, const C: usize>
+pub(crate) struct Node<V, const D: usize, const C: usize, H = Sha3_256>
     where
         V: MRTreeDefault,
 {
     pub height: u32,
     pub mbr: Rect<V, D>,
     pub hash: HashValue,
-    pub entry: Vec<ESMTEntry<V, D, C>>,
+    /// 子树中未删除的空间对象数量，作为聚合值计入父节点的摘要
+    pub count: u64,
+    pub entry: Vec<ESMTEntry<V, D, C, H>>,
+    /// 节点或其子树中有哈希尚未重新计算，参见[`Node::commit`]。子节点为dirty时父节点一定为dirty
+    pub dirty: bool,
 }
 
-pub(crate) enum ESMTEntry<V, const D: usize, const C: usize>
+/// 条目类型与其所在的层不一致时的错误描述
+const EXPECT_NODE: &str = "expect node, find object";
+const EXPECT_OBJECT: &str = "expect object, find node";
+
+pub(crate) enum ESMTEntry<V, const D: usize, const C: usize, H = Sha3_256>
     where
         V: MRTreeDefault,
 {
-    ENode(Node<V, D, C>),
-    Object(ObjectEntry<V, D>)
+    ENode(Node<V, D, C, H>),
+    Object(ObjectEntry<V, D, H>)
 }
 
-impl<V, const D: usize> ObjectEntry<V, D>
+impl<V, const D: usize, H: HashBackend> ObjectEntry<V, D, H>
     where
         V: MRTreeDefault,
 {
-    pub fn new(key: String, loc: [V; D], hash: HashValue) -> Self {
-        Self {
+    pub fn new(key: String, loc: [V; D], value: HashValue) -> Self {
+        Self::with_rect(key, Rect::new_point(loc), value)
+    }
+
+    /// 以矩形作为位置创建空间对象，矩形退化为点时与[`ObjectEntry::new`]相同
+    pub fn with_rect(key: String, loc: Rect<V, D>, value: HashValue) -> Self {
+        Self::from_parts(key, loc, None, value)
+    }
+
+    /// 创建折线对象，树中使用顶点的MBR作为其位置，顶点序列随对象保存。
+    /// 没有顶点时返回`None`
+    pub fn with_polyline(key: String, vertices: Vec<[V; D]>, value: HashValue) -> Option<Self>
+        where
+            V: MRTreeFunc,
+    {
+        let mut loc = Rect::new_point(*vertices.first()?);
+        for v in vertices.iter().skip(1) {
+            loc.expand(&Rect::new_point(*v));
+        }
+        Some(Self::from_parts(key, loc, Some(vertices), value))
+    }
+
+    /// 由各部分直接创建对象，解码时使用。loc与折线顶点的一致性由对象摘要保证
+    pub(crate) fn from_parts(key: String, loc: Rect<V, D>, polyline: Option<Vec<[V; D]>>, value: HashValue) -> Self {
+        let mut obj = Self {
             key,
-            loc: Rect::new_point(loc),
-            hash,
+            loc,
+            polyline,
+            value,
+            hash: HashValue::zero(),
             stale: false,
+            _hasher: PhantomData,
+        };
+        obj.hash = obj.calc_hash();
+        obj
+    }
+
+    /// 根据key、loc、折线顶点和value重新计算对象摘要，验证时不信任对象中保存的摘要。
+    /// 折线的顶点依次写在loc之后，点和矩形对象的摘要只包含loc
+    pub fn calc_hash(&self) -> HashValue {
+        let mut loc = vec![];
+        self.loc.write_bytes(&mut loc);
+        for v in self.polyline.iter().flatten().flatten() {
+            v.write_bytes(&mut loc);
         }
+        leaf_hash::<H>(self.key.as_bytes(), &loc, &self.value)
     }
 
+    /// 对象摘要，作为叶子节点中的条目参与节点哈希计算
     #[inline]
     pub fn hash(&self) -> HashValue {
         self.hash
     }
 
+    /// 对象的值哈希，即插入时传入的哈希
+    #[inline]
+    pub fn value(&self) -> HashValue {
+        self.value
+    }
+
     #[inline]
     pub fn hash_ref(&self) -> &[u8; HashValue::LENGTH] {
         self.hash.as_ref()
@@ -151,14 +814,30 @@ impl<V, const D: usize> ObjectEntry<V, D>
         &self.loc
     }
 
+    /// 折线对象的顶点，点和矩形对象返回`None`
+    #[inline]
+    pub fn polyline(&self) -> Option<&[[V; D]]> {
+        self.polyline.as_deref()
+    }
+
     #[inline]
     pub fn is_stale(&self) -> bool {
         self.stale
     }
 
+    /// 将对象移动到新的位置，折线对象的顶点被丢弃，此后作为矩形对象
     #[inline]
     pub fn update_loc(&mut self, new_loc: Rect<V, D>) {
         self.loc = new_loc;
+        self.polyline = None;
+        self.hash = self.calc_hash();
+    }
+
+    /// 替换对象的值哈希，位置和形状不变
+    #[inline]
+    pub fn update_value(&mut self, value: HashValue) {
+        self.value = value;
+        self.hash = self.calc_hash();
     }
 
     #[inline]
@@ -174,42 +853,42 @@ impl<V, const D: usize> ObjectEntry<V, D>
         self.key == key_2_match
     }
 
+    /// 未被删除且key为key_2_match。删除和移动会在原位置留下同一个key的过期对象，查找时需要跳过
+    #[inline]
+    pub fn is_live(&self, key_2_match: &str) -> bool {
+        !self.stale && self.match_key(key_2_match)
+    }
+
     #[inline]
     pub fn key(&self) -> String {
         self.key.clone()
     }
 }
 
-// todo: 返回Result，进行错误处理
-impl<V, const D: usize, const C: usize> ESMTEntry<V, D, C>
+impl<V, const D: usize, const C: usize, H: HashBackend> ESMTEntry<V, D, C, H>
     where
         V: MRTreeDefault,
 {
-    pub fn is_node(&self) -> bool {
-        if let Self::ENode(_) = self {
-            return true;
-        }
-        false
-    }
-
-    pub fn is_object(&self) -> bool {
-        if let Self::Object(_) = self {
-            return true;
-        }
-        false
-    }
-
-    pub fn hash_ref(&self) -> &[u8; HashValue::LENGTH] {
+    /// 该条目在父节点摘要中的承诺
+    pub fn record(&self) -> Vec<u8> {
         match self {
             ESMTEntry::ENode(n) => {
-                n.hash_ref()
+                entry_record(&n.hash, &n.mbr, Some(n.height), n.count)
             }
             ESMTEntry::Object(o) => {
-                o.hash_ref()
+                entry_record(&o.hash, &o.loc, None, self.count())
             }
         }
     }
 
+    /// 条目中未删除的空间对象数量
+    pub fn count(&self) -> u64 {
+        match self {
+            ESMTEntry::ENode(n) => n.count,
+            ESMTEntry::Object(o) => u64::from(!o.is_stale()),
+        }
+    }
+
     pub fn mbr(&self) -> &Rect<V, D> {
         match self {
             ESMTEntry::ENode(n) => {
@@ -221,50 +900,50 @@ impl<V, const D: usize, const C: usize> ESMTEntry<V, D, C>
         }
     }
 
-    pub fn unpack_node(self) -> Node<V, D, C> {
-        if let Self::ENode(n) = self {
-            return n;
+    pub fn unpack_node(self) -> Result<Node<V, D, C, H>, EsmtError> {
+        match self {
+            Self::ENode(n) => Ok(n),
+            Self::Object(_) => Err(EsmtError::CorruptedStructure(EXPECT_NODE)),
         }
-        panic!("[ESMTEntry::unpack] expect reference of Node, find ObjectEntry");
     }
 
-    pub fn unpack_object(self) -> ObjectEntry<V, D> {
-        if let Self::Object(obj) = self {
-            return obj;
+    pub fn unpack_object(self) -> Result<ObjectEntry<V, D, H>, EsmtError> {
+        match self {
+            Self::Object(obj) => Ok(obj),
+            Self::ENode(_) => Err(EsmtError::CorruptedStructure(EXPECT_OBJECT)),
         }
-        panic!("[ESMTEntry::unpack] expect ObjectEntry, find reference of Node");
     }
 
-    pub fn get_node(&self) -> &Node<V, D, C> {
-        if let Self::ENode(n) = self {
-            return n;
+    pub fn get_node(&self) -> Result<&Node<V, D, C, H>, EsmtError> {
+        match self {
+            Self::ENode(n) => Ok(n),
+            Self::Object(_) => Err(EsmtError::CorruptedStructure(EXPECT_NODE)),
         }
-        panic!("[ESMTEntry::get] expect reference of Node, find ObjectEntry");
     }
 
-    pub fn get_node_mut(&mut self) -> &mut Node<V, D, C> {
-        if let Self::ENode(n) = self {
-            return n;
+    pub fn get_node_mut(&mut self) -> Result<&mut Node<V, D, C, H>, EsmtError> {
+        match self {
+            Self::ENode(n) => Ok(n),
+            Self::Object(_) => Err(EsmtError::CorruptedStructure(EXPECT_NODE)),
         }
-        panic!("[ESMTEntry::get_mut] expect reference of Node, find ObjectEntry");
     }
 
-    pub fn get_object(&self) -> &ObjectEntry<V, D> {
-        if let Self::Object(obj) = self {
-            return obj;
+    pub fn get_object(&self) -> Result<&ObjectEntry<V, D, H>, EsmtError> {
+        match self {
+            Self::Object(obj) => Ok(obj),
+            Self::ENode(_) => Err(EsmtError::CorruptedStructure(EXPECT_OBJECT)),
         }
-        panic!("[ESMTEntry::get] expect ObjectEntry, find reference of Node");
     }
 
-    pub fn get_object_mut(&mut self) -> &mut ObjectEntry<V, D> {
-        if let Self::Object(obj) = self {
-            return obj;
+    pub fn get_object_mut(&mut self) -> Result<&mut ObjectEntry<V, D, H>, EsmtError> {
+        match self {
+            Self::Object(obj) => Ok(obj),
+            Self::ENode(_) => Err(EsmtError::CorruptedStructure(EXPECT_OBJECT)),
         }
-        panic!("[ESMTEntry::get_mut] expect ObjectEntry, find reference of Node");
     }
 }
 
-impl<V, const D: usize, const C: usize> Node<V, D, C>
+impl<V, const D: usize, const C: usize, H: HashBackend> Node<V, D, C, H>
     where
         V: MRTreeDefault,
 {
@@ -276,7 +955,9 @@ impl<V, const D: usize, const C: usize> Node<V, D, C>
             height,
             mbr: Rect::default(),
             hash: HashValue::default(),
+            count: 0,
             entry: vec![],
+            dirty: false,
         }
     }
 
@@ -285,11 +966,6 @@ impl<V, const D: usize, const C: usize> Node<V, D, C>
         self.hash
     }
 
-    #[inline]
-    fn hash_ref(&self) -> &[u8; HashValue::LENGTH] {
-        self.hash.as_ref()
-    }
-
     #[inline]
     pub fn is_overflow(&self) -> bool {
         self.entry.len() > Self::CAPACITY
@@ -300,16 +976,30 @@ impl<V, const D: usize, const C: usize> Node<V, D, C>
         self.entry.len() < Self::MIN_FANOUT
     }
 
+    /// 重新计算哈希和对象数量
     pub fn rehash(&mut self) {
-        let hash_set = self.entry.iter()
-            .map(|e| e.hash_ref())
-            .collect::<BTreeSet<_>>();
-        let hasher = hash_set
-            .into_iter()
-            .fold(ESMTHasher::default(), |hasher, entry| {
-                hasher.update(entry)
-            });
-        self.hash = hasher.finish();
+        self.hash = node_digest::<H>(self.entry.iter().map(|e| e.record()).collect());
+        self.count = self.entry.iter().map(|e| e.count()).sum();
+    }
+
+    /// 只重新计算对象数量，哈希留到[`Node::commit`]时计算
+    pub fn mark_dirty(&mut self) {
+        self.count = self.entry.iter().map(|e| e.count()).sum();
+        self.dirty = true;
+    }
+
+    /// 自底向上重新计算子树中所有dirty节点的哈希，每个节点只计算一次
+    pub fn commit(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        for ety in self.entry.iter_mut() {
+            if let ESMTEntry::ENode(n) = ety {
+                n.commit();
+            }
+        }
+        self.rehash();
+        self.dirty = false;
     }
 
     #[inline]
@@ -320,12 +1010,10 @@ impl<V, const D: usize, const C: usize> Node<V, D, C>
     pub fn first_stale(&self) -> Option<usize> {
         // assert_eq!(self.height, 0, "first_stale method can only be called on Leaf node");
         for i in 0..self.entry.len() {
-            if self.entry[i].is_object() {
-                if self.entry[i].get_object().is_stale() {
-                    return Some(i);
-                }
-            } else {
-                break;
+            match &self.entry[i] {
+                ESMTEntry::Object(obj) if obj.is_stale() => return Some(i),
+                ESMTEntry::Object(_) => {},
+                ESMTEntry::ENode(_) => break,
             }
         }
         None
@@ -344,13 +1032,10 @@ impl<V, const D: usize, const C: usize> Node<V, D, C>
         while !queue.is_empty() {
             let node = queue.pop_front().unwrap();
             res.push((node.height, node.mbr.clone()));
-            if !(node.height == 0) {
-                for entry in node.entry.iter() {
-                    queue.push_back(entry.get_node());
-                }
-            } else {
-                for entry in node.entry.iter() {
-                    objs.push((entry.get_object().is_stale() ,entry.mbr().clone()))
+            for entry in node.entry.iter() {
+                match entry {
+                    ESMTEntry::ENode(n) => queue.push_back(n),
+                    ESMTEntry::Object(o) => objs.push((o.is_stale(), o.loc().clone())),
                 }
             }
         }
@@ -358,16 +1043,18 @@ impl<V, const D: usize, const C: usize> Node<V, D, C>
     }
 }
 
-impl<V, const D: usize, const C: usize> Node<V, D, C>
+impl<V, const D: usize, const C: usize, H: HashBackend> Node<V, D, C, H>
     where
         V: MRTreeDefault + MRTreeFunc + ToPrimitive + FromPrimitive,
 {
-    pub fn new_with_entry(height: u32, entry: Vec<ESMTEntry<V, D, C>>) -> Self {
+    pub fn new_with_entry(height: u32, entry: Vec<ESMTEntry<V, D, C, H>>) -> Self {
         let mut node = Self {
             height,
             mbr: Rect::default(),
             hash: HashValue::default(),
+            count: 0,
             entry,
+            dirty: false,
         };
         node.recalculate_mbr();
         node
@@ -418,21 +1105,35 @@ impl<V, const D: usize, const C: usize> Node<V, D, C>
         subtree_idx
     }
 
-    pub fn split_by_hilbert_sort(&mut self) -> Node<V, D, C> {
+    /// 按照分裂策略将条目分到两个节点中，返回新节点
+    pub fn split_with(&mut self, policy: &dyn SplitPolicy<V, D>) -> Node<V, D, C, H> {
+        let mbrs = self.entry.iter().map(|e| e.mbr().clone()).collect::<Vec<_>>();
+        let (stay, moved) = policy.split(&mbrs, Self::MIN_FANOUT);
+        let mut entries = self.entry.drain(..).map(Some).collect::<Vec<_>>();
+        self.entry.extend(stay.into_iter().map(|i| entries[i].take().unwrap()));
         let mut new_node = Self::new_with_height(self.height);
-        let areas = self.entry.drain(..).collect::<Vec<_>>();
-        let hilbert_sorter = HilbertSorter::new(&self.mbr);
-        let mut sorted_entry = hilbert_sorter.sort(areas);
-        let cnt_after_split = sorted_entry.len() - Self::MIN_FANOUT;
-        self.entry.extend(sorted_entry.drain(..cnt_after_split));
-        new_node.entry.extend(sorted_entry.into_iter());
-
-        // recalculate mbr
+        new_node.entry.extend(moved.into_iter().map(|i| entries[i].take().unwrap()));
+
+        // recalculate mbr; 两个节点的哈希都留到commit时计算
         self.recalculate_state_after_sort();
         new_node.recalculate_state_after_sort();
         new_node
     }
 
+    /// 取出策略选择强制重插的条目（按策略给出的顺序），并重新计算节点的mbr，节点标记为dirty
+    pub(crate) fn take_for_reinsert(&mut self, policy: &dyn SplitPolicy<V, D>) -> Vec<ESMTEntry<V, D, C, H>> {
+        let mbrs = self.entry.iter().map(|e| e.mbr().clone()).collect::<Vec<_>>();
+        let taken = policy.reinsert(&mbrs, &self.mbr, Self::MIN_FANOUT);
+        if taken.is_empty() {
+            return Vec::new();
+        }
+        let mut entries = self.entry.drain(..).map(Some).collect::<Vec<_>>();
+        let res = taken.into_iter().map(|i| entries[i].take().unwrap()).collect();
+        self.entry.extend(entries.into_iter().flatten());
+        self.recalculate_state_after_sort();
+        res
+    }
+
     pub fn recalculate_mbr(&mut self) {
         if self.entry.is_empty() {
             return;
@@ -444,114 +1145,172 @@ impl<V, const D: usize, const C: usize> Node<V, D, C>
         self.mbr = rect;
     }
 
-    /// 重新计算哈希和mbr
+    /// 重新计算mbr和对象数量并标记为dirty，哈希留到[`Node::commit`]时计算
     pub fn recalculate_state_after_sort(&mut self) {
         if self.entry.is_empty() {
             return;
         }
-        let init_mbr = self.entry[0].mbr().clone();
-        let (hash_set, mbr) = self.entry.iter()
-            .fold((BTreeSet::new(), init_mbr), |(mut set, mut mbr), e| {
-                mbr.expand(e.mbr());
-                set.insert(e.hash_ref());
-                (set, mbr)
-            });
-        let hasher = hash_set.into_iter()
-            .fold(ESMTHasher::default(), |hasher, h| {
-                hasher.update(h)
-            });
-        self.hash = hasher.finish();
-        self.mbr = mbr;
+        self.recalculate_mbr();
+        self.mark_dirty();
     }
 }
 
-const HILBERT_CURVE6: [u16; 4096] = [0,3,4,5,58,59,60,63,64,65,78,79,80,83,84,85,938,939,940,943,944,945,958,959,960,963,964,965,1018,1019,1020,1023,1024,1025,1038,1039,1040,1043,1044,1045,1258,1259,1260,1263,1264,1265,1278,1279,1280,1283,1284,1285,1338,1339,1340,1343,1344,1345,1358,1359,1360,1363,1364,1365,1,2,7,6,57,56,61,62,67,66,77,76,81,82,87,86,937,936,941,942,947,946,957,956,961,962,967,966,1017,1016,1021,1022,1027,1026,1037,1036,1041,1042,1047,1046,1257,1256,1261,1262,1267,1266,1277,1276,1281,1282,1287,1286,1337,1336,1341,1342,1347,1346,1357,1356,1361,1362,1367,1366,14,13,8,9,54,55,50,49,68,71,72,75,94,93,88,89,934,935,930,929,948,951,952,955,974,973,968,969,1014,1015,1010,1009,1028,1031,1032,1035,1054,1053,1048,1049,1254,1255,1250,1249,1268,1271,1272,1275,1294,1293,1288,1289,1334,1335,1330,1329,1348,1351,1352,1355,1374,1373,1368,1369,15,12,11,10,53,52,51,48,69,70,73,74,95,92,91,90,933,932,931,928,949,950,953,954,975,972,971,970,1013,1012,1011,1008,1029,1030,1033,1034,1055,1052,1051,1050,1253,1252,1251,1248,1269,1270,1273,1274,1295,1292,1291,1290,1333,1332,1331,1328,1349,1350,1353,1354,1375,1372,1371,1370,16,17,30,31,32,33,46,47,122,121,118,117,96,99,100,101,922,923,924,927,906,905,902,901,976,977,990,991,992,993,1006,1007,1082,1081,1078,1077,1056,1059,1060,1061,1242,1243,1244,1247,1226,1225,1222,1221,1296,1297,1310,1311,1312,1313,1326,1327,1402,1401,1398,1397,1376,1379,1380,1381,19,18,29,28,35,34,45,44,123,120,119,116,97,98,103,102,921,920,925,926,907,904,903,900,979,978,989,988,995,994,1005,1004,1083,1080,1079,1076,1057,1058,1063,1062,1241,1240,1245,1246,1227,1224,1223,1220,1299,1298,1309,1308,1315,1314,1325,1324,1403,1400,1399,1396,1377,1378,1383,1382,20,23,24,27,36,39,40,43,124,125,114,115,110,109,104,105,918,919,914,913,908,909,898,899,980,983,984,987,996,999,1000,1003,1084,1085,1074,1075,1070,1069,1064,1065,1238,1239,1234,1233,1228,1229,1218,1219,1300,1303,1304,1307,1316,1319,1320,1323,1404,1405,1394,1395,1390,1389,1384,1385,21,22,25,26,37,38,41,42,127,126,113,112,111,108,107,106,917,916,915,912,911,910,897,896,981,982,985,986,997,998,1001,1002,1087,1086,1073,1072,1071,1068,1067,1066,1237,1236,1235,1232,1231,1230,1217,1216,1301,1302,1305,1306,1317,1318,1321,1322,1407,1406,1393,1392,1391,1388,1387,1386,234,233,230,229,218,217,214,213,128,129,142,143,144,147,148,149,874,875,876,879,880,881,894,895,810,809,806,805,794,793,790,789,1088,1091,1092,1093,1146,1147,1148,1151,1152,1155,1156,1157,1210,1211,1212,1215,1514,1513,1510,1509,1498,1497,1494,1493,1408,1409,1422,1423,1424,1427,1428,1429,235,232,231,228,219,216,215,212,131,130,141,140,145,146,151,150,873,872,877,878,883,882,893,892,811,808,807,804,795,792,791,788,1089,1090,1095,1094,1145,1144,1149,1150,1153,1154,1159,1158,1209,1208,1213,1214,1515,1512,1511,1508,1499,1496,1495,1492,1411,1410,1421,1420,1425,1426,1431,1430,236,237,226,227,220,221,210,211,132,135,136,139,158,157,152,153,870,871,866,865,884,887,888,891,812,813,802,803,796,797,786,787,1102,1101,1096,1097,1142,1143,1138,1137,1166,1165,1160,1161,1206,1207,1202,1201,1516,1517,1506,1507,1500,1501,1490,1491,1412,1415,1416,1419,1438,1437,1432,1433,239,238,225,224,223,222,209,208,133,134,137,138,159,156,155,154,869,868,867,864,885,886,889,890,815,814,801,800,799,798,785,784,1103,1100,1099,1098,1141,1140,1139,1136,1167,1164,1163,1162,1205,1204,1203,1200,1519,1518,1505,1504,1503,1502,1489,1488,1413,1414,1417,1418,1439,1436,1435,1434,240,243,244,245,202,203,204,207,186,185,182,181,160,163,164,165,858,859,860,863,842,841,838,837,816,819,820,821,778,779,780,783,1104,1105,1118,1119,1120,1121,1134,1135,1168,1169,1182,1183,1184,1185,1198,1199,1520,1523,1524,1525,1482,1483,1484,1487,1466,1465,1462,1461,1440,1443,1444,1445,241,242,247,246,201,200,205,206,187,184,183,180,161,162,167,166,857,856,861,862,843,840,839,836,817,818,823,822,777,776,781,782,1107,1106,1117,1116,1123,1122,1133,1132,1171,1170,1181,1180,1187,1186,1197,1196,1521,1522,1527,1526,1481,1480,1485,1486,1467,1464,1463,1460,1441,1442,1447,1446,254,253,248,249,198,199,194,193,188,189,178,179,174,173,168,169,854,855,850,849,844,845,834,835,830,829,824,825,774,775,770,769,1108,1111,1112,1115,1124,1127,1128,1131,1172,1175,1176,1179,1188,1191,1192,1195,1534,1533,1528,1529,1478,1479,1474,1473,1468,1469,1458,1459,1454,1453,1448,1449,255,252,251,250,197,196,195,192,191,190,177,176,175,172,171,170,853,852,851,848,847,846,833,832,831,828,827,826,773,772,771,768,1109,1110,1113,1114,1125,1126,1129,1130,1173,1174,1177,1178,1189,1190,1193,1194,1535,1532,1531,1530,1477,1476,1475,1472,1471,1470,1457,1456,1455,1452,1451,1450,256,257,270,271,272,275,276,277,490,491,492,495,496,497,510,511,512,513,526,527,528,531,532,533,746,747,748,751,752,753,766,767,1962,1961,1958,1957,1946,1945,1942,1941,1898,1897,1894,1893,1882,1881,1878,1877,1536,1539,1540,1541,1594,1595,1596,1599,1600,1601,1614,1615,1616,1619,1620,1621,259,258,269,268,273,274,279,278,489,488,493,494,499,498,509,508,515,514,525,524,529,530,535,534,745,744,749,750,755,754,765,764,1963,1960,1959,1956,1947,1944,1943,1940,1899,1896,1895,1892,1883,1880,1879,1876,1537,1538,1543,1542,1593,1592,1597,1598,1603,1602,1613,1612,1617,1618,1623,1622,260,263,264,267,286,285,280,281,486,487,482,481,500,503,504,507,516,519,520,523,542,541,536,537,742,743,738,737,756,759,760,763,1964,1965,1954,1955,1948,1949,1938,1939,1900,1901,1890,1891,1884,1885,1874,1875,1550,1549,1544,1545,1590,1591,1586,1585,1604,1607,1608,1611,1630,1629,1624,1625,261,262,265,266,287,284,283,282,485,484,483,480,501,502,505,506,517,518,521,522,543,540,539,538,741,740,739,736,757,758,761,762,1967,1966,1953,1952,1951,1950,1937,1936,1903,1902,1889,1888,1887,1886,1873,1872,1551,1548,1547,1546,1589,1588,1587,1584,1605,1606,1609,1610,1631,1628,1627,1626,314,313,310,309,288,291,292,293,474,475,476,479,458,457,454,453,570,569,566,565,544,547,548,549,730,731,732,735,714,713,710,709,1968,1971,1972,1973,1930,1931,1932,1935,1904,1907,1908,1909,1866,1867,1868,1871,1552,1553,1566,1567,1568,1569,1582,1583,1658,1657,1654,1653,1632,1635,1636,1637,315,312,311,308,289,290,295,294,473,472,477,478,459,456,455,452,571,568,567,564,545,546,551,550,729,728,733,734,715,712,711,708,1969,1970,1975,1974,1929,1928,1933,1934,1905,1906,1911,1910,1865,1864,1869,1870,1555,1554,1565,1564,1571,1570,1581,1580,1659,1656,1655,1652,1633,1634,1639,1638,316,317,306,307,302,301,296,297,470,471,466,465,460,461,450,451,572,573,562,563,558,557,552,553,726,727,722,721,716,717,706,707,1982,1981,1976,1977,1926,1927,1922,1921,1918,1917,1912,1913,1862,1863,1858,1857,1556,1559,1560,1563,1572,1575,1576,1579,1660,1661,1650,1651,1646,1645,1640,1641,319,318,305,304,303,300,299,298,469,468,467,464,463,462,449,448,575,574,561,560,559,556,555,554,725,724,723,720,719,718,705,704,1983,1980,1979,1978,1925,1924,1923,1920,1919,1916,1915,1914,1861,1860,1859,1856,1557,1558,1561,1562,1573,1574,1577,1578,1663,1662,1649,1648,1647,1644,1643,1642,320,323,324,325,378,379,380,383,384,387,388,389,442,443,444,447,576,579,580,581,634,635,636,639,640,643,644,645,698,699,700,703,1984,1985,1998,1999,2000,2003,2004,2005,1834,1835,1836,1839,1840,1841,1854,1855,1770,1769,1766,1765,1754,1753,1750,1749,1664,1665,1678,1679,1680,1683,1684,1685,321,322,327,326,377,376,381,382,385,386,391,390,441,440,445,446,577,578,583,582,633,632,637,638,641,642,647,646,697,696,701,702,1987,1986,1997,1996,2001,2002,2007,2006,1833,1832,1837,1838,1843,1842,1853,1852,1771,1768,1767,1764,1755,1752,1751,1748,1667,1666,1677,1676,1681,1682,1687,1686,334,333,328,329,374,375,370,369,398,397,392,393,438,439,434,433,590,589,584,585,630,631,626,625,654,653,648,649,694,695,690,689,1988,1991,1992,1995,2014,2013,2008,2009,1830,1831,1826,1825,1844,1847,1848,1851,1772,1773,1762,1763,1756,1757,1746,1747,1668,1671,1672,1675,1694,1693,1688,1689,335,332,331,330,373,372,371,368,399,396,395,394,437,436,435,432,591,588,587,586,629,628,627,624,655,652,651,650,693,692,691,688,1989,1990,1993,1994,2015,2012,2011,2010,1829,1828,1827,1824,1845,1846,1849,1850,1775,1774,1761,1760,1759,1758,1745,1744,1669,1670,1673,1674,1695,1692,1691,1690,336,337,350,351,352,353,366,367,400,401,414,415,416,417,430,431,592,593,606,607,608,609,622,623,656,657,670,671,672,673,686,687,2042,2041,2038,2037,2016,2019,2020,2021,1818,1819,1820,1823,1802,1801,1798,1797,1776,1779,1780,1781,1738,1739,1740,1743,1722,1721,1718,1717,1696,1699,1700,1701,339,338,349,348,355,354,365,364,403,402,413,412,419,418,429,428,595,594,605,604,611,610,621,620,659,658,669,668,675,674,685,684,2043,2040,2039,2036,2017,2018,2023,2022,1817,1816,1821,1822,1803,1800,1799,1796,1777,1778,1783,1782,1737,1736,1741,1742,1723,1720,1719,1716,1697,1698,1703,1702,340,343,344,347,356,359,360,363,404,407,408,411,420,423,424,427,596,599,600,603,612,615,616,619,660,663,664,667,676,679,680,683,2044,2045,2034,2035,2030,2029,2024,2025,1814,1815,1810,1809,1804,1805,1794,1795,1790,1789,1784,1785,1734,1735,1730,1729,1724,1725,1714,1715,1710,1709,1704,1705,341,342,345,346,357,358,361,362,405,406,409,410,421,422,425,426,597,598,601,602,613,614,617,618,661,662,665,666,677,678,681,682,2047,2046,2033,2032,2031,2028,2027,2026,1813,1812,1811,1808,1807,1806,1793,1792,1791,1788,1787,1786,1733,1732,1731,1728,1727,1726,1713,1712,1711,1708,1707,1706,3754,3753,3750,3749,3738,3737,3734,3733,3690,3689,3686,3685,3674,3673,3670,3669,3498,3497,3494,3493,3482,3481,3478,3477,3434,3433,3430,3429,3418,3417,3414,3413,2048,2049,2062,2063,2064,2067,2068,2069,2282,2283,2284,2287,2288,2289,2302,2303,2304,2307,2308,2309,2362,2363,2364,2367,2368,2369,2382,2383,2384,2387,2388,2389,3755,3752,3751,3748,3739,3736,3735,3732,3691,3688,3687,3684,3675,3672,3671,3668,3499,3496,3495,3492,3483,3480,3479,3476,3435,3432,3431,3428,3419,3416,3415,3412,2051,2050,2061,2060,2065,2066,2071,2070,2281,2280,2285,2286,2291,2290,2301,2300,2305,2306,2311,2310,2361,2360,2365,2366,2371,2370,2381,2380,2385,2386,2391,2390,3756,3757,3746,3747,3740,3741,3730,3731,3692,3693,3682,3683,3676,3677,3666,3667,3500,3501,3490,3491,3484,3485,3474,3475,3436,3437,3426,3427,3420,3421,3410,3411,2052,2055,2056,2059,2078,2077,2072,2073,2278,2279,2274,2273,2292,2295,2296,2299,2318,2317,2312,2313,2358,2359,2354,2353,2372,2375,2376,2379,2398,2397,2392,2393,3759,3758,3745,3744,3743,3742,3729,3728,3695,3694,3681,3680,3679,3678,3665,3664,3503,3502,3489,3488,3487,3486,3473,3472,3439,3438,3425,3424,3423,3422,3409,3408,2053,2054,2057,2058,2079,2076,2075,2074,2277,2276,2275,2272,2293,2294,2297,2298,2319,2316,2315,2314,2357,2356,2355,2352,2373,2374,2377,2378,2399,2396,2395,2394,3760,3763,3764,3765,3722,3723,3724,3727,3696,3699,3700,3701,3658,3659,3660,3663,3504,3507,3508,3509,3466,3467,3468,3471,3440,3443,3444,3445,3402,3403,3404,3407,2106,2105,2102,2101,2080,2083,2084,2085,2266,2267,2268,2271,2250,2249,2246,2245,2320,2321,2334,2335,2336,2337,2350,2351,2426,2425,2422,2421,2400,2403,2404,2405,3761,3762,3767,3766,3721,3720,3725,3726,3697,3698,3703,3702,3657,3656,3661,3662,3505,3506,3511,3510,3465,3464,3469,3470,3441,3442,3447,3446,3401,3400,3405,3406,2107,2104,2103,2100,2081,2082,2087,2086,2265,2264,2269,2270,2251,2248,2247,2244,2323,2322,2333,2332,2339,2338,2349,2348,2427,2424,2423,2420,2401,2402,2407,2406,3774,3773,3768,3769,3718,3719,3714,3713,3710,3709,3704,3705,3654,3655,3650,3649,3518,3517,3512,3513,3462,3463,3458,3457,3454,3453,3448,3449,3398,3399,3394,3393,2108,2109,2098,2099,2094,2093,2088,2089,2262,2263,2258,2257,2252,2253,2242,2243,2324,2327,2328,2331,2340,2343,2344,2347,2428,2429,2418,2419,2414,2413,2408,2409,3775,3772,3771,3770,3717,3716,3715,3712,3711,3708,3707,3706,3653,3652,3651,3648,3519,3516,3515,3514,3461,3460,3459,3456,3455,3452,3451,3450,3397,3396,3395,3392,2111,2110,2097,2096,2095,2092,2091,2090,2261,2260,2259,2256,2255,2254,2241,2240,2325,2326,2329,2330,2341,2342,2345,2346,2431,2430,2417,2416,2415,2412,2411,2410,3776,3777,3790,3791,3792,3795,3796,3797,3626,3627,3628,3631,3632,3633,3646,3647,3520,3521,3534,3535,3536,3539,3540,3541,3370,3371,3372,3375,3376,3377,3390,3391,2112,2115,2116,2117,2170,2171,2172,2175,2176,2179,2180,2181,2234,2235,2236,2239,2538,2537,2534,2533,2522,2521,2518,2517,2432,2433,2446,2447,2448,2451,2452,2453,3779,3778,3789,3788,3793,3794,3799,3798,3625,3624,3629,3630,3635,3634,3645,3644,3523,3522,3533,3532,3537,3538,3543,3542,3369,3368,3373,3374,3379,3378,3389,3388,2113,2114,2119,2118,2169,2168,2173,2174,2177,2178,2183,2182,2233,2232,2237,2238,2539,2536,2535,2532,2523,2520,2519,2516,2435,2434,2445,2444,2449,2450,2455,2454,3780,3783,3784,3787,3806,3805,3800,3801,3622,3623,3618,3617,3636,3639,3640,3643,3524,3527,3528,3531,3550,3549,3544,3545,3366,3367,3362,3361,3380,3383,3384,3387,2126,2125,2120,2121,2166,2167,2162,2161,2190,2189,2184,2185,2230,2231,2226,2225,2540,2541,2530,2531,2524,2525,2514,2515,2436,2439,2440,2443,2462,2461,2456,2457,3781,3782,3785,3786,3807,3804,3803,3802,3621,3620,3619,3616,3637,3638,3641,3642,3525,3526,3529,3530,3551,3548,3547,3546,3365,3364,3363,3360,3381,3382,3385,3386,2127,2124,2123,2122,2165,2164,2163,2160,2191,2188,2187,2186,2229,2228,2227,2224,2543,2542,2529,2528,2527,2526,2513,2512,2437,2438,2441,2442,2463,2460,2459,2458,3834,3833,3830,3829,3808,3811,3812,3813,3610,3611,3612,3615,3594,3593,3590,3589,3578,3577,3574,3573,3552,3555,3556,3557,3354,3355,3356,3359,3338,3337,3334,3333,2128,2129,2142,2143,2144,2145,2158,2159,2192,2193,2206,2207,2208,2209,2222,2223,2544,2547,2548,2549,2506,2507,2508,2511,2490,2489,2486,2485,2464,2467,2468,2469,3835,3832,3831,3828,3809,3810,3815,3814,3609,3608,3613,3614,3595,3592,3591,3588,3579,3576,3575,3572,3553,3554,3559,3558,3353,3352,3357,3358,3339,3336,3335,3332,2131,2130,2141,2140,2147,2146,2157,2156,2195,2194,2205,2204,2211,2210,2221,2220,2545,2546,2551,2550,2505,2504,2509,2510,2491,2488,2487,2484,2465,2466,2471,2470,3836,3837,3826,3827,3822,3821,3816,3817,3606,3607,3602,3601,3596,3597,3586,3587,3580,3581,3570,3571,3566,3565,3560,3561,3350,3351,3346,3345,3340,3341,3330,3331,2132,2135,2136,2139,2148,2151,2152,2155,2196,2199,2200,2203,2212,2215,2216,2219,2558,2557,2552,2553,2502,2503,2498,2497,2492,2493,2482,2483,2478,2477,2472,2473,3839,3838,3825,3824,3823,3820,3819,3818,3605,3604,3603,3600,3599,3598,3585,3584,3583,3582,3569,3568,3567,3564,3563,3562,3349,3348,3347,3344,3343,3342,3329,3328,2133,2134,2137,2138,2149,2150,2153,2154,2197,2198,2201,2202,2213,2214,2217,2218,2559,2556,2555,2554,2501,2500,2499,2496,2495,2494,2481,2480,2479,2476,2475,2474,3840,3843,3844,3845,3898,3899,3900,3903,3904,3905,3918,3919,3920,3923,3924,3925,3242,3243,3244,3247,3248,3249,3262,3263,3264,3267,3268,3269,3322,3323,3324,3327,2986,2985,2982,2981,2970,2969,2966,2965,2922,2921,2918,2917,2906,2905,2902,2901,2560,2563,2564,2565,2618,2619,2620,2623,2624,2625,2638,2639,2640,2643,2644,2645,3841,3842,3847,3846,3897,3896,3901,3902,3907,3906,3917,3916,3921,3922,3927,3926,3241,3240,3245,3246,3251,3250,3261,3260,3265,3266,3271,3270,3321,3320,3325,3326,2987,2984,2983,2980,2971,2968,2967,2964,2923,2920,2919,2916,2907,2904,2903,2900,2561,2562,2567,2566,2617,2616,2621,2622,2627,2626,2637,2636,2641,2642,2647,2646,3854,3853,3848,3849,3894,3895,3890,3889,3908,3911,3912,3915,3934,3933,3928,3929,3238,3239,3234,3233,3252,3255,3256,3259,3278,3277,3272,3273,3318,3319,3314,3313,2988,2989,2978,2979,2972,2973,2962,2963,2924,2925,2914,2915,2908,2909,2898,2899,2574,2573,2568,2569,2614,2615,2610,2609,2628,2631,2632,2635,2654,2653,2648,2649,3855,3852,3851,3850,3893,3892,3891,3888,3909,3910,3913,3914,3935,3932,3931,3930,3237,3236,3235,3232,3253,3254,3257,3258,3279,3276,3275,3274,3317,3316,3315,3312,2991,2990,2977,2976,2975,2974,2961,2960,2927,2926,2913,2912,2911,2910,2897,2896,2575,2572,2571,2570,2613,2612,2611,2608,2629,2630,2633,2634,2655,2652,2651,2650,3856,3857,3870,3871,3872,3873,3886,3887,3962,3961,3958,3957,3936,3939,3940,3941,3226,3227,3228,3231,3210,3209,3206,3205,3280,3281,3294,3295,3296,3297,3310,3311,2992,2995,2996,2997,2954,2955,2956,2959,2928,2931,2932,2933,2890,2891,2892,2895,2576,2577,2590,2591,2592,2593,2606,2607,2682,2681,2678,2677,2656,2659,2660,2661,3859,3858,3869,3868,3875,3874,3885,3884,3963,3960,3959,3956,3937,3938,3943,3942,3225,3224,3229,3230,3211,3208,3207,3204,3283,3282,3293,3292,3299,3298,3309,3308,2993,2994,2999,2998,2953,2952,2957,2958,2929,2930,2935,2934,2889,2888,2893,2894,2579,2578,2589,2588,2595,2594,2605,2604,2683,2680,2679,2676,2657,2658,2663,2662,3860,3863,3864,3867,3876,3879,3880,3883,3964,3965,3954,3955,3950,3949,3944,3945,3222,3223,3218,3217,3212,3213,3202,3203,3284,3287,3288,3291,3300,3303,3304,3307,3006,3005,3000,3001,2950,2951,2946,2945,2942,2941,2936,2937,2886,2887,2882,2881,2580,2583,2584,2587,2596,2599,2600,2603,2684,2685,2674,2675,2670,2669,2664,2665,3861,3862,3865,3866,3877,3878,3881,3882,3967,3966,3953,3952,3951,3948,3947,3946,3221,3220,3219,3216,3215,3214,3201,3200,3285,3286,3289,3290,3301,3302,3305,3306,3007,3004,3003,3002,2949,2948,2947,2944,2943,2940,2939,2938,2885,2884,2883,2880,2581,2582,2585,2586,2597,2598,2601,2602,2687,2686,2673,2672,2671,2668,2667,2666,4074,4073,4070,4069,4058,4057,4054,4053,3968,3969,3982,3983,3984,3987,3988,3989,3178,3179,3180,3183,3184,3185,3198,3199,3114,3113,3110,3109,3098,3097,3094,3093,3008,3009,3022,3023,3024,3027,3028,3029,2858,2859,2860,2863,2864,2865,2878,2879,2794,2793,2790,2789,2778,2777,2774,2773,2688,2689,2702,2703,2704,2707,2708,2709,4075,4072,4071,4068,4059,4056,4055,4052,3971,3970,3981,3980,3985,3986,3991,3990,3177,3176,3181,3182,3187,3186,3197,3196,3115,3112,3111,3108,3099,3096,3095,3092,3011,3010,3021,3020,3025,3026,3031,3030,2857,2856,2861,2862,2867,2866,2877,2876,2795,2792,2791,2788,2779,2776,2775,2772,2691,2690,2701,2700,2705,2706,2711,2710,4076,4077,4066,4067,4060,4061,4050,4051,3972,3975,3976,3979,3998,3997,3992,3993,3174,3175,3170,3169,3188,3191,3192,3195,3116,3117,3106,3107,3100,3101,3090,3091,3012,3015,3016,3019,3038,3037,3032,3033,2854,2855,2850,2849,2868,2871,2872,2875,2796,2797,2786,2787,2780,2781,2770,2771,2692,2695,2696,2699,2718,2717,2712,2713,4079,4078,4065,4064,4063,4062,4049,4048,3973,3974,3977,3978,3999,3996,3995,3994,3173,3172,3171,3168,3189,3190,3193,3194,3119,3118,3105,3104,3103,3102,3089,3088,3013,3014,3017,3018,3039,3036,3035,3034,2853,2852,2851,2848,2869,2870,2873,2874,2799,2798,2785,2784,2783,2782,2769,2768,2693,2694,2697,2698,2719,2716,2715,2714,4080,4083,4084,4085,4042,4043,4044,4047,4026,4025,4022,4021,4000,4003,4004,4005,3162,3163,3164,3167,3146,3145,3142,3141,3120,3123,3124,3125,3082,3083,3084,3087,3066,3065,3062,3061,3040,3043,3044,3045,2842,2843,2844,2847,2826,2825,2822,2821,2800,2803,2804,2805,2762,2763,2764,2767,2746,2745,2742,2741,2720,2723,2724,2725,4081,4082,4087,4086,4041,4040,4045,4046,4027,4024,4023,4020,4001,4002,4007,4006,3161,3160,3165,3166,3147,3144,3143,3140,3121,3122,3127,3126,3081,3080,3085,3086,3067,3064,3063,3060,3041,3042,3047,3046,2841,2840,2845,2846,2827,2824,2823,2820,2801,2802,2807,2806,2761,2760,2765,2766,2747,2744,2743,2740,2721,2722,2727,2726,4094,4093,4088,4089,4038,4039,4034,4033,4028,4029,4018,4019,4014,4013,4008,4009,3158,3159,3154,3153,3148,3149,3138,3139,3134,3133,3128,3129,3078,3079,3074,3073,3068,3069,3058,3059,3054,3053,3048,3049,2838,2839,2834,2833,2828,2829,2818,2819,2814,2813,2808,2809,2758,2759,2754,2753,2748,2749,2738,2739,2734,2733,2728,2729,4095,4092,4091,4090,4037,4036,4035,4032,4031,4030,4017,4016,4015,4012,4011,4010,3157,3156,3155,3152,3151,3150,3137,3136,3135,3132,3131,3130,3077,3076,3075,3072,3071,3070,3057,3056,3055,3052,3051,3050,2837,2836,2835,2832,2831,2830,2817,2816,2815,2812,2811,2810,2757,2756,2755,2752,2751,2750,2737,2736,2735,2732,2731,2730,];
-
-pub struct HilbertSorter<V, const D: usize, const C: usize>
+/// kNN最优优先搜索中的候选节点（`node`为`None`时表示空间对象），按与查询点的距离组成小顶堆
+struct KnnCandidate<'a, V, const D: usize, const C: usize, H>
     where
         V: MRTreeDefault,
 {
-    order: usize,
-    step: i32,
-    lowbound: [V; D],
-    range: [V; D],
+    dist: V,
+    node: Option<&'a Node<V, D, C, H>>,
+}
+
+impl<'a, V, const D: usize, const C: usize, H: HashBackend> PartialEq for KnnCandidate<'a, V, D, C, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.dist == other.dist
+    }
+}
+
+impl<'a, V, const D: usize, const C: usize, H: HashBackend> Eq for KnnCandidate<'a, V, D, C, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{}
+
+impl<'a, V, const D: usize, const C: usize, H: HashBackend> PartialOrd for KnnCandidate<'a, V, D, C, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, V, const D: usize, const C: usize, H: HashBackend> Ord for KnnCandidate<'a, V, D, C, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        // 反转比较结果，使`BinaryHeap`成为小顶堆
+        other.dist.partial_cmp(&self.dist).unwrap_or(Ordering::Equal)
+    }
 }
 
-impl<V, const D: usize, const C: usize> HilbertSorter<V, D, C>
+/// 从若干根节点开始最优优先搜索，返回距离point最近的（至多）k个对象的距离，升序排列
+pub(crate) fn knn_dists<'a, V, H: HashBackend, const D: usize, const C: usize>(roots: impl IntoIterator<Item = &'a Node<V, D, C, H>>, point: &Rect<V, D>, k: usize) -> Vec<V>
     where
-        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+        V: MRTreeDefault + MRTreeFunc + 'a,
 {
-    pub fn new(area: &Rect<V, D>) -> Self {
-        let mut range = [V::default(); D];
-        for i in 0..D {
-            range[i] = area._max[i] - area._min[i];
+    let mut heap = BinaryHeap::new();
+    for root in roots {
+        heap.push(KnnCandidate { dist: root.mbr.rect_dist(point), node: Some(root) });
+    }
+    let mut dists = Vec::with_capacity(k);
+    while dists.len() < k {
+        match heap.pop() {
+            None => break,
+            Some(KnnCandidate { dist, node: None }) => dists.push(dist),
+            Some(KnnCandidate { node: Some(node), .. }) => {
+                // 已删除的对象不是候选结果
+                for ety in node.entry.iter().filter(|e| e.count() > 0) {
+                    let child = match ety {
+                        ESMTEntry::ENode(n) => Some(n),
+                        ESMTEntry::Object(_) => None,
+                    };
+                    heap.push(KnnCandidate { dist: ety.mbr().rect_dist(point), node: child });
+                }
+            },
         }
-        Self {
-            order: 6,
-            step: 64,
-            lowbound: area._min.clone(),
-            range,
-        }
-    }
-
-    pub fn hilbert_idx(&self, obj: &Rect<V, D>) -> u16 {
-        assert_eq!(D, 2, "only support 2-D now!");
-        let step = V::from_i32(self.step);
-        let obj_c = Self::center(obj);
-        let mut x = (((obj_c[0] - self.lowbound[0]) * step) / self.range[0]).to_usize();
-        let mut y = (((obj_c[1] - self.lowbound[1]) * step) / self.range[1]).to_usize();
-        x = x - (x >> self.order);
-        y = y - (y >> self.order);
-        let idx = (y << self.order) | x;
-        // if idx >= 64 {
-        //     println!("lowbound: {:?}, range: {:?}, center: {:?}", self.lowbound, self.range, obj_c);
-        // }
-        HILBERT_CURVE6[idx]
-    }
-
-    pub(crate) fn sort(&self, v: Vec<ESMTEntry<V, D, C>>) -> Vec<ESMTEntry<V, D, C>> {
-        // calculate hilebert index
-        let mut indexed = v.into_iter()
-            .map(|e| (self.hilbert_idx(e.mbr()), e))
-            .collect::<Vec<_>>();
-        // sort
-        indexed.sort_by(|a, b| a.0.cmp(&b.0));
-        // discard index
-        indexed.into_iter()
-            .map(|(_hi, e)| {
-                // println!("{}, loc: {:?}", hi, center(e.mbr()));
-                e
-            })
-            .collect()
-    }
-
-    fn center(rect: &Rect<V, D>) -> [V; D] {
-        let mut c = [V::default(); D];
-        for i in 0..D {
-            c[i] = (rect._max[i] + rect._min[i]) / (V::from_i32(2));
-        }
-        c
-    }
-}
-
-#[cfg(test)]
-mod test {
-    use super::HilbertSorter;
-    use crate::node::Rect;
-
-    #[test]
-    fn test_hilbert_idx() {
-        let hilbert_sorter: HilbertSorter<f64, 2, 4> = HilbertSorter::new(&Rect::new([0.0f64, 0.0f64], [64.0f64, 64.0f64]));
-        let points = vec![
-            [0.0f64, 1.7],
-            [35.189, 64.0],
-            [64.0, 29.3742],
-            [26.6746, 0.0],
-            [63.12, 12.10342],
-        ];
-        for p in points {
-            let idx = hilbert_sorter.hilbert_idx(&Rect::new_point(p.clone()));
-            println!("point: {:?} = {}", p, idx);
-        }
-    }
-}
\ No newline at end of file
+    }
+    dists
+}
+
+/// kNN查询的剪枝界：第k近对象的距离，以及距离恰好等于该距离、尚未输出的结果数
+pub(crate) struct KnnBound<V> {
+    dist: V,
+    ties: usize,
+}
+
+impl<V> KnnBound<V>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    /// 找到的对象不足k个时没有剪枝界，需要返回所有对象；k为0时剪掉所有条目
+    pub fn new(dists: &[V], k: usize) -> Option<Self> {
+        if k == 0 {
+            return Some(Self { dist: V::default(), ties: 0 });
+        }
+        if dists.len() < k {
+            return None;
+        }
+        let dist = dists[k - 1];
+        let ties = dists.iter().filter(|d| **d == dist).count();
+        Some(Self { dist, ties })
+    }
+
+    /// 距离为dist的节点或分区中是否可能含有结果
+    #[inline]
+    pub fn reachable(&self, dist: V) -> bool {
+        dist < self.dist || (dist == self.dist && self.ties > 0)
+    }
+
+    /// 距离为dist的对象是否作为结果输出
+    pub fn take(&mut self, dist: V) -> bool {
+        if dist < self.dist {
+            true
+        } else if dist == self.dist && self.ties > 0 {
+            self.ties -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<V, const D: usize, const C: usize, H: HashBackend> Node<V, D, C, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    /// 生成kNN查询的VO。bound为`None`时不剪枝，所有对象都作为结果；否则只展开可能含有结果的子节点，
+    /// 其余子节点和对象作为兄弟节点输出，验证时它们与查询点的距离都不小于第k近对象的距离
+    pub(crate) fn knn_query(&self, point: &Rect<V, D>, bound: &mut Option<KnnBound<V>>) -> Result<VerifyObject<V, D, H>, EsmtError> {
+        let mut vo = VerifyObject::new();
+        vo.push(VerifyObjectEntry::LevelBegin);
+        for ety in self.entry.iter() {
+            let dist = ety.mbr().rect_dist(point);
+            if self.height == 0 {
+                let obj = ety.get_object()?;
+                if ety.count() > 0 && bound.as_mut().is_none_or(|b| b.take(dist)) {
+                    vo.push(VerifyObjectEntry::Target(obj.clone()));
+                } else {
+                    vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(obj)));
+                }
+            } else if bound.as_ref().is_none_or(|b| b.reachable(dist)) {
+                vo.extend(ety.get_node()?.knn_query(point, bound)?);
+            } else {
+                vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(ety.get_node()?)));
+            }
+        }
+        vo.push(VerifyObjectEntry::LevlEnd);
+        Ok(vo)
+    }
+
+    /// 生成聚合（COUNT）范围查询的VO：被查询完全包含或与查询不相交的子树和对象不再展开，
+    /// 作为带有对象数量的兄弟节点输出，只展开与查询边界相交的节点
+    pub(crate) fn count_query<Q: QueryShape<V, D>>(&self, query: &Q) -> VerifyObject<V, D, H> {
+        let mut vo = VerifyObject::new();
+        vo.push(VerifyObjectEntry::LevelBegin);
+        for ety in self.entry.iter() {
+            match ety {
+                ESMTEntry::ENode(n) if n.count > 0 && query.intersects(&n.mbr) && !query.contains(&n.mbr) => {
+                    vo.extend(n.count_query(query));
+                },
+                ESMTEntry::ENode(n) => vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(n))),
+                ESMTEntry::Object(o) => vo.push(VerifyObjectEntry::Sibling(SiblingObject::from(o))),
+            }
+        }
+        vo.push(VerifyObjectEntry::LevlEnd);
+        vo
+    }
+}
@@ -1,5 +1,7 @@
+use std::cmp::Ordering;
 use std::fmt::{Debug, Display, Formatter};
 use std::ops::{Add, Div, Mul, Sub};
+use crate::node::ToBytes;
 
 /// 自定义类型V的D维的矩形
 ///
@@ -166,32 +168,32 @@ where
         }
         let mut dist = zero;
         for i in 0..D {
-            let d = max(self._min[i], rect._min[i]) - min(self._max[i], rect._max[i]);
-            if d > zero {
+            // 先比较再相减，避免无符号类型下溢
+            let (lo, hi) = (min(self._max[i], rect._max[i]), max(self._min[i], rect._min[i]));
+            if hi > lo {
+                let d = hi - lo;
                 dist = dist + (d * d);
             }
         }
         dist
     }
 
-    /// 计算两个矩形的重叠测度，不重叠时返回V的默认值
+    /// 计算两个矩形的重叠测度，不重叠时返回V的默认值。
+    /// 先比较再相减，避免无符号类型下溢；坐标为NaN时无法比较，同样视为不重叠
     pub fn overlap_area(&self, rect: &Rect<V, D>) -> V {
         let zero = V::default();
-        if D == 0 {
-            return zero;
-        }
-        let mut area = min(self._max[0], rect._max[0]) - max(self._min[0], rect._min[0]);
-        if !(area > zero) {
-            return zero;
-        }
-        for i in 1..D {
-            let d = min(self._max[i], rect._max[i]) - max(self._min[i], rect._min[i]);
-            if !(d > zero) {
-                return zero;
+        let mut area = None;
+        for i in 0..D {
+            let (lo, hi) = (max(self._min[i], rect._min[i]), min(self._max[i], rect._max[i]));
+            match hi.partial_cmp(&lo) {
+                Some(Ordering::Greater) => {
+                    let d = hi - lo;
+                    area = Some(area.map_or(d, |a| a * d));
+                }
+                _ => return zero,
             }
-            area = area * d;
         }
-        area
+        area.unwrap_or(zero)
     }
 
     /// 输出信息
@@ -200,6 +202,18 @@ where
     }
 }
 
+impl<V, const D: usize> Rect<V, D>
+where
+    V: Default + Debug + Copy + ToBytes,
+{
+    /// 规范字节编码：依次写入各维度的最小值和最大值
+    pub fn write_bytes(&self, buf: &mut Vec<u8>) {
+        for v in self._min.iter().chain(self._max.iter()) {
+            v.write_bytes(buf);
+        }
+    }
+}
+
 impl<V, const D: usize> Default for Rect<V, D> 
 where
     V: Default + Debug + Copy,
@@ -215,4 +229,387 @@ where
 unsafe impl<V, const D: usize> Send for Rect<V, D>
 where
     V: Default + Debug + Copy + Send,
-{}
\ No newline at end of file
+{}
+
+/// 范围查询的查询形状。树和验证者只需要判断查询与矩形（节点的MBR或对象的位置）的关系：
+/// 与查询不相交的子树被剪枝，被查询包含的对象是查询结果
+pub trait QueryShape<V, const D: usize>
+where
+    V: Default + Debug + Copy,
+{
+    /// 判断查询与D维矩形rect是否相交，包含边界
+    fn intersects(&self, rect: &Rect<V, D>) -> bool;
+
+    /// 判断查询是否包含D维矩形rect，包含边界
+    fn contains(&self, rect: &Rect<V, D>) -> bool;
+}
+
+impl<V, const D: usize> QueryShape<V, D> for Rect<V, D>
+where
+    V: Default + Debug + Copy,
+    V: PartialOrd + Sub<Output=V> + Add<Output=V> + Mul<Output=V> + Div<Output=V>,
+{
+    #[inline]
+    fn intersects(&self, rect: &Rect<V, D>) -> bool {
+        Rect::intersects(self, rect)
+    }
+
+    #[inline]
+    fn contains(&self, rect: &Rect<V, D>) -> bool {
+        Rect::contains(self, rect)
+    }
+}
+
+/// D维的圆（球），用于查询与中心点距离不超过半径的对象。包含边界
+///
+/// ```rust
+/// use authentic_rtree::shape::{Circle, QueryShape, Rect};
+/// let circle = Circle::new([0.0f64, 0.0], 5.0);
+/// assert!(circle.contains(&Rect::new([0.0, 0.0], [3.0, 4.0])));
+/// assert!(circle.intersects(&Rect::new([3.0, 3.0], [6.0, 6.0])));
+/// assert!(!circle.intersects(&Rect::new([4.0, 4.0], [6.0, 6.0])));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Circle<V, const D: usize>
+where
+    V: Default + Debug + Copy,
+{
+    center: [V; D],
+    radius: V,
+}
+
+impl<V, const D: usize> Circle<V, D>
+where
+    V: Default + Debug + Copy,
+    V: PartialOrd + Sub<Output=V> + Add<Output=V> + Mul<Output=V> + Div<Output=V>,
+{
+    pub fn new(center: [V; D], radius: V) -> Self {
+        Self {
+            center,
+            radius,
+        }
+    }
+
+    #[inline]
+    pub fn center(&self) -> &[V; D] {
+        &self.center
+    }
+
+    #[inline]
+    pub fn radius(&self) -> V {
+        self.radius
+    }
+
+    /// 中心点到矩形上最远点的距离，距离为欧氏距离的平方
+    fn farthest_dist(&self, rect: &Rect<V, D>) -> V {
+        let mut dist = V::default();
+        for i in 0..D {
+            let d = max(signed_sub(self.center[i], rect._min[i]).1, signed_sub(self.center[i], rect._max[i]).1);
+            dist = dist + d * d;
+        }
+        dist
+    }
+}
+
+impl<V, const D: usize> QueryShape<V, D> for Circle<V, D>
+where
+    V: Default + Debug + Copy,
+    V: PartialOrd + Sub<Output=V> + Add<Output=V> + Mul<Output=V> + Div<Output=V>,
+{
+    /// 矩形上离中心点最近的点在圆内
+    fn intersects(&self, rect: &Rect<V, D>) -> bool {
+        rect.rect_dist(&Rect::new_point(self.center)) <= self.radius * self.radius
+    }
+
+    /// 矩形上离中心点最远的点在圆内
+    fn contains(&self, rect: &Rect<V, D>) -> bool {
+        self.farthest_dist(rect) <= self.radius * self.radius
+    }
+}
+
+/// 二维多边形，用于地理围栏等任意形状的范围查询。
+/// 顶点按顺序首尾相连，不需要重复第一个顶点；多边形应当是简单多边形，
+/// 自相交时按非零环绕规则判断内外。包含边界
+///
+/// ```rust
+/// use authentic_rtree::shape::{Polygon, QueryShape, Rect};
+/// let triangle = Polygon::new(vec![[0.0f64, 0.0], [4.0, 0.0], [0.0, 4.0]]);
+/// assert!(triangle.contains(&Rect::new([0.5, 0.5], [1.5, 1.5])));
+/// assert!(triangle.intersects(&Rect::new([1.5, 1.5], [3.0, 3.0])));
+/// assert!(!triangle.intersects(&Rect::new([3.0, 3.0], [4.0, 4.0])));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Polygon<V>
+where
+    V: Default + Debug + Copy,
+{
+    vertices: Vec<[V; 2]>,
+    mbr: Rect<V, 2>,
+}
+
+impl<V> Polygon<V>
+where
+    V: Default + Debug + Copy,
+    V: PartialOrd + Sub<Output=V> + Add<Output=V> + Mul<Output=V> + Div<Output=V>,
+{
+    /// 由顶点序列构造多边形，不足三个顶点时退化为线段、点或空集
+    pub fn new(vertices: Vec<[V; 2]>) -> Self {
+        let mut mbr = vertices.first().map(|v| Rect::new_point(*v)).unwrap_or_default();
+        for v in vertices.iter().skip(1) {
+            mbr.expand(&Rect::new_point(*v));
+        }
+        Self {
+            vertices,
+            mbr,
+        }
+    }
+
+    #[inline]
+    pub fn vertices(&self) -> &[[V; 2]] {
+        &self.vertices
+    }
+
+    /// 多边形的最小外接矩形，多边形为空时为默认值
+    #[inline]
+    pub fn mbr(&self) -> &Rect<V, 2> {
+        &self.mbr
+    }
+
+    /// 多边形的边，最后一条边连接最后一个顶点和第一个顶点
+    fn edges(&self) -> impl Iterator<Item = (&[V; 2], &[V; 2])> {
+        let next = self.vertices.iter().cycle().skip(1);
+        self.vertices.iter().zip(next)
+    }
+
+    /// 判断点是否在多边形内或边界上。使用环绕数，只需要比较和乘法，整数坐标下没有舍入误差
+    pub fn contains_point(&self, point: &[V; 2]) -> bool {
+        if self.vertices.is_empty() || !self.mbr.contains(&Rect::new_point(*point)) {
+            return false;
+        }
+        let mut winding = 0i64;
+        for (a, b) in self.edges() {
+            if on_segment(point, a, b) {
+                return true;
+            }
+            if a[1] <= point[1] {
+                if b[1] > point[1] && orientation(a, b, point) == Ordering::Greater {
+                    winding += 1;
+                }
+            } else if b[1] <= point[1] && orientation(a, b, point) == Ordering::Less {
+                winding -= 1;
+            }
+        }
+        winding != 0
+    }
+}
+
+impl<V> QueryShape<V, 2> for Polygon<V>
+where
+    V: Default + Debug + Copy,
+    V: PartialOrd + Sub<Output=V> + Add<Output=V> + Mul<Output=V> + Div<Output=V>,
+{
+    /// 矩形的顶点在多边形内，或多边形的顶点在矩形内，或两者的边相交
+    fn intersects(&self, rect: &Rect<V, 2>) -> bool {
+        if self.vertices.is_empty() || !self.mbr.intersects(rect) {
+            return false;
+        }
+        let corners = corners(rect);
+        if corners.iter().any(|c| self.contains_point(c)) ||
+            self.vertices.iter().any(|v| rect.contains(&Rect::new_point(*v))) {
+            return true;
+        }
+        self.edges().any(|(a, b)| {
+            (0..4).any(|i| segments_intersect(a, b, &corners[i], &corners[(i + 1) % 4]))
+        })
+    }
+
+    /// 矩形的顶点都在多边形内，且多边形的边界不穿过矩形内部。
+    /// 矩形退化为线段时，多边形的顶点落在线段中间也视为不包含，结果偏保守
+    fn contains(&self, rect: &Rect<V, 2>) -> bool {
+        if self.vertices.is_empty() || !self.mbr.contains(rect) {
+            return false;
+        }
+        let corners = corners(rect);
+        if !corners.iter().all(|c| self.contains_point(c)) {
+            return false;
+        }
+        let flat = [rect._max[0] > rect._min[0], rect._max[1] > rect._min[1]];
+        match flat {
+            [true, true] => !self.edges().any(|(a, b)| crosses_interior(a, b, rect)),
+            [false, false] => true,
+            _ => {
+                let (lo, hi) = (&rect._min, &rect._max);
+                !self.edges().any(|(a, b)| {
+                    crosses_properly(a, b, lo, hi) || (on_segment(a, lo, hi) && a != lo && a != hi)
+                })
+            },
+        }
+    }
+}
+
+/// 二维矩形的四个顶点，按逆时针顺序
+fn corners<V>(rect: &Rect<V, 2>) -> [[V; 2]; 4]
+where
+    V: Default + Debug + Copy,
+{
+    [
+        [rect._min[0], rect._min[1]],
+        [rect._max[0], rect._min[1]],
+        [rect._max[0], rect._max[1]],
+        [rect._min[0], rect._max[1]],
+    ]
+}
+
+/// 计算a-b，返回（是否为负，绝对值）。先比较再相减，避免无符号类型下溢
+fn signed_sub<V>(a: V, b: V) -> (bool, V)
+where
+    V: PartialOrd + Sub<Output=V>,
+{
+    if a < b {
+        (true, b - a)
+    } else {
+        (false, a - b)
+    }
+}
+
+/// 比较两个用（是否为负，绝对值）表示的数
+fn signed_cmp<V>(a: (bool, V), b: (bool, V)) -> Ordering
+where
+    V: Default + PartialOrd,
+{
+    let zero = V::default();
+    let a_neg = a.0 && a.1 > zero;
+    let b_neg = b.0 && b.1 > zero;
+    match (a_neg, b_neg) {
+        (false, false) => a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal),
+        (true, true) => b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal),
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+    }
+}
+
+/// 向量ab与ac叉积的符号：`Greater`表示c在ab左侧，`Less`表示在右侧，`Equal`表示三点共线
+fn orientation<V>(a: &[V; 2], b: &[V; 2], c: &[V; 2]) -> Ordering
+where
+    V: Default + Copy + PartialOrd + Sub<Output=V> + Mul<Output=V>,
+{
+    let (n1, x1) = signed_sub(b[0], a[0]);
+    let (n2, y2) = signed_sub(c[1], a[1]);
+    let (n3, y1) = signed_sub(b[1], a[1]);
+    let (n4, x2) = signed_sub(c[0], a[0]);
+    signed_cmp((n1 != n2, x1 * y2), (n3 != n4, y1 * x2))
+}
+
+/// 判断点p是否在线段ab上，包含端点
+fn on_segment<V>(p: &[V; 2], a: &[V; 2], b: &[V; 2]) -> bool
+where
+    V: Default + Copy + PartialOrd + Sub<Output=V> + Mul<Output=V>,
+{
+    (0..2).all(|i| {
+        let (lo, hi) = if a[i] < b[i] { (a[i], b[i]) } else { (b[i], a[i]) };
+        lo <= p[i] && p[i] <= hi
+    }) && orientation(a, b, p) == Ordering::Equal
+}
+
+/// 判断线段ab与cd是否在各自的内部严格交叉
+fn crosses_properly<V>(a: &[V; 2], b: &[V; 2], c: &[V; 2], d: &[V; 2]) -> bool
+where
+    V: Default + Copy + PartialOrd + Sub<Output=V> + Mul<Output=V>,
+{
+    let opposite = |x: Ordering, y: Ordering| x != Ordering::Equal && y != Ordering::Equal && x != y;
+    opposite(orientation(a, b, c), orientation(a, b, d)) && opposite(orientation(c, d, a), orientation(c, d, b))
+}
+
+/// 判断闭线段ab与cd是否相交
+fn segments_intersect<V>(a: &[V; 2], b: &[V; 2], c: &[V; 2], d: &[V; 2]) -> bool
+where
+    V: Default + Copy + PartialOrd + Sub<Output=V> + Mul<Output=V>,
+{
+    crosses_properly(a, b, c, d) ||
+        on_segment(c, a, b) || on_segment(d, a, b) ||
+        on_segment(a, c, d) || on_segment(b, c, d)
+}
+
+/// 判断线段ab是否经过矩形的内部（不含边界）。
+/// 分离轴只有两个坐标轴和ab的法向：线段的投影与矩形内部在坐标轴上严格重叠，
+/// 且ab所在直线把矩形的顶点严格分在两侧
+fn crosses_interior<V>(a: &[V; 2], b: &[V; 2], rect: &Rect<V, 2>) -> bool
+where
+    V: Default + Copy + Debug + PartialOrd + Sub<Output=V> + Mul<Output=V>,
+{
+    for i in 0..2 {
+        let (lo, hi) = if a[i] < b[i] { (a[i], b[i]) } else { (b[i], a[i]) };
+        if hi <= rect._min[i] || lo >= rect._max[i] {
+            return false;
+        }
+    }
+    let sides = corners(rect).map(|c| orientation(a, b, &c));
+    sides.contains(&Ordering::Greater) && sides.contains(&Ordering::Less)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::shape::{Circle, Polygon, QueryShape, Rect};
+
+    #[test]
+    fn test_polygon() {
+        // 凹多边形：右上角有一个缺口
+        let poly = Polygon::new(vec![[0usize, 0], [8, 0], [8, 4], [4, 4], [4, 8], [0, 8]]);
+        assert!(poly.contains_point(&[2, 6]));
+        assert!(poly.contains_point(&[8, 2]));
+        assert!(poly.contains_point(&[4, 4]));
+        assert!(!poly.contains_point(&[6, 6]));
+        assert!(!poly.contains_point(&[9, 1]));
+
+        assert!(poly.contains(&Rect::new([0, 0], [8, 4])));
+        assert!(poly.contains(&Rect::new([1, 1], [4, 8])));
+        assert!(poly.contains(&Rect::new_point([3, 7])));
+        // 顶点都在多边形内，但缺口穿过矩形内部
+        assert!(!poly.contains(&Rect::new([2, 2], [6, 6])));
+        assert!(poly.intersects(&Rect::new([2, 2], [6, 6])));
+        assert!(poly.intersects(&Rect::new([4, 4], [7, 7])));
+        assert!(!poly.intersects(&Rect::new([5, 5], [7, 7])));
+        assert!(!poly.intersects(&Rect::new([9, 0], [10, 8])));
+        // 矩形包含整个多边形
+        assert!(poly.intersects(&Rect::new([0, 0], [10, 10])));
+        assert!(!poly.contains(&Rect::new([0, 0], [10, 10])));
+        // 退化为线段的矩形经过缺口的顶点
+        assert!(!poly.contains(&Rect::new([2, 4], [6, 4])));
+        assert!(poly.contains(&Rect::new([2, 4], [4, 4])));
+
+        // 三角形与浮点坐标，矩形和多边形的边交叉但顶点互不包含
+        let tri = Polygon::new(vec![[0.0f64, 0.0], [10.0, 0.0], [5.0, 10.0]]);
+        assert!(tri.intersects(&Rect::new([-1.0, 2.0], [11.0, 3.0])));
+        assert!(!tri.contains(&Rect::new([-1.0, 2.0], [11.0, 3.0])));
+        assert!(!tri.intersects(&Rect::new([0.0, 5.0], [2.0, 10.0])));
+        assert!(!Polygon::<f64>::new(vec![]).intersects(&Rect::new([0.0, 0.0], [1.0, 1.0])));
+    }
+
+    #[test]
+    fn test_overlap_area() {
+        let r = Rect::new([0.0f64, 0.0], [4.0, 4.0]);
+        assert_eq!(r.overlap_area(&Rect::new([2.0, 1.0], [6.0, 3.0])), 4.0);
+        // 只相接或不相交时为0
+        assert_eq!(r.overlap_area(&Rect::new([4.0, 0.0], [6.0, 4.0])), 0.0);
+        assert_eq!(r.overlap_area(&Rect::new([5.0, 5.0], [6.0, 6.0])), 0.0);
+        // 无符号坐标不相交时不会下溢
+        assert_eq!(Rect::new([0u32, 0], [2, 2]).overlap_area(&Rect::new([3, 3], [5, 5])), 0);
+        // NaN坐标视为不重叠
+        assert_eq!(r.overlap_area(&Rect::new([f64::NAN, 0.0], [2.0, 2.0])), 0.0);
+    }
+
+    #[test]
+    fn test_circle() {
+        let circle = Circle::new([5usize, 5, 5], 3);
+        assert!(circle.intersects(&Rect::new_point([5, 5, 8])));
+        assert!(!circle.intersects(&Rect::new_point([7, 7, 7])));
+        assert!(circle.intersects(&Rect::new([0, 0, 0], [2, 5, 5])));
+        assert!(!circle.intersects(&Rect::new([0, 0, 0], [2, 2, 2])));
+        // 矩形包含整个圆
+        assert!(circle.intersects(&Rect::new([0, 0, 0], [10, 10, 10])));
+        assert!(!circle.contains(&Rect::new([0, 0, 0], [10, 10, 10])));
+        assert!(circle.contains(&Rect::new([4, 4, 4], [6, 6, 6])));
+        assert!(!circle.contains(&Rect::new([3, 3, 3], [7, 7, 7])));
+        assert!(circle.contains(&Rect::new([5, 5, 2], [5, 5, 8])));
+    }
+}
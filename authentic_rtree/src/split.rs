@@ -0,0 +1,461 @@
+//! 节点溢出时的处理策略。树在创建时选择策略，默认使用希尔伯特排序分裂
+
+use std::cmp::Ordering;
+use crate::curve::Curve;
+use types::hash_value::HashBackend;
+use crate::node::{ESMTEntry, FromPrimitive, MRTreeDefault, MRTreeFunc, Node, ToPrimitive};
+use crate::shape::Rect;
+
+/// 节点分裂策略。策略只看到溢出节点中各条目的MBR，返回两组条目的下标
+pub trait SplitPolicy<V, const D: usize>: Send + Sync
+    where
+        V: MRTreeDefault,
+{
+    /// 将条目分为两组，第一组留在原节点，第二组移入新节点。
+    /// 两组的下标合起来恰好覆盖所有条目，并且每组至少有`min_fanout`个条目；组内的顺序即节点中条目的顺序
+    fn split(&self, mbrs: &[Rect<V, D>], min_fanout: usize) -> (Vec<usize>, Vec<usize>);
+
+    /// 溢出时先强制重插的条目下标（R*树），返回空表示直接分裂。
+    /// 一次插入过程中每一层最多重插一次，根节点总是直接分裂
+    fn reinsert(&self, _mbrs: &[Rect<V, D>], _node_mbr: &Rect<V, D>, _min_fanout: usize) -> Vec<usize> {
+        Vec::new()
+    }
+}
+
+/// 按照条目中心的希尔伯特序排序，最后`min_fanout`个条目移入新节点
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HilbertSplit;
+
+/// 与[`HilbertSplit`]相同，但按指定的空间填充曲线排序
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CurveSplit(pub Curve);
+
+/// Guttman的二次分裂：选择合并后浪费面积最大的两个条目作为种子，每次分配扩张差异最大的条目
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuadraticSplit;
+
+/// Guttman的线性分裂：选择归一化分离度最大的两个条目作为种子，其余条目按顺序分配
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinearSplit;
+
+/// R*树的拓扑分裂：选择边长之和最小的轴，再选择重叠最小的分割位置；
+/// 溢出时先把距离节点中心最远的`reinsert_ratio`比例的条目重新插入
+#[derive(Debug, Clone, Copy)]
+pub struct RStarSplit {
+    reinsert_ratio: f64,
+}
+
+impl RStarSplit {
+    pub fn new(reinsert_ratio: f64) -> Self {
+        assert!((0.0..1.0).contains(&reinsert_ratio), "reinsert ratio must be in [0, 1)");
+        Self {
+            reinsert_ratio,
+        }
+    }
+
+    #[inline]
+    pub fn reinsert_ratio(&self) -> f64 {
+        self.reinsert_ratio
+    }
+}
+
+impl Default for RStarSplit {
+    /// 论文中推荐的30%
+    fn default() -> Self {
+        Self::new(0.3)
+    }
+}
+
+/// 以f64表示的矩形，避免无符号坐标相减时下溢
+#[derive(Debug, Clone, Copy)]
+struct Bounds<const D: usize> {
+    min: [f64; D],
+    max: [f64; D],
+}
+
+impl<const D: usize> Bounds<D> {
+    fn from_rect<V: MRTreeDefault + ToPrimitive>(rect: &Rect<V, D>) -> Self {
+        let mut min = [0.0; D];
+        let mut max = [0.0; D];
+        for i in 0..D {
+            min[i] = rect._min[i].to_f64();
+            max[i] = rect._max[i].to_f64();
+        }
+        Self { min, max }
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let mut res = *self;
+        for i in 0..D {
+            res.min[i] = res.min[i].min(other.min[i]);
+            res.max[i] = res.max[i].max(other.max[i]);
+        }
+        res
+    }
+
+    fn area(&self) -> f64 {
+        (0..D).map(|i| self.max[i] - self.min[i]).product()
+    }
+
+    fn margin(&self) -> f64 {
+        (0..D).map(|i| self.max[i] - self.min[i]).sum()
+    }
+
+    fn overlap(&self, other: &Self) -> f64 {
+        let mut area = 1.0;
+        for i in 0..D {
+            let d = self.max[i].min(other.max[i]) - self.min[i].max(other.min[i]);
+            if d <= 0.0 {
+                return 0.0;
+            }
+            area *= d;
+        }
+        area
+    }
+
+    fn center_dist(&self, other: &Self) -> f64 {
+        (0..D).map(|i| {
+            let d = (self.min[i] + self.max[i] - other.min[i] - other.max[i]) / 2.0;
+            d * d
+        }).sum()
+    }
+
+    fn enlargement(&self, other: &Self) -> f64 {
+        self.union(other).area() - self.area()
+    }
+}
+
+fn union_all<const D: usize>(bounds: &[Bounds<D>], idx: &[usize]) -> Bounds<D> {
+    idx[1..].iter().fold(bounds[idx[0]], |acc, i| acc.union(&bounds[*i]))
+}
+
+impl<V, const D: usize> SplitPolicy<V, D> for HilbertSplit
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    fn split(&self, mbrs: &[Rect<V, D>], min_fanout: usize) -> (Vec<usize>, Vec<usize>) {
+        curve_split(Curve::Hilbert, mbrs, min_fanout)
+    }
+}
+
+impl<V, const D: usize> SplitPolicy<V, D> for CurveSplit
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    fn split(&self, mbrs: &[Rect<V, D>], min_fanout: usize) -> (Vec<usize>, Vec<usize>) {
+        curve_split(self.0, mbrs, min_fanout)
+    }
+}
+
+fn curve_split<V, const D: usize>(curve: Curve, mbrs: &[Rect<V, D>], min_fanout: usize) -> (Vec<usize>, Vec<usize>)
+    where
+        V: MRTreeDefault + MRTreeFunc + FromPrimitive + ToPrimitive,
+{
+    let mut area = mbrs[0].clone();
+    for mbr in mbrs[1..].iter() {
+        area.expand(mbr);
+    }
+    let sorter = curve.sorter(&area);
+    let mut order = (0..mbrs.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| sorter.curve_idx(&mbrs[*i]));
+    let moved = order.split_off(mbrs.len() - min_fanout);
+    (order, moved)
+}
+
+/// 将剩余条目逐个分配到两组中。pick从剩余条目中选出下一个条目的位置，
+/// 某一组必须接收所有剩余条目才能满足最小扇出时直接分配
+fn distribute<const D: usize>(bounds: &[Bounds<D>],
+                              seeds: (usize, usize),
+                              min_fanout: usize,
+                              pick: impl Fn(&[usize], &Bounds<D>, &Bounds<D>) -> usize,
+) -> (Vec<usize>, Vec<usize>) {
+    let mut groups = (vec![seeds.0], vec![seeds.1]);
+    let mut covers = (bounds[seeds.0], bounds[seeds.1]);
+    let mut rest = (0..bounds.len()).filter(|i| *i != seeds.0 && *i != seeds.1).collect::<Vec<_>>();
+    while !rest.is_empty() {
+        if groups.0.len() + rest.len() == min_fanout {
+            groups.0.append(&mut rest);
+            break;
+        }
+        if groups.1.len() + rest.len() == min_fanout {
+            groups.1.append(&mut rest);
+            break;
+        }
+        let idx = rest.remove(pick(&rest, &covers.0, &covers.1));
+        let (d0, d1) = (covers.0.enlargement(&bounds[idx]), covers.1.enlargement(&bounds[idx]));
+        // 扩张较小的组优先，其次面积较小的组，最后条目较少的组
+        let first = match d0.partial_cmp(&d1) {
+            Some(Ordering::Less) => true,
+            Some(Ordering::Greater) => false,
+            _ => match covers.0.area().partial_cmp(&covers.1.area()) {
+                Some(Ordering::Less) => true,
+                Some(Ordering::Greater) => false,
+                _ => groups.0.len() <= groups.1.len(),
+            },
+        };
+        if first {
+            groups.0.push(idx);
+            covers.0 = covers.0.union(&bounds[idx]);
+        } else {
+            groups.1.push(idx);
+            covers.1 = covers.1.union(&bounds[idx]);
+        }
+    }
+    groups
+}
+
+impl<V, const D: usize> SplitPolicy<V, D> for QuadraticSplit
+    where
+        V: MRTreeDefault + ToPrimitive,
+{
+    fn split(&self, mbrs: &[Rect<V, D>], min_fanout: usize) -> (Vec<usize>, Vec<usize>) {
+        let bounds = mbrs.iter().map(Bounds::from_rect).collect::<Vec<_>>();
+        let mut seeds = (0, 1);
+        let mut worst = f64::NEG_INFINITY;
+        for i in 0..bounds.len() {
+            for j in i + 1..bounds.len() {
+                let waste = bounds[i].union(&bounds[j]).area() - bounds[i].area() - bounds[j].area();
+                if waste > worst {
+                    worst = waste;
+                    seeds = (i, j);
+                }
+            }
+        }
+        distribute(&bounds, seeds, min_fanout, |rest, c0, c1| {
+            let mut best = 0;
+            let mut best_diff = f64::NEG_INFINITY;
+            for (pos, idx) in rest.iter().enumerate() {
+                let diff = (c0.enlargement(&bounds[*idx]) - c1.enlargement(&bounds[*idx])).abs();
+                if diff > best_diff {
+                    best_diff = diff;
+                    best = pos;
+                }
+            }
+            best
+        })
+    }
+}
+
+impl<V, const D: usize> SplitPolicy<V, D> for LinearSplit
+    where
+        V: MRTreeDefault + ToPrimitive,
+{
+    fn split(&self, mbrs: &[Rect<V, D>], min_fanout: usize) -> (Vec<usize>, Vec<usize>) {
+        let bounds = mbrs.iter().map(Bounds::from_rect).collect::<Vec<_>>();
+        let all = union_all(&bounds, &(0..bounds.len()).collect::<Vec<_>>());
+        let mut seeds = (0, 1);
+        let mut best = f64::NEG_INFINITY;
+        for d in 0..D {
+            // 下界最大的条目与上界最小的条目
+            let highest_low = (0..bounds.len())
+                .max_by(|a, b| bounds[*a].min[d].total_cmp(&bounds[*b].min[d]))
+                .unwrap();
+            let lowest_high = (0..bounds.len())
+                .filter(|i| *i != highest_low)
+                .min_by(|a, b| bounds[*a].max[d].total_cmp(&bounds[*b].max[d]))
+                .unwrap();
+            let width = all.max[d] - all.min[d];
+            let separation = bounds[highest_low].min[d] - bounds[lowest_high].max[d];
+            let normalized = if width > 0.0 { separation / width } else { separation };
+            if normalized > best {
+                best = normalized;
+                seeds = (lowest_high, highest_low);
+            }
+        }
+        distribute(&bounds, seeds, min_fanout, |_, _, _| 0)
+    }
+}
+
+impl<V, const D: usize> SplitPolicy<V, D> for RStarSplit
+    where
+        V: MRTreeDefault + ToPrimitive,
+{
+    fn split(&self, mbrs: &[Rect<V, D>], min_fanout: usize) -> (Vec<usize>, Vec<usize>) {
+        let bounds = mbrs.iter().map(Bounds::from_rect).collect::<Vec<_>>();
+        let n = bounds.len();
+        // 每个轴上分别按下界和上界排序
+        let sorted = |d: usize| -> [Vec<usize>; 2] {
+            let mut by_min = (0..n).collect::<Vec<_>>();
+            by_min.sort_by(|a, b| bounds[*a].min[d].total_cmp(&bounds[*b].min[d])
+                .then(bounds[*a].max[d].total_cmp(&bounds[*b].max[d])));
+            let mut by_max = (0..n).collect::<Vec<_>>();
+            by_max.sort_by(|a, b| bounds[*a].max[d].total_cmp(&bounds[*b].max[d])
+                .then(bounds[*a].min[d].total_cmp(&bounds[*b].min[d])));
+            [by_min, by_max]
+        };
+        let distributions = min_fanout..=(n - min_fanout);
+        // 选择所有分割方式的边长之和最小的轴
+        let mut axis = 0;
+        let mut best_margin = f64::INFINITY;
+        for d in 0..D {
+            let margin = sorted(d).iter()
+                .flat_map(|order| distributions.clone().map(move |k| (order, k)))
+                .map(|(order, k)| union_all(&bounds, &order[..k]).margin() + union_all(&bounds, &order[k..]).margin())
+                .sum::<f64>();
+            if margin < best_margin {
+                best_margin = margin;
+                axis = d;
+            }
+        }
+        // 在该轴上选择重叠最小、其次面积之和最小的分割
+        let mut best: Option<(f64, f64, Vec<usize>, usize)> = None;
+        for order in sorted(axis) {
+            for k in distributions.clone() {
+                let (g0, g1) = (union_all(&bounds, &order[..k]), union_all(&bounds, &order[k..]));
+                let overlap = g0.overlap(&g1);
+                let area = g0.area() + g1.area();
+                let better = match &best {
+                    None => true,
+                    Some((o, a, _, _)) => overlap < *o || (overlap == *o && area < *a),
+                };
+                if better {
+                    best = Some((overlap, area, order.clone(), k));
+                }
+            }
+        }
+        let (_, _, mut order, k) = best.unwrap();
+        let moved = order.split_off(k);
+        (order, moved)
+    }
+
+    fn reinsert(&self, mbrs: &[Rect<V, D>], node_mbr: &Rect<V, D>, min_fanout: usize) -> Vec<usize> {
+        let count = ((mbrs.len() as f64) * self.reinsert_ratio).round() as usize;
+        let count = count.min(mbrs.len().saturating_sub(min_fanout));
+        if count == 0 {
+            return Vec::new();
+        }
+        let center = Bounds::from_rect(node_mbr);
+        let dists = mbrs.iter().map(|m| Bounds::from_rect(m).center_dist(&center)).collect::<Vec<_>>();
+        let mut order = (0..mbrs.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| dists[*b].total_cmp(&dists[*a]));
+        order.truncate(count);
+        order
+    }
+}
+
+/// 一次插入过程中的溢出处理状态：记录已经强制重插过的层以及等待重插的条目
+pub(crate) struct Overflow<'a, V, const D: usize, const C: usize, H>
+    where
+        V: MRTreeDefault,
+{
+    policy: &'a dyn SplitPolicy<V, D>,
+    reinserted: u64,
+    pending: Vec<ESMTEntry<V, D, C, H>>,
+}
+
+impl<'a, V, const D: usize, const C: usize, H: HashBackend> Overflow<'a, V, D, C, H>
+    where
+        V: MRTreeDefault + MRTreeFunc + ToPrimitive + FromPrimitive,
+{
+    pub fn new(policy: &'a dyn SplitPolicy<V, D>) -> Self {
+        Self {
+            policy,
+            reinserted: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn policy(&self) -> &'a dyn SplitPolicy<V, D> {
+        self.policy
+    }
+
+    /// 是否有条目被移出等待重插，此时路径上的mbr可能缩小，需要重新计算
+    #[inline]
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// 取出一个等待重插的条目，先取出距离原节点中心较近的条目
+    #[inline]
+    pub fn pop(&mut self) -> Option<ESMTEntry<V, D, C, H>> {
+        self.pending.pop()
+    }
+
+    /// 处理非根节点的溢出：返回分裂出的新节点；如果策略选择了强制重插，
+    /// 将条目移出节点（重新计算节点状态）并返回`None`
+    pub fn treat(&mut self, node: &mut Node<V, D, C, H>) -> Option<Node<V, D, C, H>> {
+        let level = 1u64 << node.height.min(63);
+        if self.reinserted & level == 0 {
+            self.reinserted |= level;
+            let taken = node.take_for_reinsert(self.policy);
+            if !taken.is_empty() {
+                self.pending.extend(taken);
+                return None;
+            }
+        }
+        Some(node.split_with(self.policy))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CurveSplit, HilbertSplit, LinearSplit, QuadraticSplit, RStarSplit, SplitPolicy};
+    use crate::curve::Curve;
+    use crate::shape::Rect;
+
+    fn clusters() -> Vec<Rect<f64, 2>> {
+        // 两簇相距很远的矩形，交错排列
+        let mut mbrs = vec![];
+        for i in 0..5 {
+            let o = i as f64;
+            mbrs.push(Rect::new([o, o], [o + 1.0, o + 1.0]));
+            mbrs.push(Rect::new([100.0 + o, 100.0], [101.0 + o, 102.0]));
+        }
+        mbrs
+    }
+
+    fn check(policy: &dyn SplitPolicy<f64, 2>, mbrs: &[Rect<f64, 2>], min_fanout: usize) -> (Vec<usize>, Vec<usize>) {
+        let (g0, g1) = policy.split(mbrs, min_fanout);
+        assert!(g0.len() >= min_fanout && g1.len() >= min_fanout);
+        let mut all = g0.iter().chain(g1.iter()).cloned().collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(all, (0..mbrs.len()).collect::<Vec<_>>());
+        (g0, g1)
+    }
+
+    #[test]
+    fn test_split_policies() {
+        let mbrs = clusters();
+        let policies: Vec<Box<dyn SplitPolicy<f64, 2>>> = vec![
+            Box::new(QuadraticSplit),
+            Box::new(LinearSplit),
+            Box::new(RStarSplit::default()),
+            Box::new(CurveSplit(Curve::ZOrder)),
+            Box::new(CurveSplit(Curve::Peano)),
+        ];
+        for policy in policies.iter() {
+            let (g0, g1) = check(policy.as_ref(), &mbrs, 5);
+            // 两簇被完整地分开
+            let side = |g: &Vec<usize>| g.iter().map(|i| i % 2).collect::<Vec<_>>();
+            assert!(side(&g0).windows(2).all(|w| w[0] == w[1]));
+            assert!(side(&g1).windows(2).all(|w| w[0] == w[1]));
+            assert_ne!(g0[0] % 2, g1[0] % 2);
+        }
+        for min_fanout in 1..=5 {
+            check(&HilbertSplit, &mbrs, min_fanout);
+            check(&CurveSplit(Curve::ZOrder), &mbrs, min_fanout);
+            check(&QuadraticSplit, &mbrs, min_fanout);
+            check(&LinearSplit, &mbrs, min_fanout);
+            check(&RStarSplit::default(), &mbrs, min_fanout);
+        }
+        // 所有条目重合时也能满足最小扇出
+        let same = vec![Rect::new_point([1.0f64, 1.0]); 8];
+        check(&QuadraticSplit, &same, 4);
+        check(&LinearSplit, &same, 4);
+        check(&RStarSplit::default(), &same, 4);
+    }
+
+    #[test]
+    fn test_rstar_reinsert() {
+        let mbrs = (0..10).map(|i| Rect::new_point([i as f64, 0.0])).collect::<Vec<_>>();
+        let node_mbr = Rect::new([0.0f64, 0.0], [9.0, 0.0]);
+        let mut taken = RStarSplit::default().reinsert(&mbrs, &node_mbr, 5);
+        assert_eq!(taken.len(), 3);
+        taken.sort();
+        assert!(taken.contains(&0) && taken.contains(&9));
+        assert!(RStarSplit::new(0.0).reinsert(&mbrs, &node_mbr, 5).is_empty());
+        assert!(RStarSplit::new(0.9).reinsert(&mbrs, &node_mbr, 5).len() <= 5);
+        assert!(SplitPolicy::<f64, 2>::reinsert(&QuadraticSplit, &mbrs, &node_mbr, 5).is_empty());
+    }
+}
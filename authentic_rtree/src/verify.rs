@@ -1,21 +1,21 @@
-use std::{slice::Iter, vec::IntoIter, collections::BTreeSet};
+use std::{marker::PhantomData, slice::Iter, vec::IntoIter};
 
-use types::hash_value::{HashValue, ESMTHasher};
+use types::hash_value::{HashBackend, HashValue, Sha3_256};
 
-use crate::{node::{ObjectEntry, MRTreeDefault, Node, MRTreeFunc}, shape::Rect};
+use crate::{node::{ObjectEntry, MRTreeDefault, Node, MRTreeFunc, entry_record, node_digest, partition_leaf, merkle_root, merkle_path_root, key_leaf, key_node, key_index_root}, shape::{Circle, QueryShape, Rect}};
 
 #[derive(Clone)]
-pub enum VerifyObjectEntry<V, const D: usize> 
+pub enum VerifyObjectEntry<V, const D: usize, H = Sha3_256> 
     where
         V: MRTreeDefault,
 {
     LevelBegin,
     LevlEnd,
-    Target(ObjectEntry<V, D>),
+    Target(ObjectEntry<V, D, H>),
     Sibling(SiblingObject<V, D>),
 }
 
-impl<V, const D: usize> VerifyObjectEntry<V, D> 
+impl<V, const D: usize, H: HashBackend> VerifyObjectEntry<V, D, H> 
     where
         V: MRTreeDefault,
 {
@@ -35,12 +35,25 @@ pub struct SiblingObject<V, const D: usize>
 {
     range: Rect<V, D>,
     hash: HashValue,
+    /// 兄弟节点的高度，兄弟为空间对象时为`None`
+    height: Option<u32>,
+    /// 兄弟节点中未删除的空间对象数量
+    count: u64,
 }
 
 impl<V, const D: usize> SiblingObject<V, D> 
     where
         V: MRTreeDefault,
 {
+    pub(crate) fn new(range: Rect<V, D>, hash: HashValue, height: Option<u32>, count: u64) -> Self {
+        Self {
+            range,
+            hash,
+            height,
+            count,
+        }
+    }
+
     #[inline]
     pub fn hash_ref(&self) -> &[u8] {
         self.hash.as_ref()
@@ -55,40 +68,56 @@ impl<V, const D: usize> SiblingObject<V, D>
     pub fn range(&self) -> &Rect<V, D> {
         &self.range
     }
+
+    #[inline]
+    pub fn height(&self) -> Option<u32> {
+        self.height
+    }
+
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
 }
 
-impl<V, const D: usize, const C: usize> From<&Node<V, D, C>> for SiblingObject<V, D> 
+impl<V, const D: usize, const C: usize, H: HashBackend> From<&Node<V, D, C, H>> for SiblingObject<V, D> 
     where
         V: MRTreeDefault,
 {
-    fn from(node: &Node<V, D, C>) -> Self {
+    fn from(node: &Node<V, D, C, H>) -> Self {
         Self {
             range: node.mbr.clone(),
-            hash: node.hash.clone(),
+            hash: node.hash,
+            height: Some(node.height),
+            count: node.count,
         }
     }
 }
 
-impl<V, const D: usize> From<&ObjectEntry<V, D>> for SiblingObject<V, D> 
+impl<V, const D: usize, H: HashBackend> From<&ObjectEntry<V, D, H>> for SiblingObject<V, D> 
     where
         V: MRTreeDefault,
 {
-    fn from(obj: &ObjectEntry<V, D>) -> Self {
+    fn from(obj: &ObjectEntry<V, D, H>) -> Self {
         Self {
             range: obj.loc().clone(),
-            hash: obj.hash()
+            hash: obj.hash(),
+            height: None,
+            count: u64::from(!obj.is_stale()),
         }
     }
 }
 
-pub struct VerifyObject<V, const D: usize> 
+/// 查询的VO，`H`为生成VO的树所使用的哈希后端，验证时按同一后端重新计算摘要
+#[derive(Clone)]
+pub struct VerifyObject<V, const D: usize, H = Sha3_256> 
     where
         V: MRTreeDefault,
 {
-    verify_path: Vec<VerifyObjectEntry<V, D>>,
+    verify_path: Vec<VerifyObjectEntry<V, D, H>>,
 }
 
-impl<V, const D: usize> VerifyObject<V, D> 
+impl<V, const D: usize, H: HashBackend> VerifyObject<V, D, H> 
     where
         V: MRTreeDefault,
 {
@@ -99,12 +128,12 @@ impl<V, const D: usize> VerifyObject<V, D>
     }
 
     #[inline]
-    pub fn push(&mut self, entry: VerifyObjectEntry<V, D>) {
+    pub fn push(&mut self, entry: VerifyObjectEntry<V, D, H>) {
         self.verify_path.push(entry);
     }
 
     #[inline]
-    pub fn extend(&mut self, ano: VerifyObject<V, D>) {
+    pub fn extend(&mut self, ano: VerifyObject<V, D, H>) {
         self.verify_path.extend(ano.verify_path);
     }
 
@@ -114,12 +143,12 @@ impl<V, const D: usize> VerifyObject<V, D>
     }
 
     #[inline]
-    pub fn iter(&self) -> Iter<'_, VerifyObjectEntry<V, D>> {
+    pub fn iter(&self) -> Iter<'_, VerifyObjectEntry<V, D, H>> {
         self.verify_path.iter()
     }
 
     #[inline]
-    pub fn into_iter(self) -> IntoIter<VerifyObjectEntry<V, D>> {
+    pub fn into_iter(self) -> IntoIter<VerifyObjectEntry<V, D, H>> {
         self.verify_path.into_iter()
     }
 
@@ -144,75 +173,840 @@ impl<V, const D: usize> VerifyObject<V, D>
     }
 }
 
-impl<V, const D: usize> VerifyObject<V, D> 
+impl<V, const D: usize, H: HashBackend> VerifyObject<V, D, H> 
     where
         V: MRTreeDefault + MRTreeFunc,
 {
-    pub fn verify(&self, query: &Rect<V, D>, root_hash: HashValue) -> Result<(), VerifyError> {
-        let mut res_mbr = None;
-        let mut parse_stack = vec![];
-        let mut hash_stack: Vec<HashValue> = vec![];
-        for ety in self.verify_path.iter() {
-            match ety {
+    /// 验证范围查询的VO：结果对象都在查询范围内，被剪枝的兄弟节点与查询不相交，且能重新计算出根哈希。
+    /// 结果对象按[`HashDomain::Leaf`](types::hash_value::HashDomain::Leaf)、每一层按[`HashDomain::Node`](types::hash_value::HashDomain::Node)重新计算摘要，子树的摘要不能冒充对象
+    /// 查询可以是矩形，也可以是[`Polygon`](crate::shape::Polygon)等任意[`QueryShape`]
+    pub fn verify<Q: QueryShape<V, D>>(&self, query: &Q, root_hash: HashValue) -> Result<(), VerifyError> {
+        self.check_queries(std::slice::from_ref(query))?;
+        self.verify_root(root_hash)
+    }
+
+    /// 验证批量范围查询的VO，返回每个查询各自的结果对象，顺序与queries一致。
+    /// 每个结果对象至少属于一个查询，被剪枝的条目与所有查询都不相交，且整个VO只重建一次根哈希
+    pub fn verify_batch<Q: QueryShape<V, D>>(&self, queries: &[Q], root_hash: HashValue) -> Result<Vec<Vec<&ObjectEntry<V, D, H>>>, VerifyError> {
+        self.check_queries(queries)?;
+        self.verify_root(root_hash)?;
+        let mut results = vec![vec![]; queries.len()];
+        for target in self.verify_path.iter().filter_map(|ety| match ety {
+            VerifyObjectEntry::Target(t) => Some(t),
+            _ => None,
+        }) {
+            for (res, query) in results.iter_mut().zip(queries) {
+                if query.contains(target.loc()) {
+                    res.push(target);
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// 验证聚合范围查询的VO，返回查询范围内未删除的对象数量。
+    /// 兄弟节点要么被查询完全包含（计入其对象数量），要么与查询不相交，要么不含对象
+    pub fn verify_count<Q: QueryShape<V, D>>(&self, query: &Q, root_hash: HashValue) -> Result<u64, VerifyError> {
+        let total = self.count_in(query)?;
+        self.verify_root(root_hash)?;
+        Ok(total)
+    }
+
+    fn count_in<Q: QueryShape<V, D>>(&self, query: &Q) -> Result<u64, VerifyError> {
+        let mut depth = 0usize;
+        let mut total = 0u64;
+        for (index, ety) in self.verify_path.iter().enumerate() {
+            let count = match ety {
                 VerifyObjectEntry::LevelBegin => {
-                    parse_stack.push(ety.clone());
+                    depth += 1;
+                    continue;
                 },
                 VerifyObjectEntry::LevlEnd => {
-                    let mut hash_set = BTreeSet::new();
-                    loop {
-                        let op = parse_stack.pop().unwrap();
-                        if let VerifyObjectEntry::LevelBegin = op {
-                            break;
-                        }
-                        hash_set.insert(hash_stack.pop().unwrap());
-                    }
-                    let hasher = hash_set
-                        .into_iter()
-                        .fold(ESMTHasher::default(), |hasher, entry| {
-                            hasher.update(entry.as_ref())
-                        });
-                    hash_stack.push(hasher.finish());
-                },
-                VerifyObjectEntry::Target(target) => {
-                    parse_stack.push(ety.clone());
-                    hash_stack.push(target.hash());
-                    if res_mbr.is_none() {
-                        res_mbr = Some(target.loc().clone());
-                    } else {
-                        let r = res_mbr.as_mut().unwrap();
-                        r.expand(target.loc());
-                    }
-                },
-                VerifyObjectEntry::Sibling(sibling) => {
-                    if sibling.range().intersects(query) {
-                        return Err(VerifyError::CompletenessError);
-                    }
-                    parse_stack.push(ety.clone());
-                    hash_stack.push(sibling.hash());
+                    depth = depth.saturating_sub(1);
+                    continue;
                 },
+                VerifyObjectEntry::Target(target) if query.contains(target.loc()) => 1,
+                VerifyObjectEntry::Target(_) => return Err(VerifyError::TargetOutsideQuery { index, depth }),
+                VerifyObjectEntry::Sibling(sibling) if sibling.count() == 0 || !query.intersects(sibling.range()) => 0,
+                VerifyObjectEntry::Sibling(sibling) if query.contains(sibling.range()) => sibling.count(),
+                // 与查询部分相交的对象不在查询范围内
+                VerifyObjectEntry::Sibling(sibling) if sibling.height().is_none() => 0,
+                VerifyObjectEntry::Sibling(_) => return Err(VerifyError::SiblingIntersectsQuery { index, depth }),
+            };
+            total = total.checked_add(count).ok_or(VerifyError::CountOverflow { index, depth })?;
+        }
+        Ok(total)
+    }
+
+    fn check_queries<Q: QueryShape<V, D>>(&self, queries: &[Q]) -> Result<(), VerifyError> {
+        let mut depth = 0usize;
+        for (index, ety) in self.verify_path.iter().enumerate() {
+            check_query(queries, ety, index, depth)?;
+            match ety {
+                VerifyObjectEntry::LevelBegin => depth += 1,
+                VerifyObjectEntry::LevlEnd => depth = depth.saturating_sub(1),
+                _ => {},
             }
         }
-        if res_mbr.is_none() {
-            return Ok(());
-        } else {
-            let res_mbr = res_mbr.unwrap();
-            if !query.contains(&res_mbr) {
-                return Err(VerifyError::ResultError);
+        Ok(())
+    }
+
+    /// 验证半径查询的VO：结果对象与center的距离都不超过radius，
+    /// 被剪枝的兄弟节点与center的距离都大于radius
+    pub fn verify_circle(&self, center: &[V; D], radius: V, root_hash: HashValue) -> Result<(), VerifyError> {
+        self.verify(&Circle::new(*center, radius), root_hash)
+    }
+
+    /// 验证kNN查询的VO：结果恰好为距离point最近的k个对象（对象不足k个时为全部对象），
+    /// 即所有被剪枝的兄弟节点与point的距离都不小于第k近结果的距离
+    pub fn verify_knn(&self, point: &[V; D], k: usize, root_hash: HashValue) -> Result<(), VerifyError> {
+        let point = Rect::new_point(*point);
+        let mut targets = vec![];
+        let mut pruned = vec![];
+        self.knn_dists(&point, None, &mut targets, &mut pruned);
+        check_knn(targets, pruned, k)?;
+        self.verify_root(root_hash)
+    }
+
+    /// 分别收集结果对象的距离，以及兄弟节点的距离和位置
+    fn knn_dists(&self, point: &Rect<V, D>, partition: Option<usize>, targets: &mut Vec<V>, pruned: &mut Vec<KnnPruned<V>>) {
+        for (index, ety) in self.verify_path.iter().enumerate() {
+            match ety {
+                VerifyObjectEntry::Target(target) => targets.push(target.loc().rect_dist(point)),
+                // 不含对象的兄弟节点不可能遗漏结果
+                VerifyObjectEntry::Sibling(sibling) if sibling.count() > 0 => {
+                    pruned.push((sibling.range().rect_dist(point), partition, Some(index)));
+                },
+                _ => {},
             }
         }
-        let verify_root_hash = hash_stack.pop().unwrap();
-        if verify_root_hash == root_hash {
+    }
+
+    fn verify_root(&self, root_hash: HashValue) -> Result<(), VerifyError> {
+        let computed = self.reconstruct()?;
+        if computed == root_hash {
             Ok(())
         } else {
-            Err(VerifyError::SoundnessError)
+            Err(VerifyError::RootMismatch { expected: root_hash, computed })
+        }
+    }
+
+    /// 按照VO的层次结构重新计算根哈希，只检查结构，不检查查询条件。
+    /// 任何不合法的输入都返回错误，不会panic
+    fn reconstruct(&self) -> Result<HashValue, VerifyError> {
+        let mut state = Reconstructor::new();
+        for ety in self.verify_path.iter() {
+            state.push(ety)?;
+        }
+        state.finish()
+    }
+}
+
+/// 检查条目是否满足范围查询：结果对象至少在一个查询范围内，被剪枝的兄弟节点与所有查询范围都不相交，
+/// 被剪枝的对象不被任何查询包含，或者兄弟不含任何对象
+fn check_query<V, H, Q, const D: usize>(queries: &[Q], ety: &VerifyObjectEntry<V, D, H>, index: usize, depth: usize) -> Result<(), VerifyError>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+        H: HashBackend,
+        Q: QueryShape<V, D>,
+{
+    match ety {
+        VerifyObjectEntry::Target(target) if !queries.iter().any(|q| q.contains(target.loc())) => {
+            Err(VerifyError::TargetOutsideQuery { index, depth })
+        },
+        VerifyObjectEntry::Sibling(sibling) if sibling.count() > 0 && queries.iter().any(|q| may_hold_result(q, sibling)) => {
+            Err(VerifyError::SiblingIntersectsQuery { index, depth })
+        },
+        _ => Ok(()),
+    }
+}
+
+/// 被剪枝的兄弟是否可能含有查询结果。有范围的对象与查询部分相交时不是结果，
+/// 只有被查询包含的对象才会被遗漏；兄弟节点与查询相交时则可能含有结果
+fn may_hold_result<V, Q, const D: usize>(query: &Q, sibling: &SiblingObject<V, D>) -> bool
+    where
+        V: MRTreeDefault + MRTreeFunc,
+        Q: QueryShape<V, D>,
+{
+    match sibling.height() {
+        None => query.contains(sibling.range()),
+        Some(_) => query.intersects(sibling.range()),
+    }
+}
+
+/// 增量地按照VO的层次结构重新计算根哈希，只保存未闭合层中的条目
+struct Reconstructor<V, const D: usize, H>
+    where
+        V: MRTreeDefault,
+{
+    /// 每个未闭合的层中已解析的条目，与节点摘要中的承诺一一对应
+    levels: Vec<Vec<LevelEntry<V, D>>>,
+    root: Option<HashValue>,
+    /// 下一个条目在VO中的位置
+    index: usize,
+    _hasher: PhantomData<fn() -> H>,
+}
+
+impl<V, const D: usize, H: HashBackend> Reconstructor<V, D, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    fn new() -> Self {
+        Self {
+            levels: vec![],
+            root: None,
+            index: 0,
+            _hasher: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    fn push(&mut self, ety: &VerifyObjectEntry<V, D, H>) -> Result<(), VerifyError> {
+        let index = self.index;
+        let depth = self.levels.len();
+        self.index += 1;
+        match ety {
+            VerifyObjectEntry::LevelBegin => {
+                if depth == 0 && self.root.is_some() {
+                    return Err(VerifyError::MultipleRoots { index });
+                }
+                self.levels.push(vec![]);
+            },
+            VerifyObjectEntry::LevlEnd => {
+                let children = self.levels.pop().ok_or(VerifyError::UnmatchedLevelEnd { index })?;
+                let mut records = Vec::with_capacity(children.len());
+                let mut mbr: Option<Rect<V, D>> = None;
+                let mut child_height = None;
+                let mut total = 0u64;
+                for (hash, range, height, count) in children {
+                    // 同一节点中的条目高度必须一致
+                    if records.is_empty() {
+                        child_height = height;
+                    } else if child_height != height {
+                        return Err(VerifyError::HeightMismatch { index, depth, expected: child_height, found: height });
+                    }
+                    records.push(entry_record(&hash, &range, height, count));
+                    total = total.checked_add(count).ok_or(VerifyError::CountOverflow { index, depth })?;
+                    match mbr.as_mut() {
+                        None => mbr = Some(range),
+                        Some(r) => r.expand(&range),
+                    }
+                }
+                let mbr = mbr.ok_or(VerifyError::EmptyLevel { index, depth })?;
+                let height = match child_height {
+                    None => 0,
+                    Some(h) => h.checked_add(1).ok_or(VerifyError::HeightMismatch { index, depth, expected: None, found: child_height })?,
+                };
+                // 当前层归约为上一层中的一个条目
+                let entry = (node_digest::<H>(records), mbr, Some(height), total);
+                match self.levels.last_mut() {
+                    Some(parent) => parent.push(entry),
+                    None => self.root = Some(entry.0),
+                }
+            },
+            VerifyObjectEntry::Target(target) => {
+                // 由key、loc和value重新计算摘要，保证结果对象确实位于所报告的位置
+                self.levels.last_mut()
+                    .ok_or(VerifyError::EntryOutsideLevel { index })?
+                    .push((target.calc_hash(), target.loc().clone(), None, 1));
+            },
+            VerifyObjectEntry::Sibling(sibling) => {
+                self.levels.last_mut()
+                    .ok_or(VerifyError::EntryOutsideLevel { index })?
+                    .push((sibling.hash(), sibling.range().clone(), sibling.height(), sibling.count()));
+            },
+        }
+        Ok(())
+    }
+
+    /// 无论结果是否为空，都必须恰好归约出一个根哈希
+    fn finish(self) -> Result<HashValue, VerifyError> {
+        if !self.levels.is_empty() {
+            return Err(VerifyError::UnclosedLevel { depth: self.levels.len() });
+        }
+        self.root.ok_or(VerifyError::EmptyProof)
+    }
+}
+
+/// 范围查询VO的流式验证器，逐个消费条目而不需要完整的VO。
+///
+/// 每当一层闭合时返回该层中的结果对象，此时对象已满足查询条件且其摘要已计入上层节点，
+/// 但只有[`StreamVerifier::finish`]通过根哈希检查后，所有结果才是可信的。
+///
+/// ```rust
+/// use authentic_rtree::mrtree::MerkleRTree;
+/// use authentic_rtree::shape::Rect;
+/// use authentic_rtree::verify::StreamVerifier;
+/// use types::test_utils::num_hash;
+/// let mut tree = MerkleRTree::<usize, 2, 4>::new();
+/// for i in 0..20 {
+///     tree.insert(format!("key-{}", i), [i, i], num_hash(i as i32)).unwrap();
+/// }
+/// let query = Rect::new([3, 3], [8, 8]);
+/// let vo = tree.range_query(&query).unwrap();
+/// let mut verifier = StreamVerifier::new(query, tree.root_hash().unwrap());
+/// let mut results = vec![];
+/// for ety in vo.into_iter() {
+///     results.extend(verifier.push(ety).unwrap());
+/// }
+/// assert!(verifier.finish().is_ok());
+/// assert_eq!(results.len(), 6);
+/// ```
+pub struct StreamVerifier<V, const D: usize, H = Sha3_256>
+    where
+        V: MRTreeDefault,
+{
+    query: Rect<V, D>,
+    root_hash: HashValue,
+    state: Reconstructor<V, D, H>,
+    /// 每个未闭合层中尚未返回的结果对象
+    pending: Vec<Vec<ObjectEntry<V, D, H>>>,
+}
+
+impl<V, const D: usize, H: HashBackend> StreamVerifier<V, D, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    pub fn new(query: Rect<V, D>, root_hash: HashValue) -> Self {
+        Self {
+            query,
+            root_hash,
+            state: Reconstructor::new(),
+            pending: vec![],
+        }
+    }
+
+    /// 消费一个条目，返回因该条目闭合的层中的结果对象。出错后验证器不应继续使用
+    pub fn push(&mut self, ety: VerifyObjectEntry<V, D, H>) -> Result<Vec<ObjectEntry<V, D, H>>, VerifyError> {
+        check_query(std::slice::from_ref(&self.query), &ety, self.state.index, self.state.depth())?;
+        self.state.push(&ety)?;
+        match ety {
+            VerifyObjectEntry::LevelBegin => self.pending.push(vec![]),
+            VerifyObjectEntry::LevlEnd => return Ok(self.pending.pop().unwrap_or_default()),
+            VerifyObjectEntry::Target(target) => {
+                if let Some(level) = self.pending.last_mut() {
+                    level.push(target);
+                }
+            },
+            VerifyObjectEntry::Sibling(_) => {},
+        }
+        Ok(vec![])
+    }
+
+    /// 所有条目消费完毕后检查结构的完整性和根哈希
+    pub fn finish(self) -> Result<(), VerifyError> {
+        let computed = self.state.finish()?;
+        if computed != self.root_hash {
+            return Err(VerifyError::RootMismatch { expected: self.root_hash, computed });
+        }
+        Ok(())
+    }
+
+    /// 依次验证entries中的条目，每当一层闭合时将其中的结果对象交给on_result
+    pub fn verify_all<I, F>(mut self, entries: I, mut on_result: F) -> Result<(), VerifyError>
+        where
+            I: IntoIterator<Item = VerifyObjectEntry<V, D, H>>,
+            F: FnMut(ObjectEntry<V, D, H>),
+    {
+        for ety in entries {
+            self.push(ety)?.into_iter().for_each(&mut on_result);
+        }
+        self.finish()
+    }
+}
+
+/// 重建过程中层内的条目：（哈希，mbr，高度，对象数量）
+type LevelEntry<V, const D: usize> = (HashValue, Rect<V, D>, Option<u32>, u64);
+
+/// kNN验证中被剪枝的条目：（与查询点的距离，分区编号，VO中的条目位置）
+type KnnPruned<V> = (V, Option<usize>, Option<usize>);
+
+/// 检查kNN结果：结果数量不超过k；结果不足k个时不允许剪枝，
+/// 否则被剪枝条目的距离都不小于第k近结果的距离
+fn check_knn<V: PartialOrd + Copy>(targets: Vec<V>, pruned: Vec<KnnPruned<V>>, k: usize) -> Result<(), VerifyError> {
+    if targets.len() > k {
+        return Err(VerifyError::TooManyResults { expected: k, found: targets.len() });
+    }
+    let kth = targets.iter().copied().reduce(|a, b| if b > a { b } else { a });
+    for (dist, partition, index) in pruned {
+        let closer = match kth {
+            // 结果不足k个时，任何被剪枝的条目都可能含有遗漏的对象
+            _ if targets.len() < k => true,
+            Some(kth) => dist < kth,
+            None => false,
+        };
+        if closer {
+            return Err(VerifyError::PrunedCloserThanResult { partition, index });
+        }
+    }
+    Ok(())
+}
+
+/// 分区证明：将某个分区的根哈希（及其VO）与`PartionManager`的全局根哈希关联起来
+#[derive(Clone)]
+pub struct PartitionProof<V, const D: usize, H = Sha3_256>
+    where
+        V: MRTreeDefault,
+{
+    /// 分区在`PartionManager`中的编号
+    index: usize,
+    /// 全局Merkle树的叶子数，即分区总数加上key索引
+    count: usize,
+    area: Rect<V, D>,
+    /// 分区的根哈希，空分区为`None`
+    root: Option<HashValue>,
+    /// 自底向上的兄弟哈希，没有兄弟（直接提升到上一层）的层不占位置
+    path: Vec<HashValue>,
+    _hasher: PhantomData<fn() -> H>,
+}
+
+impl<V, const D: usize, H: HashBackend> PartitionProof<V, D, H>
+    where
+        V: MRTreeDefault,
+{
+    pub(crate) fn new(index: usize, count: usize, area: Rect<V, D>, root: Option<HashValue>, path: Vec<HashValue>) -> Self {
+        Self {
+            index,
+            count,
+            area,
+            root,
+            path,
+            _hasher: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    #[inline]
+    pub fn area(&self) -> &Rect<V, D> {
+        &self.area
+    }
+
+    #[inline]
+    pub fn root(&self) -> Option<HashValue> {
+        self.root
+    }
+
+    /// 验证分区的（编号，范围，根哈希）包含在全局根哈希中
+    pub fn verify_root(&self, global_root: HashValue) -> Result<(), VerifyError> {
+        let leaf = partition_leaf::<V, H, D>(self.index, &self.area, self.root);
+        check_path::<H>(leaf, self.index, self.count, &self.path, global_root)
+    }
+}
+
+impl<V, const D: usize, H: HashBackend> PartitionProof<V, D, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    /// 先用分区根哈希验证VO，再验证分区根哈希包含在全局根哈希中
+    pub fn verify<Q: QueryShape<V, D>>(&self, vo: &VerifyObject<V, D, H>, query: &Q, global_root: HashValue) -> Result<(), VerifyError> {
+        let root = self.root.ok_or(VerifyError::UnexpectedPartitionVo { partition: self.index })?;
+        vo.verify(query, root).map_err(|e| e.in_partition(self.index))?;
+        self.verify_root(global_root)
+    }
+}
+
+/// 组合证明中的一个分区：（分区范围，分区根哈希，VO）
+type PartitionRecord<V, const D: usize, H> = (Rect<V, D>, Option<HashValue>, Option<VerifyObject<V, D, H>>);
+
+/// 多分区查询的组合证明。按分区编号列出每个分区的范围和根哈希，
+/// 与查询相交的非空分区附带其VO；其余分区由范围或空根哈希证明与查询无关
+pub struct PartitionVerifyObject<V, const D: usize, H = Sha3_256>
+    where
+        V: MRTreeDefault,
+{
+    partitions: Vec<PartitionRecord<V, D, H>>,
+    /// key索引的根，是全局Merkle树的最后一个叶子
+    key_root: HashValue,
+}
+
+impl<V, const D: usize, H: HashBackend> PartitionVerifyObject<V, D, H>
+    where
+        V: MRTreeDefault,
+{
+    pub fn new(key_root: HashValue) -> Self {
+        Self {
+            partitions: vec![],
+            key_root,
         }
     }
+
+    #[inline]
+    pub fn push(&mut self, area: Rect<V, D>, root: Option<HashValue>, vo: Option<VerifyObject<V, D, H>>) {
+        self.partitions.push((area, root, vo));
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.partitions.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.partitions.is_empty()
+    }
+
+    /// 参与查询的分区：（分区编号，分区范围，VO）
+    pub fn touched(&self) -> impl Iterator<Item = (usize, &Rect<V, D>, &VerifyObject<V, D, H>)> {
+        self.partitions.iter()
+            .enumerate()
+            .filter_map(|(idx, (area, _, vo))| vo.as_ref().map(|vo| (idx, area, vo)))
+    }
 }
 
+impl<V, const D: usize, H: HashBackend> PartitionVerifyObject<V, D, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    pub fn verify<Q: QueryShape<V, D>>(&self, query: &Q, global_root: HashValue) -> Result<(), VerifyError> {
+        self.verify_global_root(global_root)?;
+        for (partition, (area, root, vo)) in self.partitions.iter().enumerate() {
+            match (root, vo) {
+                (Some(root), Some(vo)) => vo.verify(query, *root).map_err(|e| e.in_partition(partition))?,
+                // 非空分区与查询相交时必须给出VO
+                (Some(_), None) if query.intersects(area) => return Err(VerifyError::MissingPartitionVo { partition }),
+                (None, Some(_)) => return Err(VerifyError::UnexpectedPartitionVo { partition }),
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    /// 验证多分区的聚合范围查询，返回所有分区中位于查询范围内的对象数量
+    pub fn verify_count<Q: QueryShape<V, D>>(&self, query: &Q, global_root: HashValue) -> Result<u64, VerifyError> {
+        self.verify_global_root(global_root)?;
+        let mut total = 0u64;
+        for (partition, (area, root, vo)) in self.partitions.iter().enumerate() {
+            let count = match (root, vo) {
+                (Some(root), Some(vo)) => vo.verify_count(query, *root).map_err(|e| e.in_partition(partition))?,
+                (Some(_), None) if query.intersects(area) => return Err(VerifyError::MissingPartitionVo { partition }),
+                (None, Some(_)) => return Err(VerifyError::UnexpectedPartitionVo { partition }),
+                _ => 0,
+            };
+            total = total.checked_add(count).ok_or(VerifyError::CountOverflow { index: partition, depth: 0 })?;
+        }
+        Ok(total)
+    }
+
+    /// 验证多分区的半径查询，未给出VO的非空分区与center的距离必须大于radius
+    pub fn verify_circle(&self, center: &[V; D], radius: V, global_root: HashValue) -> Result<(), VerifyError> {
+        self.verify(&Circle::new(*center, radius), global_root)
+    }
 
-#[derive(Debug, Clone, Copy)]
+    /// 验证多分区kNN查询：所有VO的结果合起来是距离point最近的k个对象，
+    /// 未给出VO的非空分区与point的距离不小于第k近结果的距离
+    pub fn verify_knn(&self, point: &[V; D], k: usize, global_root: HashValue) -> Result<(), VerifyError> {
+        self.verify_global_root(global_root)?;
+        let point = Rect::new_point(*point);
+        let mut targets = vec![];
+        let mut pruned = vec![];
+        for (partition, (area, root, vo)) in self.partitions.iter().enumerate() {
+            match (root, vo) {
+                (Some(root), Some(vo)) => {
+                    vo.verify_root(*root).map_err(|e| e.in_partition(partition))?;
+                    vo.knn_dists(&point, Some(partition), &mut targets, &mut pruned);
+                },
+                (Some(_), None) => pruned.push((area.rect_dist(&point), Some(partition), None)),
+                (None, Some(_)) => return Err(VerifyError::UnexpectedPartitionVo { partition }),
+                (None, None) => {},
+            }
+        }
+        check_knn(targets, pruned, k)
+    }
+
+    /// 所有分区的（编号，范围，根哈希）必须重新计算出全局根哈希
+    fn verify_global_root(&self, global_root: HashValue) -> Result<(), VerifyError> {
+        if self.partitions.is_empty() {
+            return Err(VerifyError::EmptyProof);
+        }
+        let mut leaves = self.partitions.iter()
+            .enumerate()
+            .map(|(idx, (area, root, _))| partition_leaf::<V, H, D>(idx, area, *root))
+            .collect::<Vec<_>>();
+        leaves.push(self.key_root);
+        let computed = merkle_root::<H>(leaves);
+        if computed != global_root {
+            return Err(VerifyError::RootMismatch { expected: global_root, computed });
+        }
+        Ok(())
+    }
+}
+
+/// key索引树堆中从叶子到根的一步：当前子树位于父节点的哪一侧、父节点自身的叶子，以及另一侧子树的大小和哈希
+#[derive(Clone)]
+pub struct KeyIndexStep {
+    from_left: bool,
+    leaf: HashValue,
+    sibling_count: usize,
+    sibling: HashValue,
+}
+
+impl KeyIndexStep {
+    pub(crate) fn new(from_left: bool, leaf: HashValue, sibling_count: usize, sibling: HashValue) -> Self {
+        Self {
+            from_left,
+            leaf,
+            sibling_count,
+            sibling,
+        }
+    }
+}
+
+/// key索引中的一个叶子：叶子位置、key、对象摘要、叶子所在节点两棵子树的大小和哈希，
+/// 以及自底向上到key索引树堆根的路径
+#[derive(Clone)]
+pub struct KeyIndexLeaf {
+    index: usize,
+    key: String,
+    digest: HashValue,
+    left: (usize, HashValue),
+    right: (usize, HashValue),
+    path: Vec<KeyIndexStep>,
+}
+
+impl KeyIndexLeaf {
+    pub(crate) fn new(index: usize, key: String, digest: HashValue, left: (usize, HashValue), right: (usize, HashValue), path: Vec<KeyIndexStep>) -> Self {
+        Self {
+            index,
+            key,
+            digest,
+            left,
+            right,
+            path,
+        }
+    }
+
+    #[inline]
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// 沿路径重新计算树堆的根，同时由各子树的大小得到叶子的位置和key的总数，
+    /// 二者与声明的位置和数量不一致时返回`None`
+    fn root<H: HashBackend>(&self, count: usize) -> Option<HashValue> {
+        let leaf = key_leaf::<H>(&self.key, &self.digest);
+        let mut hash = key_node::<H>((self.left.0, &self.left.1), &leaf, (self.right.0, &self.right.1));
+        let mut rank = self.left.0;
+        let mut size = self.left.0.checked_add(self.right.0)?.checked_add(1)?;
+        for step in self.path.iter() {
+            hash = if step.from_left {
+                key_node::<H>((size, &hash), &step.leaf, (step.sibling_count, &step.sibling))
+            } else {
+                rank = rank.checked_add(step.sibling_count)?.checked_add(1)?;
+                key_node::<H>((step.sibling_count, &step.sibling), &step.leaf, (size, &hash))
+            };
+            size = size.checked_add(step.sibling_count)?.checked_add(1)?;
+        }
+        (rank == self.index && size == count).then_some(hash)
+    }
+}
+
+/// 按key查找的证明
+pub enum KeyProof<V, const D: usize, H = Sha3_256>
+    where
+        V: MRTreeDefault,
+{
+    /// key存在：对象、对象所在分区中以对象位置为查询的VO，以及该分区到全局根哈希的证明
+    Member {
+        object: ObjectEntry<V, D, H>,
+        vo: VerifyObject<V, D, H>,
+        proof: PartitionProof<V, D, H>,
+    },
+    /// key不存在：key索引中与key相邻的前驱和后继叶子（key小于或大于所有key时只有一个），
+    /// 以及key索引到全局根哈希的路径
+    NonMember {
+        count: usize,
+        lower: Option<KeyIndexLeaf>,
+        upper: Option<KeyIndexLeaf>,
+        global_count: usize,
+        global_path: Vec<HashValue>,
+    },
+}
+
+impl<V, const D: usize, H: HashBackend> KeyProof<V, D, H>
+    where
+        V: MRTreeDefault,
+{
+    /// 查找到的对象，key不存在时返回`None`
+    pub fn object(&self) -> Option<&ObjectEntry<V, D, H>> {
+        match self {
+            KeyProof::Member { object, .. } => Some(object),
+            KeyProof::NonMember { .. } => None,
+        }
+    }
+}
+
+impl<V, const D: usize, H: HashBackend> KeyProof<V, D, H>
+    where
+        V: MRTreeDefault + MRTreeFunc,
+{
+    pub fn verify(&self, key: &str, global_root: HashValue) -> Result<(), VerifyError> {
+        match self {
+            KeyProof::Member { object, vo, proof } => {
+                if !object.match_key(key) {
+                    return Err(VerifyError::KeyMismatch { expected: key.to_string(), found: object.key() });
+                }
+                // 对象必须作为结果出现在VO中
+                let digest = object.calc_hash();
+                let found = vo.iter().any(|ety| match ety {
+                    VerifyObjectEntry::Target(t) => t.match_key(key) && t.calc_hash() == digest,
+                    _ => false,
+                });
+                if !found {
+                    return Err(VerifyError::ObjectNotInProof { key: key.to_string() });
+                }
+                proof.verify(vo, object.loc(), global_root)
+            },
+            KeyProof::NonMember { count, lower, upper, global_count, global_path } => {
+                if let Some(leaf) = lower {
+                    if leaf.key.as_str() >= key {
+                        return Err(VerifyError::KeyOrderViolation { key: key.to_string(), neighbor: leaf.key.clone() });
+                    }
+                }
+                if let Some(leaf) = upper {
+                    if leaf.key.as_str() <= key {
+                        return Err(VerifyError::KeyOrderViolation { key: key.to_string(), neighbor: leaf.key.clone() });
+                    }
+                }
+                // 前驱和后继必须在key索引中相邻，缺少的一侧必须是key索引的边界
+                let adjacent = match (lower, upper) {
+                    (Some(l), Some(u)) => u.index.checked_sub(1) == Some(l.index),
+                    (Some(l), None) => l.index.checked_add(1) == Some(*count),
+                    (None, Some(u)) => u.index == 0,
+                    (None, None) => *count == 0,
+                };
+                if !adjacent {
+                    return Err(VerifyError::NeighborsNotAdjacent {
+                        lower: lower.as_ref().map(|l| l.index),
+                        upper: upper.as_ref().map(|u| u.index),
+                        count: *count,
+                    });
+                }
+                let leaf_root = |leaf: &KeyIndexLeaf| leaf.root::<H>(*count)
+                    .ok_or(VerifyError::InvalidPath { index: leaf.index, width: *count });
+                let lower_root = lower.as_ref().map(leaf_root).transpose()?;
+                let upper_root = upper.as_ref().map(leaf_root).transpose()?;
+                // 前驱和后继必须证明到同一个key索引根
+                let root = match (lower_root, upper_root) {
+                    (Some(l), Some(u)) if l != u => return Err(VerifyError::RootMismatch { expected: l, computed: u }),
+                    (l, u) => l.or(u).unwrap_or_else(HashValue::zero),
+                };
+                let key_root = key_index_root::<H>(*count, &root);
+                let key_index = global_count.checked_sub(1)
+                    .ok_or(VerifyError::InvalidPath { index: 0, width: *global_count })?;
+                check_path::<H>(key_root, key_index, *global_count, global_path, global_root)
+            },
+        }
+    }
+}
+
+/// 由叶子和路径重新计算Merkle根，并与期望的根比较
+fn check_path<H: HashBackend>(leaf: HashValue, index: usize, width: usize, path: &[HashValue], expected: HashValue) -> Result<(), VerifyError> {
+    let computed = merkle_path_root::<H>(leaf, index, width, path)
+        .ok_or(VerifyError::InvalidPath { index, width })?;
+    if computed != expected {
+        return Err(VerifyError::RootMismatch { expected, computed });
+    }
+    Ok(())
+}
+
+/// 验证失败的原因。index为出错条目在VO中的位置，depth为该条目所在的层数（根节点为第1层）
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum VerifyError {
-    SoundnessError,
-    CompletenessError,
-    ResultError,
-}
\ No newline at end of file
+    /// VO中没有任何节点
+    EmptyProof,
+    /// LevlEnd没有对应的LevelBegin
+    UnmatchedLevelEnd { index: usize },
+    /// VO结束时仍有未闭合的层
+    UnclosedLevel { depth: usize },
+    /// Target或Sibling不在任何层中
+    EntryOutsideLevel { index: usize },
+    /// 根节点闭合后又出现了新的层
+    MultipleRoots { index: usize },
+    /// 层中没有任何条目
+    EmptyLevel { index: usize, depth: usize },
+    /// 同一层中的条目高度不一致
+    HeightMismatch { index: usize, depth: usize, expected: Option<u32>, found: Option<u32> },
+    /// 层中对象数量之和溢出
+    CountOverflow { index: usize, depth: usize },
+    /// 重新计算的根哈希与期望的根哈希不一致
+    RootMismatch { expected: HashValue, computed: HashValue },
+    /// Merkle路径的长度或位置不合法
+    InvalidPath { index: usize, width: usize },
+    /// 结果对象不在查询范围内
+    TargetOutsideQuery { index: usize, depth: usize },
+    /// 被剪枝的兄弟节点与查询范围相交，可能遗漏结果
+    SiblingIntersectsQuery { index: usize, depth: usize },
+    /// kNN结果数量超过k
+    TooManyResults { expected: usize, found: usize },
+    /// kNN中被剪枝的条目比第k近的结果更近，index为None表示整个分区被剪枝
+    PrunedCloserThanResult { partition: Option<usize>, index: Option<usize> },
+    /// 分区中的VO验证失败
+    Partition { partition: usize, error: Box<VerifyError> },
+    /// 非空分区与查询相交但没有给出VO
+    MissingPartitionVo { partition: usize },
+    /// 空分区给出了VO
+    UnexpectedPartitionVo { partition: usize },
+    /// 证明中的对象与查询的key不一致
+    KeyMismatch { expected: String, found: String },
+    /// 对象没有作为结果出现在VO中
+    ObjectNotInProof { key: String },
+    /// 前驱或后继与查询key的顺序不正确
+    KeyOrderViolation { key: String, neighbor: String },
+    /// 前驱和后继在key索引中不相邻，或缺少的一侧不是边界
+    NeighborsNotAdjacent { lower: Option<usize>, upper: Option<usize>, count: usize },
+}
+
+impl VerifyError {
+    /// 将分区内VO的错误包装为带分区编号的错误
+    fn in_partition(self, partition: usize) -> Self {
+        VerifyError::Partition { partition, error: Box::new(self) }
+    }
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::EmptyProof => write!(f, "proof contains no node"),
+            VerifyError::UnmatchedLevelEnd { index } => write!(f, "entry {}: level end without level begin", index),
+            VerifyError::UnclosedLevel { depth } => write!(f, "proof ends with {} unclosed level(s)", depth),
+            VerifyError::EntryOutsideLevel { index } => write!(f, "entry {}: entry outside of any level", index),
+            VerifyError::MultipleRoots { index } => write!(f, "entry {}: level begins after the root is closed", index),
+            VerifyError::EmptyLevel { index, depth } => write!(f, "entry {} (depth {}): empty level", index, depth),
+            VerifyError::HeightMismatch { index, depth, expected, found } => {
+                write!(f, "entry {} (depth {}): height mismatch, expected {:?}, found {:?}", index, depth, expected, found)
+            },
+            VerifyError::CountOverflow { index, depth } => write!(f, "entry {} (depth {}): object count overflow", index, depth),
+            VerifyError::RootMismatch { expected, computed } => {
+                write!(f, "root mismatch, expected {}, computed {}", expected, computed)
+            },
+            VerifyError::InvalidPath { index, width } => write!(f, "invalid merkle path for leaf {} of {}", index, width),
+            VerifyError::TargetOutsideQuery { index, depth } => write!(f, "entry {} (depth {}): target outside of query", index, depth),
+            VerifyError::SiblingIntersectsQuery { index, depth } => {
+                write!(f, "entry {} (depth {}): pruned sibling intersects query", index, depth)
+            },
+            VerifyError::TooManyResults { expected, found } => write!(f, "expected at most {} results, found {}", expected, found),
+            VerifyError::PrunedCloserThanResult { partition, index } => {
+                write!(f, "pruned entry {:?} of partition {:?} is closer than the results", index, partition)
+            },
+            VerifyError::Partition { partition, error } => write!(f, "partition {}: {}", partition, error),
+            VerifyError::MissingPartitionVo { partition } => write!(f, "partition {}: missing verify object", partition),
+            VerifyError::UnexpectedPartitionVo { partition } => write!(f, "partition {}: unexpected verify object", partition),
+            VerifyError::KeyMismatch { expected, found } => write!(f, "expected key {}, found {}", expected, found),
+            VerifyError::ObjectNotInProof { key } => write!(f, "object {} is not a result of the proof", key),
+            VerifyError::KeyOrderViolation { key, neighbor } => write!(f, "neighbor {} is out of order for key {}", neighbor, key),
+            VerifyError::NeighborsNotAdjacent { lower, upper, count } => {
+                write!(f, "neighbors {:?} and {:?} are not adjacent in key index of {}", lower, upper, count)
+            },
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
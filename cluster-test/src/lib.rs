@@ -5,6 +5,8 @@ use structopt::StructOpt;
 
 pub mod utils;
 
+use utils::{node_overlap, ESMTreeBuilder, MRTreeBuilder};
+
 #[derive(StructOpt, Debug)]
 pub struct ClusterArgs {
     #[structopt(short = "t", long, default_value = "0")]
@@ -15,6 +17,9 @@ pub struct ClusterArgs {
     pub file: PathBuf,
     #[structopt(short = "o", long, parse(from_os_str))]
     pub output: PathBuf,
+    /// 节点分裂策略：hilbert, quadratic, linear, rstar
+    #[structopt(short = "s", long, default_value = "hilbert")]
+    pub split: utils::SplitKind,
 }
 
 pub fn read_dataset(data_set: &str, path: PathBuf) -> Result<Vec<[f64;2]>, String> {
@@ -29,7 +34,8 @@ pub fn read_dataset(data_set: &str, path: PathBuf) -> Result<Vec<[f64;2]>, Strin
             Some(read_dcw_points(file))
         }
         "dcw-l" => {
-            Some(read_dcw_lines(file))
+            // 点数据集只取折线的顶点，整条折线参见`read_line_dataset`
+            Some(read_dcw_lines(file)?.into_iter().flatten().collect())
         }
         "imis" => {
             Some(read_imis(file))
@@ -43,8 +49,78 @@ pub fn read_dataset(data_set: &str, path: PathBuf) -> Result<Vec<[f64;2]>, Strin
     data.ok_or("something error".to_string())
 }
 
-fn read_dcw_lines(_file: File) -> Vec<[f64; 2]> {
-    vec![]
+/// 按命令行参数读取数据集，使用args.split指定的节点分裂策略分别构建MRTree和ESMT，
+/// 输出建树时间和MRTree中同层节点的重叠面积，并写入args.output。
+/// 折线数据集(dcw-l)中每条折线作为一个对象插入
+pub fn run(args: &ClusterArgs) -> Result<(), String> {
+    let (mrt, esmt) = if args.data_set == "dcw-l" {
+        let lines = read_line_dataset(&args.data_set, args.file.clone())?;
+        let (min, max) = bounds(lines.iter().flatten()).ok_or("empty data set".to_string())?;
+        let mrt = MRTreeBuilder::new().split_policy(args.split)
+            .build_line_insert_test(&lines);
+        let esmt = ESMTreeBuilder::new().split_policy(args.split)
+            .range(min, max)
+            .build_line_insert_test(&lines);
+        (mrt, esmt)
+    } else {
+        let data = read_dataset(&args.data_set, args.file.clone())?;
+        let (min, max) = bounds(data.iter()).ok_or("empty data set".to_string())?;
+        let mrt = MRTreeBuilder::new().split_policy(args.split)
+            .base_size(data.len()).opt_size(0)
+            .set_testset(&data).build_insert_test();
+        let esmt = ESMTreeBuilder::new().split_policy(args.split)
+            .base_size(data.len()).opt_size(0)
+            .range(min, max)
+            .set_testset(&data).build_insert_test();
+        (mrt, esmt)
+    };
+    let (mrt, mrt_time) = mrt.exec();
+    let (_, esmt_time) = esmt.exec();
+    let report = format!("{}/{:?}\tmrt: {:.4} s\tesmt: {:.4} s\tnode overlap: {:.4}\n",
+                         args.data_set, args.split, mrt_time, esmt_time, node_overlap(&mrt.display().0));
+    print!("{}", report);
+    let mut output = File::create(&args.output).map_err(|e| format!("{:?}", e))?;
+    output.write_all(report.as_bytes()).map_err(|e| format!("{:?}", e))
+}
+
+/// 数据集的范围，数据集为空时返回`None`
+fn bounds<'a>(points: impl Iterator<Item = &'a [f64; 2]>) -> Option<([f64; 2], [f64; 2])> {
+    points.fold(None, |acc, p| Some(match acc {
+        None => (*p, *p),
+        Some((min, max)) => ([min[0].min(p[0]), min[1].min(p[1])], [max[0].max(p[0]), max[1].max(p[1])]),
+    }))
+}
+
+/// 读取折线数据集，每条折线作为一个空间对象插入，参见`ObjectEntry::with_polyline`
+pub fn read_line_dataset(data_set: &str, path: PathBuf) -> Result<Vec<Vec<[f64; 2]>>, String> {
+    let file = File::open(path).map_err(|e| format!("{:?}", e))?;
+    match data_set {
+        "dcw-l" => read_dcw_lines(file),
+        _ => Err(format!("{} is not a line data set", data_set)),
+    }
+}
+
+/// 每行一条折线，顶点之间以空白分隔，每个顶点与dcw-p相同为`x:y`，格式错误时返回所在行号
+fn read_dcw_lines(file: File) -> Result<Vec<Vec<[f64; 2]>>, String> {
+    let mut data = vec![];
+    let buffered = BufReader::new(file);
+    for (no, line) in buffered.lines().enumerate() {
+        let line = line.map_err(|e| format!("line {}: {:?}", no + 1, e))?;
+        let polyline = line
+            .split_whitespace()
+            .map(|v| parse_vertex(v).ok_or(format!("line {}: invalid vertex `{}`", no + 1, v)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !polyline.is_empty() {
+            data.push(polyline);
+        }
+    }
+    Ok(data)
+}
+
+/// 解析`x:y`格式的顶点
+fn parse_vertex(v: &str) -> Option<[f64; 2]> {
+    let (x, y) = v.split_once(':')?;
+    Some([f64::from_str(x.trim()).ok()?, f64::from_str(y.trim()).ok()?])
 }
 
 fn read_dcw_points(file: File) -> Vec<[f64; 2]> {
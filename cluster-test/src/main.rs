@@ -1,10 +1,18 @@
 use std::{path::PathBuf, str::FromStr, time::Instant, os::unix::thread};
 
 use authentic_rtree::shape::Rect;
-use cluster_test::{ClusterArgs, read_dataset, utils::{manu_test, MRTreeBuilder, ESMTreeBuilder}};
+use cluster_test::{ClusterArgs, read_dataset, run, utils::{manu_test, MRTreeBuilder, ESMTreeBuilder}};
 use structopt::StructOpt;
 
 fn main() {
+    // 给出命令行参数时只按参数建树并比较分裂策略，参见`ClusterArgs`
+    if std::env::args().len() > 1 {
+        let args = ClusterArgs::from_args();
+        if let Err(e) = run(&args) {
+            println!("{}", e);
+        }
+        return;
+    }
     // let args = ClusterArgs::from_args();
     // println!("{:?}", args.file);
     // let data = read_dataset(&args.data_set, args.file.clone());
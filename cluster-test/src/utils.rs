@@ -1,5 +1,6 @@
 use std::{collections::{HashMap}, str::FromStr, time::{Instant, Duration}, task::Poll};
-use authentic_rtree::{mrtree::MerkleRTree as MRTree, shape::Rect, esmtree::PartionManager};
+use authentic_rtree::{mrtree::MerkleRTree as MRTree, shape::Rect, esmtree::PartionManager, node::ObjectEntry};
+use authentic_rtree::split::{HilbertSplit, LinearSplit, QuadraticSplit, RStarSplit};
 use rand::{thread_rng, seq::SliceRandom, distributions::Uniform, prelude::Distribution};
 use types::hash_value::{HashValue, ESMTHasher};
 use authentic_rtree::mrtree::{NODE_SPLIT, NODE_TRAVERSE};
@@ -10,6 +11,8 @@ use std::sync::{Arc, Barrier};
 
 pub enum TreeOpt {
     Insert(String, [f64; 2], HashValue),
+    /// 插入折线等带有形状的对象，参见`ObjectEntry::with_polyline`
+    InsertObject(ObjectEntry<f64, 2>),
     Update(String, [f64; 2]),
     Delete(String),
     Query(Rect<f64, 2>),
@@ -26,6 +29,62 @@ impl TreeOpt {
     }
 }
 
+/// 建树时使用的节点分裂策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitKind {
+    Hilbert,
+    Quadratic,
+    Linear,
+    RStar,
+}
+
+impl FromStr for SplitKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hilbert" => Ok(SplitKind::Hilbert),
+            "quadratic" => Ok(SplitKind::Quadratic),
+            "linear" => Ok(SplitKind::Linear),
+            "rstar" => Ok(SplitKind::RStar),
+            _ => Err(format!("unknown split policy: {}", s)),
+        }
+    }
+}
+
+impl SplitKind {
+    pub fn mrtree<const C: usize>(self) -> MRTree<f64, 2, C> {
+        match self {
+            SplitKind::Hilbert => MRTree::with_split_policy(HilbertSplit),
+            SplitKind::Quadratic => MRTree::with_split_policy(QuadraticSplit),
+            SplitKind::Linear => MRTree::with_split_policy(LinearSplit),
+            SplitKind::RStar => MRTree::with_split_policy(RStarSplit::default()),
+        }
+    }
+
+    pub fn esmtree<const C: usize>(self, range: Rect<f64, 2>, height: u32) -> PartionManager<f64, 2, C> {
+        match self {
+            SplitKind::Hilbert => PartionManager::with_split_policy(range, height, HilbertSplit),
+            SplitKind::Quadratic => PartionManager::with_split_policy(range, height, QuadraticSplit),
+            SplitKind::Linear => PartionManager::with_split_policy(range, height, LinearSplit),
+            SplitKind::RStar => PartionManager::with_split_policy(range, height, RStarSplit::default()),
+        }
+    }
+}
+
+/// 同一层节点两两之间的重叠面积之和，levels为`MerkleRTree::display`返回的节点列表
+pub fn node_overlap(levels: &[(u32, Rect<f64, 2>)]) -> f64 {
+    let mut overlap = 0.0;
+    for (i, (h1, r1)) in levels.iter().enumerate() {
+        for (h2, r2) in levels[i + 1..].iter() {
+            if h1 == h2 {
+                overlap += r1.overlap_area(r2);
+            }
+        }
+    }
+    overlap
+}
+
 pub struct MRTreeTestManager {
     pub data: Vec<TreeOpt>,
     pub tree: MRTree<f64, 2, 51>,
@@ -35,6 +94,7 @@ pub struct MRTreeTestManager {
 pub struct MRTreeBuilder {
     base: usize,
     q_size: usize,
+    split: SplitKind,
     data: Vec<[f64; 2]>,
 }
 
@@ -43,10 +103,17 @@ impl MRTreeBuilder {
         Self {
             base: 1000,
             q_size: 100,
+            split: SplitKind::Hilbert,
             data: vec![],
         }
     }
 
+    #[inline]
+    pub fn split_policy(mut self, split: SplitKind) -> Self {
+        self.split = split;
+        self
+    }
+
     #[inline]
     pub fn base_size(mut self, size: usize) -> Self {
         self.base = size;
@@ -69,7 +136,7 @@ impl MRTreeBuilder {
     }
 
     pub fn build_insert_test(self) -> MRTreeTestManager {
-        let tree = MRTree::new();
+        let tree = self.split.mrtree();
         let data = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -86,6 +153,24 @@ impl MRTreeBuilder {
         }
     }
 
+    /// 每条折线作为一个对象插入，没有顶点的折线被跳过
+    pub fn build_line_insert_test(self, lines: &[Vec<[f64; 2]>]) -> MRTreeTestManager {
+        let tree = self.split.mrtree();
+        let data = lines.iter()
+            .enumerate()
+            .filter_map(|(idx, line)| {
+                let k = format!("test/mbr/line/{}", idx);
+                let hash = ESMTHasher::default().update(k.as_bytes()).finish();
+                ObjectEntry::with_polyline(k, line.clone(), hash).map(TreeOpt::InsertObject)
+            })
+            .collect();
+        MRTreeTestManager {
+            data,
+            tree,
+            keymap: HashMap::new()
+        }
+    }
+
     pub fn build_update_test(self, percent: f64) -> MRTreeTestManager {
         assert!(percent >= 0.0 && percent <= 1.0, "require percent in [0,1], input: {}", percent);
         let insert_cnt = (self.q_size as f64 * percent).floor() as usize;
@@ -100,7 +185,7 @@ impl MRTreeBuilder {
                 (key, p)
             })
             .collect::<HashMap<_, _>>();
-        let mut tree = MRTree::new();
+        let mut tree = self.split.mrtree();
         let iter = keymap.iter()
             .take(self.base)
             .map(|(k,l)| {
@@ -108,7 +193,7 @@ impl MRTreeBuilder {
                 (k.clone(), l.clone(), hash)
             });
         for (key, loc, hash) in iter {
-            tree.insert(key, loc, hash);
+            tree.insert(key, loc, hash).unwrap();
         }
         // 构造数据
         let mut data = insert_vec.into_iter()
@@ -157,7 +242,7 @@ impl MRTreeBuilder {
                 (key, p)
             })
             .collect::<HashMap<_, _>>();
-        let mut tree = MRTree::new();
+        let mut tree = self.split.mrtree();
         let iter = keymap.iter()
             .take(self.base)
             .map(|(k,l)| {
@@ -165,7 +250,7 @@ impl MRTreeBuilder {
                 (k.clone(), l.clone(), hash)
             });
         for (key, loc, hash) in iter {
-            tree.insert(key, loc, hash);
+            tree.insert(key, loc, hash).unwrap();
         }
         let data = del_cand.into_iter()
             .map(|idx| {
@@ -191,7 +276,7 @@ impl MRTreeBuilder {
                 (key, p)
             })
             .collect::<HashMap<_, _>>();
-        let mut tree = MRTree::new();
+        let mut tree = self.split.mrtree();
         let iter = keymap.iter()
             .take(self.base)
             .map(|(k,l)| {
@@ -199,7 +284,7 @@ impl MRTreeBuilder {
                 (k.clone(), l.clone(), hash)
             });
         for (key, loc, hash) in iter {
-            tree.insert(key, loc, hash);
+            tree.insert(key, loc, hash).unwrap();
         }
         // 构造查询数据
         let mut rng = thread_rng();
@@ -236,14 +321,19 @@ impl MRTreeTestManager {
             match opt {
                 TreeOpt::Insert(key, loc, hash) => {
                     let timer_start = Instant::now();
-                    tree.insert(key.clone(), loc.clone(), hash);
+                    tree.insert(key.clone(), loc.clone(), hash).unwrap();
                     value += Instant::elapsed(&timer_start);
                     keymap.insert(key, loc);
                 },
+                TreeOpt::InsertObject(obj) => {
+                    let timer_start = Instant::now();
+                    tree.insert_object(obj).unwrap();
+                    value += Instant::elapsed(&timer_start);
+                },
                 TreeOpt::Update(key, nloc) => {
                     let timer_start = Instant::now();
                     let oloc = keymap.get(&key).unwrap();
-                    tree.update_loc(&key, oloc, nloc.clone());
+                    let _ = tree.update_loc(&key, oloc, nloc.clone());
                     value += Instant::elapsed(&timer_start);
                     let _ = keymap.insert(key, nloc);
                 },
@@ -282,6 +372,7 @@ pub struct ESMTreeBuilder {
     q_size: usize,
     p_height: u32,
     range: Rect<f64, 2>,
+    split: SplitKind,
     data: Vec<[f64; 2]>,
 }
 
@@ -292,10 +383,17 @@ impl ESMTreeBuilder {
             q_size: 100,
             p_height: 4,
             range: Rect { _max: [0.0, 0.0], _min: [100.0, 100.0] },
+            split: SplitKind::Hilbert,
             data: vec![],
         }
     }
 
+    #[inline]
+    pub fn split_policy(mut self, split: SplitKind) -> Self {
+        self.split = split;
+        self
+    }
+
     #[inline]
     pub fn base_size(mut self, size: usize) -> Self {
         self.base = size;
@@ -330,7 +428,7 @@ impl ESMTreeBuilder {
     }
 
     pub fn build_insert_test(self) -> ESMTreeTestManager {
-        let tree = PartionManager::new(self.range, self.p_height);
+        let tree = self.split.esmtree(self.range, self.p_height);
         let data = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -348,13 +446,31 @@ impl ESMTreeBuilder {
         }
     }
 
+    /// 每条折线作为一个对象插入，没有顶点的折线被跳过
+    pub fn build_line_insert_test(self, lines: &[Vec<[f64; 2]>]) -> ESMTreeTestManager {
+        let tree = self.split.esmtree(self.range, self.p_height);
+        let data = lines.iter()
+            .enumerate()
+            .filter_map(|(idx, line)| {
+                let k = format!("test/esmt/line/{}", idx);
+                let hash = ESMTHasher::default().update(k.as_bytes()).finish();
+                ObjectEntry::with_polyline(k, line.clone(), hash).map(TreeOpt::InsertObject)
+            })
+            .collect();
+        ESMTreeTestManager {
+            prepare: vec![],
+            data,
+            tree,
+        }
+    }
+
     pub fn build_update_test(self, percent: f64) -> ESMTreeTestManager {
         assert!(percent >= 0.0 && percent <= 1.0, "require percent in [0,1], input: {}", percent);
         let insert_cnt = (self.q_size as f64 * percent).floor() as usize;
         let insert_vec = self.data[self.base..(self.base + insert_cnt)].to_vec();
         let update_vec = self.data[(self.base + insert_cnt)..].to_vec();
         // init
-        let mut tree = PartionManager::new(self.range, self.p_height);
+        let mut tree = self.split.esmtree(self.range, self.p_height);
         let iter = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -364,7 +480,7 @@ impl ESMTreeBuilder {
                 (key, point, hash)
             });
         for (key, loc, hash) in iter {
-            tree.insert(key, loc, hash);
+            tree.insert(key, loc, hash).unwrap();
         }
         // 构造数据
         let mut data = insert_vec.into_iter()
@@ -405,7 +521,7 @@ impl ESMTreeBuilder {
             .map(|key| *key)
             .collect::<Vec<_>>();
         // init
-        let mut tree = PartionManager::new(self.range, self.p_height);
+        let mut tree = self.split.esmtree(self.range, self.p_height);
         let iter = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -415,7 +531,7 @@ impl ESMTreeBuilder {
                 (key, point, hash)
             });
         for (key, loc, hash) in iter {
-            tree.insert(key, loc, hash);
+            tree.insert(key, loc, hash).unwrap();
         }
         // 构造
         let data = del_cand.into_iter()
@@ -434,7 +550,7 @@ impl ESMTreeBuilder {
 
     pub fn build_query_test(self, size: f64) -> ESMTreeTestManager {
         // init
-        let mut tree = PartionManager::new(self.range.clone(), self.p_height);
+        let mut tree = self.split.esmtree(self.range.clone(), self.p_height);
         let iter = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -444,7 +560,7 @@ impl ESMTreeBuilder {
                 (key, point, hash)
             });
         for (key, loc, hash) in iter {
-            tree.insert(key, loc, hash);
+            tree.insert(key, loc, hash).unwrap();
         }
         // 构造查询数据
         let mut rng = thread_rng();
@@ -468,7 +584,7 @@ impl ESMTreeBuilder {
 
     pub fn build_edge_test(self, size: f64) -> ESMTreeTestManager {
         // init
-        let mut tree = PartionManager::new(self.range.clone(), self.p_height);
+        let mut tree = self.split.esmtree(self.range.clone(), self.p_height);
         let iter = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -478,7 +594,7 @@ impl ESMTreeBuilder {
                 (key, point, hash)
             });
         for (key, loc, hash) in iter {
-            tree.insert(key, loc, hash);
+            tree.insert(key, loc, hash).unwrap();
         }
         // 构造查询数据
         let mut rng = thread_rng();
@@ -501,7 +617,7 @@ impl ESMTreeBuilder {
     }
 
     pub fn build_batch_construct(self, batch_size: usize) -> ESMTreeTestManager {
-        let tree = PartionManager::new(self.range, self.p_height);
+        let tree = self.split.esmtree(self.range, self.p_height);
         let mut data = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -530,7 +646,7 @@ impl ESMTreeBuilder {
     pub fn build_batch_insert(self, batch_size: usize) -> ESMTreeTestManager {
         let insert_vec = self.data[self.base..].to_vec();
         // init
-        let mut tree = PartionManager::new(self.range, self.p_height);
+        let mut tree = self.split.esmtree(self.range, self.p_height);
         let iter = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -540,7 +656,7 @@ impl ESMTreeBuilder {
                 (key, point, hash)
             });
         for (key, loc, hash) in iter {
-            tree.insert(key, loc, hash);
+            tree.insert(key, loc, hash).unwrap();
         }
         // 构造数据
         let mut data = insert_vec.into_iter()
@@ -568,7 +684,7 @@ impl ESMTreeBuilder {
 
     pub fn batch_build_query(self, batch_size: usize, size: f64) -> ESMTreeTestManager {
         // 初始化esmt
-        let mut tree = PartionManager::new(self.range.clone(), self.p_height);
+        let mut tree = self.split.esmtree(self.range.clone(), self.p_height);
         let mut iter = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -579,7 +695,7 @@ impl ESMTreeBuilder {
             }).collect::<Vec<_>>();
         while iter.len() >= batch_size {
             let batch = iter.drain(..batch_size).collect::<Vec<_>>();
-            tree.batch_insert(batch);
+            tree.batch_insert(batch).unwrap();
         }
         // 构造测试数据
         // 构造查询数据
@@ -604,7 +720,7 @@ impl ESMTreeBuilder {
 
     pub fn batch_build_iter_query(self, batch_size: usize, size: f64) -> ESMTreeTestManager {
         // 初始化esmt
-        let mut tree = PartionManager::new(self.range.clone(), self.p_height);
+        let mut tree = self.split.esmtree(self.range.clone(), self.p_height);
         let mut iter = self.data.into_iter()
             .enumerate()
             .take(self.base)
@@ -615,7 +731,7 @@ impl ESMTreeBuilder {
             }).collect::<Vec<_>>();
         while iter.len() >= batch_size {
             let batch = iter.drain(..batch_size).collect::<Vec<_>>();
-            tree.batch_iter_insert(batch);
+            tree.batch_iter_insert(batch).unwrap();
         }
         // 构造测试数据
         // 构造查询数据
@@ -648,12 +764,17 @@ impl ESMTreeTestManager {
             match opt {
                 TreeOpt::Insert(key, loc, hash) => {
                     let timer_start = Instant::now();
-                    tree.insert(key, loc, hash);
+                    tree.insert(key, loc, hash).unwrap();
+                    value += Instant::elapsed(&timer_start);
+                },
+                TreeOpt::InsertObject(obj) => {
+                    let timer_start = Instant::now();
+                    tree.insert_object(obj).unwrap();
                     value += Instant::elapsed(&timer_start);
                 },
                 TreeOpt::Update(key, nloc) => {
                     let timer_start = Instant::now();
-                    tree.update(&key, nloc);
+                    let _ = tree.update(&key, nloc);
                     value += Instant::elapsed(&timer_start);
                 },
                 TreeOpt::Delete(key) => {
@@ -681,7 +802,7 @@ impl ESMTreeTestManager {
         let mut value = Duration::new(0,0);
         for batch in self.prepare {
             let timer_start = Instant::now();
-            tree.batch_insert(batch);
+            tree.batch_insert(batch).unwrap();
             value += Instant::elapsed(&timer_start);
         }
         (tree, value.as_secs_f64())
@@ -6,7 +6,7 @@ use std::time::{Instant};
 use feature_test::xy2d;
 use rand::{Rng, thread_rng};
 use types::hash_value::{HashValue};
-use authentic_rtree::node::{HilbertSorter};
+use authentic_rtree::curve::{HilbertSorter};
 use authentic_rtree::shape::Rect;
 use types::test_utils::{calc_hash, num_hash};
 use rustyline::error::ReadlineError;
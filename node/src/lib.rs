@@ -1,5 +1,6 @@
 use std::sync::mpsc::{Sender, Receiver, channel};
 use authentic_rtree::esmtree::PartionManager;
+use authentic_rtree::error::EsmtError;
 use structopt::StructOpt;
 use types::hash_value::HashValue;
 use authentic_rtree::shape::Rect;
@@ -15,23 +16,23 @@ impl MockChain {
         }
     }
 
-    pub fn insert(&mut self, key: String, loc: [f64;2], hash: HashValue) {
-        self.esmt.insert(key, loc, hash);
+    pub fn insert(&mut self, key: String, loc: [f64;2], hash: HashValue) -> Result<(), EsmtError> {
+        self.esmt.insert(key, loc, hash)
     }
 
-    pub fn delete(&mut self, key: String) {
-        self.esmt.delete(&key);
+    pub fn delete(&mut self, key: String) -> Result<(), EsmtError> {
+        self.esmt.delete(&key).map(|_| ())
     }
 
-    pub fn update(&mut self, key: String, nloc: [f64;2]) {
-        self.esmt.update(&key, nloc);
+    pub fn update(&mut self, key: String, nloc: [f64;2]) -> Result<(), EsmtError> {
+        self.esmt.update(&key, nloc)
     }
 
-    pub fn batch_insert(&mut self, data: Vec<(String, [f64;2], HashValue)>) {
-        self.esmt.batch_insert(data);
+    pub fn batch_insert(&mut self, data: Vec<(String, [f64;2], HashValue)>) -> Result<(), EsmtError> {
+        self.esmt.batch_insert(data)
     }
 
-    pub fn hashes(&self) -> Vec<Option<HashValue>>{
+    pub fn hashes(&self) -> Result<Vec<Option<HashValue>>, EsmtError> {
         self.esmt.get_hashes()
     }
 }
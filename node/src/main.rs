@@ -89,25 +89,29 @@ fn generate_testset(test: &str, size: usize) -> Vec<Request> {
 fn work(mut node: MockChain, chan: ServerEnd) {
     loop {
         let req: Request = chan.recv().unwrap();
-        match req {
+        let res = match req {
             Request::INSERT(key, loc, hash) => {
-                node.insert(key, loc, hash);
+                node.insert(key, loc, hash)
             },
             Request::DELETE(key) => {
-                node.delete(key);
+                node.delete(key)
             },
             Request::UPDATE(key, nloc) => {
-                node.update(key, nloc);
+                node.update(key, nloc)
             },
             Request::BATCHINSERT(data) => {
-                node.batch_insert(data);
+                node.batch_insert(data)
             },
             Request::QUIT => {
                 println!("ready to quit");
                 break;
             },
+        };
+        // 出错的请求被丢弃，不影响后续请求
+        if let Err(e) = res {
+            println!("request failed: {}", e);
         }
-        let _ = chan.send(Response{ hashes: node.hashes()}).unwrap();
+        let _ = chan.send(Response{ hashes: node.hashes().unwrap()}).unwrap();
     }
 }
 
@@ -154,16 +158,16 @@ fn main() {
     if test == "update" || test == "delete" {
         for req in pre_data {
             if let Request::INSERT(k, loc, hash) = req {
-                node1.insert(k.clone(), loc.clone(), hash.clone());
-                node2.insert(k.clone(), loc.clone(), hash.clone());
-                node3.insert(k.clone(), loc.clone(), hash.clone());
-                node4.insert(k.clone(), loc.clone(), hash.clone());
+                node1.insert(k.clone(), loc.clone(), hash.clone()).unwrap();
+                node2.insert(k.clone(), loc.clone(), hash.clone()).unwrap();
+                node3.insert(k.clone(), loc.clone(), hash.clone()).unwrap();
+                node4.insert(k.clone(), loc.clone(), hash.clone()).unwrap();
             }
         }
-        let r1 = node1.hashes();
-        let r2 = node2.hashes();
-        let r3 = node3.hashes();
-        let r4 = node4.hashes();
+        let r1 = node1.hashes().unwrap();
+        let r2 = node2.hashes().unwrap();
+        let r3 = node3.hashes().unwrap();
+        let r4 = node4.hashes().unwrap();
         if !check(r1, r2, r3, r4) {
             println!("test failed!");
             return;
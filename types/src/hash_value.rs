@@ -5,12 +5,6 @@ use crypto::sha3::Sha3;
 use once_cell::sync::Lazy;
 use serde::{Serialize, Deserialize};
 
-static ESMT_SALT: Lazy<HashValue> = Lazy::new(|| {
-    let hasher = ESMTHasher::new();
-    // add salt
-    hasher.update("esmt".as_bytes()).finish()
-});
-
 /// Hash Value in ESMT
 #[derive(Eq, PartialEq, Copy, Clone, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct HashValue {
@@ -105,36 +99,202 @@ impl AsRef<[u8; HashValue::LENGTH]> for HashValue {
 }
 
 
+/// A hash function that can back an [`ESMTHasher`].
+///
+/// Digests are stored in a [`HashValue`], so `OUTPUT_LEN` must equal
+/// [`HashValue::LENGTH`]; this is checked when an [`ESMTHasher`] is built.
+pub trait HashBackend: Clone + Send + Sync + 'static {
+    /// the length of the digest in bytes
+    const OUTPUT_LEN: usize;
+    /// the salt whose digest prefixes every salted hash
+    const SALT: &'static [u8] = b"esmt";
+
+    fn new() -> Self;
+
+    fn update(&mut self, bytes: &[u8]);
+
+    /// write the digest into `out`, which is `OUTPUT_LEN` bytes long
+    fn finish(self, out: &mut [u8]);
+
+    /// the unsalted digest of [`HashBackend::SALT`]; backends should cache it
+    fn salt_digest() -> HashValue {
+        ESMTHasher::<Self>::unsalted().update(Self::SALT).finish()
+    }
+}
+
+/// Overrides [`HashBackend::salt_digest`] with a digest computed once per
+/// backend. A `static` in the default method would be shared by every
+/// backend, so each implementation names its own.
+macro_rules! cached_salt_digest {
+    ($backend:ty) => {
+        fn salt_digest() -> HashValue {
+            static SALT: Lazy<HashValue> = Lazy::new(|| ESMTHasher::<$backend>::unsalted().update(<$backend>::SALT).finish());
+            *SALT
+        }
+    };
+}
+
+/// SHA3-256, the default backend
+#[derive(Clone)]
+pub struct Sha3_256(Sha3);
+
+impl HashBackend for Sha3_256 {
+    const OUTPUT_LEN: usize = 32;
+
+    fn new() -> Self {
+        Self(Sha3::sha3_256())
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.input(bytes);
+    }
+
+    fn finish(mut self, out: &mut [u8]) {
+        self.0.result(out);
+    }
+
+    cached_salt_digest!(Sha3_256);
+}
+
+/// Keccak-256 as used by Ethereum, i.e. SHA3 with the original padding
+#[derive(Clone)]
+pub struct Keccak256(Sha3);
+
+impl HashBackend for Keccak256 {
+    const OUTPUT_LEN: usize = 32;
+
+    fn new() -> Self {
+        Self(Sha3::keccak256())
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.input(bytes);
+    }
+
+    fn finish(mut self, out: &mut [u8]) {
+        self.0.result(out);
+    }
+
+    cached_salt_digest!(Keccak256);
+}
+
+/// BLAKE3 with the default 32-byte output
+#[derive(Clone)]
+pub struct Blake3(blake3::Hasher);
+
+impl HashBackend for Blake3 {
+    const OUTPUT_LEN: usize = 32;
+
+    fn new() -> Self {
+        Self(blake3::Hasher::new())
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(self, out: &mut [u8]) {
+        out.copy_from_slice(self.0.finalize().as_bytes());
+    }
+
+    cached_salt_digest!(Blake3);
+}
+
+/// Domain tags that keep digests of different kinds of data apart.
+///
+/// Every structural digest starts with its tag right after the salt, so a
+/// digest computed in one domain can never be accepted in another, e.g. an
+/// internal node passed off as a spatial object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum HashDomain {
+    /// a spatial object
+    Leaf = 0x00,
+    /// an internal node of a partition tree
+    Node = 0x01,
+    /// a partition root committed as a leaf of the global Merkle tree
+    Partition = 0x02,
+    /// an inner node of the global binary Merkle tree, including the global root
+    Global = 0x03,
+    /// a `(key, digest)` leaf of the key index
+    KeyLeaf = 0x04,
+    /// the key index root committed as a leaf of the global Merkle tree
+    KeyIndex = 0x05,
+    /// a node of the key index treap: both subtrees with their sizes and the
+    /// node's own `(key, digest)` leaf
+    KeyNode = 0x06,
+    /// the heap priority of a key in the key index treap; never committed, it
+    /// only fixes the shape of the treap for a given set of keys
+    KeyPriority = 0x07,
+}
+
 /// authentic_rtree Node HAasher
-pub struct ESMTHasher {
-    hasher: Sha3,
+///
+/// `ESMTHasher::default()` is salted and uses SHA3-256; other backends are
+/// selected with [`ESMTHasher::salted`], e.g. `ESMTHasher::<Keccak256>::salted()`.
+pub struct ESMTHasher<H = Sha3_256> {
+    hasher: H,
 }
 
 impl ESMTHasher {
     pub fn new() -> Self {
+        Self::unsalted()
+    }
+}
+
+impl<H: HashBackend> ESMTHasher<H> {
+    /// a hasher without the salt prefix
+    pub fn unsalted() -> Self {
+        const { assert!(H::OUTPUT_LEN == HashValue::LENGTH, "backend output length must match HashValue::LENGTH") };
         Self {
-            hasher: Sha3::sha3_256(),
+            hasher: H::new(),
         }
     }
 
+    /// a hasher prefixed with the digest of the backend's salt
+    pub fn salted() -> Self {
+        Self::unsalted().update(H::salt_digest().as_ref())
+    }
+
+    /// a salted hasher followed by the tag of `domain`
+    pub fn tagged(domain: HashDomain) -> Self {
+        Self::salted().update(&[domain as u8])
+    }
+
     pub fn update(mut self, bytes: &[u8]) -> Self {
-        self.hasher.input(bytes);
+        self.hasher.update(bytes);
         self
     }
 
-    pub fn finish(mut self) -> HashValue {
+    pub fn finish(self) -> HashValue {
         let mut hash = HashValue::default();
-        self.hasher.result(hash.as_ref_mut());
+        self.hasher.finish(hash.as_ref_mut());
         hash
     }
 }
 
 impl Default for ESMTHasher {
     fn default() -> Self {
-        ESMTHasher::new().update((*ESMT_SALT).as_ref())
+        ESMTHasher::salted()
     }
 }
 
+/// Canonical leaf digest of a spatial object.
+///
+/// Tagged with [`HashDomain::Leaf`]. Commits to the object's key, the canonical byte encoding of its location
+/// and the hash of its value. The key and location are length-prefixed
+/// (u64, little endian) so that different splits of the same bytes never
+/// collide.
+pub fn leaf_hash<H: HashBackend>(key: &[u8], loc: &[u8], value: &HashValue) -> HashValue {
+    ESMTHasher::<H>::tagged(HashDomain::Leaf)
+        .update(&(key.len() as u64).to_le_bytes())
+        .update(key)
+        .update(&(loc.len() as u64).to_le_bytes())
+        .update(loc)
+        .update(value.as_ref())
+        .finish()
+}
+
 #[cfg(test)]
 #[test]
 fn test_hashvalue() {
@@ -146,4 +306,59 @@ fn test_hashvalue() {
     println!("\"hello world\" hashed to {:?}", hash);
     println!("\"hello world\" hashed to {}", hash);
     //assert_eq!(hash, *(ESMT_SALT));
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+#[test]
+fn test_leaf_hash() {
+    let value = ESMTHasher::default().update(b"value").finish();
+    let h = leaf_hash::<Sha3_256>(b"key", &[1, 2, 3, 4], &value);
+    assert_eq!(h, leaf_hash::<Sha3_256>(b"key", &[1, 2, 3, 4], &value));
+    // changing any of key, loc or value changes the digest
+    assert_ne!(h, leaf_hash::<Sha3_256>(b"kez", &[1, 2, 3, 4], &value));
+    assert_ne!(h, leaf_hash::<Sha3_256>(b"key", &[1, 2, 3, 5], &value));
+    assert_ne!(h, leaf_hash::<Sha3_256>(b"key", &[1, 2, 3, 4], &HashValue::zero()));
+    // length prefixes keep shifted field boundaries from colliding
+    assert_ne!(h, leaf_hash::<Sha3_256>(b"key\x01", &[2, 3, 4], &value));
+}
+
+#[cfg(test)]
+#[test]
+fn test_hash_backends() {
+    // digests of the empty input
+    let empty = |h: HashValue| h.to_hex();
+    assert_eq!(empty(ESMTHasher::new().finish()),
+               "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a");
+    assert_eq!(empty(ESMTHasher::<Keccak256>::unsalted().finish()),
+               "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+    assert_eq!(empty(ESMTHasher::<Blake3>::unsalted().finish()),
+               "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262");
+    // the default hasher is salted SHA3-256
+    assert_eq!(ESMTHasher::default().update(b"data").finish(), ESMTHasher::<Sha3_256>::salted().update(b"data").finish());
+    assert_eq!(Sha3_256::salt_digest(), ESMTHasher::new().update(b"esmt").finish());
+    let sha3 = leaf_hash::<Sha3_256>(b"key", &[1], &HashValue::zero());
+    let keccak = leaf_hash::<Keccak256>(b"key", &[1], &HashValue::zero());
+    let blake3 = leaf_hash::<Blake3>(b"key", &[1], &HashValue::zero());
+    assert_ne!(sha3, keccak);
+    assert_ne!(sha3, blake3);
+    assert_ne!(keccak, blake3);
+}
+
+#[cfg(test)]
+#[test]
+fn test_hash_domains() {
+    let domains = [HashDomain::Leaf, HashDomain::Node, HashDomain::Partition,
+                   HashDomain::Global, HashDomain::KeyLeaf, HashDomain::KeyIndex,
+                   HashDomain::KeyNode, HashDomain::KeyPriority];
+    let digests: Vec<_> = domains.iter()
+        .map(|d| ESMTHasher::<Sha3_256>::tagged(*d).update(b"data").finish())
+        .collect();
+    for i in 0..digests.len() {
+        for j in i + 1..digests.len() {
+            assert_ne!(digests[i], digests[j], "{:?} and {:?} collide", domains[i], domains[j]);
+        }
+    }
+    // the same bytes hashed as a leaf and as an untagged digest differ
+    assert_ne!(digests[0], ESMTHasher::default().update(b"data").finish());
+    assert_eq!(digests[0], ESMTHasher::default().update(&[0x00]).update(b"data").finish());
+}
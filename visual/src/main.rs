@@ -137,8 +137,10 @@ fn main() {
                         let x = rng.gen_range(-1.0f32..1.0f32);
                         let y = rng.gen_range(-1.0f32..1.0f32);
                         let key = format!("[{}, {}]", x, y);
-                        inserted_object.push((key.clone(), [x,y]));
-                        tree.insert(key, [x,y], HashValue::zero());
+                        match tree.insert(key.clone(), [x,y], HashValue::zero()) {
+                            Ok(()) => inserted_object.push((key, [x,y])),
+                            Err(e) => println!("insert failed: {}", e),
+                        }
                         println!("insert object: {:?}, tree.len() = {}", [x, y], tree.len());
                         (nodes, objs) = tree.display();
                         modified = true;
@@ -160,13 +162,17 @@ fn main() {
                         let (key, oloc) = inserted_object[idx].clone();
                         let x = rng.gen_range(-1.0f32..1.0f32);
                         let y = rng.gen_range(-1.0f32..1.0f32);
-                        inserted_object[idx].1 = [x,y];
-                        tree.update(&key, &oloc, [x,y]);
+                        match tree.update(&key, &oloc, [x,y]) {
+                            Ok(()) => inserted_object[idx].1 = [x,y],
+                            Err(e) => println!("update failed: {}", e),
+                        }
                         println!("update object oloc: {:?}, nloc: {:?}, tree.len() = {}", oloc, [x,y],tree.len());
                         (nodes, objs) = tree.display();
                         modified = true;
                     } else if input.scancode == 50 && input.state == ElementState::Released {
-                        tree.merge_empty();
+                        if let Err(e) = tree.merge_empty() {
+                            println!("merge failed: {}", e);
+                        }
                         (nodes, objs) = tree.display();
                         modified = true;
                     }